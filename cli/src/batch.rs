@@ -0,0 +1,254 @@
+//! Rendering many images with one config, for `fl batch`.
+//!
+//! The inputs are found by [`crate::glob`], each one gets an output path
+//! from the name template, and the jobs are rendered on a pool of threads.
+//! A failed job does not stop the others, the failures are collected for
+//! the summary.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Error, Result};
+
+use crate::{glob, output::ImageFormat};
+
+/// The extension of the rendered images.
+pub const OUTPUT_EXTENSION: &str = "png";
+
+/// An input image and where to write its render.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The input image.
+    pub input: PathBuf,
+    /// The rendered image.
+    pub output: PathBuf,
+}
+
+/// Find the inputs of the patterns, and name their outputs.
+///
+/// An input found by several patterns is rendered once. The directories of
+/// the inputs below the patterns are kept in `out_dir`. The extension of the
+/// names picks the format of the outputs, see [`ImageFormat::from_path`].
+pub fn jobs(patterns: &[String], out_dir: &Path, name: &str) -> Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    let mut inputs = HashSet::new();
+    // Output -> input, to detect two inputs with the same output.
+    let mut outputs: HashMap<PathBuf, PathBuf> = HashMap::new();
+
+    for pattern in patterns {
+        let matches = glob::expand(pattern)?;
+        if matches.is_empty() {
+            eprintln!("warning: \"{}\" matches no file.", pattern);
+        }
+
+        for m in matches {
+            if !inputs.insert(m.path.clone()) {
+                continue;
+            }
+
+            let output = out_dir
+                .join(&m.relative_dir)
+                .join(output_name(name, &m.path)?);
+            ImageFormat::from_path(&output)?;
+            if let Some(input) = outputs.insert(output.clone(), m.path.clone()) {
+                bail!(
+                    "\"{}\" and \"{}\" are both rendered to \"{}\", add {{stem}} to --name.",
+                    input.display(),
+                    m.path.display(),
+                    output.display()
+                );
+            }
+            jobs.push(Job {
+                input: m.path,
+                output,
+            });
+        }
+    }
+
+    Ok(jobs)
+}
+
+/// The file name of the render of `input`.
+///
+/// The template can use `{stem}`, the file name of the input without its
+/// extension, `{name}`, the file name of the input, and `{ext}`, the
+/// extension of the output.
+pub fn output_name(template: &str, input: &Path) -> Result<String> {
+    let file_name = |path: Option<&std::ffi::OsStr>| {
+        path.map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            bail!("Invalid --name \"{}\": unclosed \"{{\".", template);
+        };
+        match &rest[start + 1..start + end] {
+            "stem" => name.push_str(&file_name(input.file_stem())),
+            "name" => name.push_str(&file_name(input.file_name())),
+            "ext" => name.push_str(OUTPUT_EXTENSION),
+            other => bail!(
+                "Invalid --name \"{}\": unknown placeholder \"{{{}}}\", expected {{stem}}, {{name}} or {{ext}}.",
+                template,
+                other
+            ),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+
+    // The name must stay in the output directory.
+    let path = Path::new(&name);
+    if name.is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!(
+            "Invalid --name \"{}\": \"{}\" is not a file name in the output directory.",
+            template,
+            name
+        );
+    }
+
+    Ok(name)
+}
+
+/// Run `render` on every job, with `threads` threads.
+///
+/// Returns the failed jobs with their errors, in the order of the jobs.
+pub fn run<F>(jobs: &[Job], threads: usize, render: F) -> Vec<(&Job, Error)>
+where
+    F: Fn(&Job) -> Result<()> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+
+    thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(index) else {
+                    break;
+                };
+                match render(job) {
+                    Ok(()) => eprintln!("{} -> {}", job.input.display(), job.output.display()),
+                    Err(e) => {
+                        eprintln!("{}: error: {:#}", job.input.display(), e);
+                        failures.lock().unwrap().push((index, e));
+                    }
+                }
+            });
+        }
+    });
+
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort_by_key(|(index, _)| *index);
+    failures
+        .into_iter()
+        .map(|(index, e)| (&jobs[index], e))
+        .collect()
+}
+
+/// The summary printed after a batch.
+pub struct Summary<'a> {
+    /// The number of jobs.
+    pub total: usize,
+    /// The failed jobs with their errors.
+    pub failures: &'a [(&'a Job, Error)],
+    /// The time the batch took.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Rendered {} of {} image(s) in {:.1}s.",
+            self.total - self.failures.len(),
+            self.total,
+            self.elapsed.as_secs_f64()
+        )?;
+        if !self.failures.is_empty() {
+            writeln!(f, "{} failed:", self.failures.len())?;
+            for (job, e) in self.failures {
+                writeln!(f, "  {}: {:#}", job.input.display(), e)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_outputs() {
+        let input = Path::new("shots/login.page.png");
+        assert_eq!(
+            output_name("{stem}-fl.{ext}", input).unwrap(),
+            "login.page-fl.png"
+        );
+        assert_eq!(
+            output_name("{name}.{ext}", input).unwrap(),
+            "login.page.png.png"
+        );
+        assert_eq!(
+            output_name("{stme}.png", input).unwrap_err().to_string(),
+            "Invalid --name \"{stme}.png\": unknown placeholder \"{stme}\", expected {stem}, {name} or {ext}."
+        );
+        assert!(output_name("{stem", input).is_err());
+        assert!(output_name("../{stem}.png", input).is_err());
+        assert!(output_name("/tmp/{stem}.png", input).is_err());
+    }
+
+    #[test]
+    fn run_jobs() {
+        let jobs: Vec<_> = (0..20)
+            .map(|i| Job {
+                input: PathBuf::from(format!("{}.png", i)),
+                output: PathBuf::from(format!("out/{}.png", i)),
+            })
+            .collect();
+
+        let done = AtomicUsize::new(0);
+        let failures = run(&jobs, 4, |job| {
+            done.fetch_add(1, Ordering::Relaxed);
+            match job.input.to_str() {
+                Some("3.png" | "11.png") => bail!("broken"),
+                _ => Ok(()),
+            }
+        });
+
+        assert_eq!(done.into_inner(), 20);
+        let failed: Vec<_> = failures
+            .iter()
+            .map(|(job, _)| job.input.to_str().unwrap())
+            .collect();
+        assert_eq!(failed, ["3.png", "11.png"]);
+
+        let summary = Summary {
+            total: jobs.len(),
+            failures: &failures,
+            elapsed: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            summary.to_string(),
+            "Rendered 18 of 20 image(s) in 1.5s.\n2 failed:\n  3.png: broken\n  11.png: broken\n"
+        );
+    }
+}
@@ -0,0 +1,312 @@
+//! Download of remote image sources over HTTP(S).
+
+use std::{io::Read, time::Duration};
+
+use thiserror::Error;
+
+use footlights_engine::limits::Limits;
+
+use crate::policy::{Policy, PolicyError};
+
+/// The error type for fetching remote images.
+#[derive(Debug, Error)]
+pub enum FetchError {
+    /// The server answered with an error status.
+    #[error("Failed to fetch \"{url}\": HTTP status {status}.")]
+    Status {
+        /// The requested URL.
+        url: String,
+        /// The HTTP status code.
+        status: u16,
+    },
+    /// The connection failed or timed out.
+    #[error("Failed to fetch \"{0}\": {1}")]
+    Network(String, #[source] Box<ureq::Error>),
+    /// The request was redirected too many times.
+    #[error("Failed to fetch \"{url}\": more than {limit} redirects.")]
+    TooManyRedirects {
+        /// The requested URL.
+        url: String,
+        /// The maximum number of redirects.
+        limit: u32,
+    },
+    /// The URL, or the target of a redirect, is refused by the policy.
+    #[error(transparent)]
+    Denied(#[from] PolicyError),
+    /// The response body could not be read.
+    #[error("Failed to read the response of \"{0}\": {1}")]
+    Io(String, #[source] std::io::Error),
+    /// The response is not an image.
+    #[error("\"{url}\" is not an image (content type \"{content_type}\").")]
+    ContentType {
+        /// The requested URL.
+        url: String,
+        /// The content type of the response.
+        content_type: String,
+    },
+    /// The response is larger than the limit.
+    #[error("\"{url}\" is larger than the limit of {limit} bytes.")]
+    TooLarge {
+        /// The requested URL.
+        url: String,
+        /// The size limit in bytes.
+        limit: u64,
+    },
+}
+
+/// Options for fetching remote images.
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    /// Timeout for the whole request, including the body.
+    pub timeout: Duration,
+    /// The maximum size of the response body, in bytes.
+    pub max_size: u64,
+    /// The maximum number of redirects to follow.
+    pub max_redirects: u32,
+    /// The policy of the URLs, checked for every redirect.
+    pub policy: Policy,
+    /// The limits of the images, and of the render.
+    pub limits: Limits,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            max_size: 64 * 1024 * 1024,
+            max_redirects: 5,
+            policy: Policy::default(),
+            limits: Limits::default(),
+        }
+    }
+}
+
+/// Whether `src` is a remote image source.
+pub fn is_remote(src: &str) -> bool {
+    src.starts_with("http://") || src.starts_with("https://")
+}
+
+/// Download the image at `url`.
+///
+/// The response must have an `image/*` or `application/octet-stream`
+/// content type, or none, and must not be larger than `options.max_size`.
+pub fn fetch(url: &str, options: &FetchOptions) -> Result<Vec<u8>, FetchError> {
+    // The redirects are followed here, to check their targets.
+    let agent = ureq::AgentBuilder::new()
+        .timeout(options.timeout)
+        .redirects(0)
+        .build();
+
+    let mut target = url.to_owned();
+    let mut redirects = 0;
+    let response = loop {
+        options.policy.check_url(&target)?;
+        let response = agent.get(&target).call().map_err(|e| match e {
+            ureq::Error::Status(status, _) => FetchError::Status {
+                url: url.to_owned(),
+                status,
+            },
+            e => FetchError::Network(url.to_owned(), Box::new(e)),
+        })?;
+
+        let location = response.header("Location");
+        let (true, Some(location)) = ((300..400).contains(&response.status()), location) else {
+            break response;
+        };
+        if redirects == options.max_redirects {
+            return Err(FetchError::TooManyRedirects {
+                url: url.to_owned(),
+                limit: options.max_redirects,
+            });
+        }
+        redirects += 1;
+        target = match url::Url::parse(&target).and_then(|base| base.join(location)) {
+            Ok(next) => next.into(),
+            Err(_) => location.to_owned(),
+        };
+    };
+
+    // ureq reports `text/plain` when the server sends no content type.
+    let content_type = response.content_type().to_ascii_lowercase();
+    let is_image = content_type.starts_with("image/") || content_type == "application/octet-stream";
+    if response.header("Content-Type").is_some() && !is_image {
+        return Err(FetchError::ContentType {
+            url: url.to_owned(),
+            content_type,
+        });
+    }
+
+    let too_large = || FetchError::TooLarge {
+        url: url.to_owned(),
+        limit: options.max_size,
+    };
+
+    let length = response
+        .header("Content-Length")
+        .and_then(|length| length.parse::<u64>().ok());
+    if matches!(length, Some(length) if length > options.max_size) {
+        return Err(too_large());
+    }
+
+    // Read one byte more than allowed to detect oversized bodies
+    // without a `Content-Length`.
+    let mut bytes = Vec::with_capacity(length.unwrap_or(0) as usize);
+    response
+        .into_reader()
+        .take(options.max_size + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| FetchError::Io(url.to_owned(), e))?;
+    if bytes.len() as u64 > options.max_size {
+        return Err(too_large());
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Scheme;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    /// Serve the canned responses on a local port, one per connection,
+    /// and return the base URL of the server.
+    fn serve(responses: Vec<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                // Drain the request header.
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                stream.write_all(&response).unwrap();
+            }
+        });
+
+        url
+    }
+
+    fn response(status: &str, headers: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+        for (name, value) in headers {
+            response.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        response.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    #[test]
+    fn fetch_image() {
+        let url = serve(vec![response(
+            "200 OK",
+            &[("Content-Type", "image/png")],
+            b"\x89PNG",
+        )]);
+
+        let bytes = fetch(&format!("{}/a.png", url), &FetchOptions::default()).unwrap();
+        assert_eq!(bytes, b"\x89PNG");
+
+        // Without a content type.
+        let url = serve(vec![response("200 OK", &[], b"\x89PNG")]);
+        let bytes = fetch(&format!("{}/a.png", url), &FetchOptions::default()).unwrap();
+        assert_eq!(bytes, b"\x89PNG");
+    }
+
+    #[test]
+    fn fetch_redirect() {
+        let target = serve(vec![response(
+            "200 OK",
+            &[("Content-Type", "image/png")],
+            b"\x89PNG",
+        )]);
+        let url = serve(vec![response(
+            "302 Found",
+            &[("Location", &format!("{}/b.png", target))],
+            b"",
+        )]);
+
+        let bytes = fetch(&format!("{}/a.png", url), &FetchOptions::default()).unwrap();
+        assert_eq!(bytes, b"\x89PNG");
+
+        let url = serve(vec![response("302 Found", &[("Location", "/a.png")], b"")]);
+        let options = FetchOptions {
+            max_redirects: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            fetch(&format!("{}/a.png", url), &options),
+            Err(FetchError::TooManyRedirects { limit: 0, .. })
+        ));
+
+        // The target of a redirect is checked.
+        let url = serve(vec![response(
+            "302 Found",
+            &[("Location", "http://metadata.internal/a.png")],
+            b"",
+        )]);
+        let options = FetchOptions {
+            policy: Policy::new(None, vec![Scheme::Http], vec!["*.internal".to_owned()]).unwrap(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            fetch(&format!("{}/a.png", url), &options),
+            Err(FetchError::Denied(PolicyError::Denied { .. }))
+        ));
+    }
+
+    #[test]
+    fn fetch_errors() {
+        let url = serve(vec![
+            response("404 Not Found", &[("Content-Type", "text/plain")], b"gone"),
+            response("200 OK", &[("Content-Type", "text/html")], b"<html>"),
+            response("200 OK", &[("Content-Type", "image/png")], &[0; 64]),
+        ]);
+        let options = FetchOptions {
+            max_size: 32,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            fetch(&format!("{}/a.png", url), &options),
+            Err(FetchError::Status { status: 404, .. })
+        ));
+        assert!(matches!(
+            fetch(&format!("{}/a.png", url), &options),
+            Err(FetchError::ContentType { .. })
+        ));
+        assert!(matches!(
+            fetch(&format!("{}/a.png", url), &options),
+            Err(FetchError::TooLarge { limit: 32, .. })
+        ));
+    }
+
+    #[test]
+    fn fetch_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/a.png", listener.local_addr().unwrap());
+        // Accept the connection, but never answer.
+        let handle = thread::spawn(move || listener.accept().map(|(stream, _)| stream));
+
+        let options = FetchOptions {
+            timeout: Duration::from_millis(200),
+            ..Default::default()
+        };
+        assert!(matches!(
+            fetch(&url, &options),
+            Err(FetchError::Network(..))
+        ));
+        drop(handle.join());
+    }
+}
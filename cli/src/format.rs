@@ -0,0 +1,290 @@
+//! Config file formats: YAML, TOML and JSON.
+//!
+//! Every format reads and writes the same [`Config`] type. They only differ
+//! in how enum variants are written: YAML uses tags (`color: !Pure white`),
+//! while TOML and JSON use a single-key table (`color = { Pure = "white" }`).
+//!
+//! Templated configs are read and written as trees of values instead, whose
+//! placeholders may not have the type of their field yet.
+
+use std::{fmt, path::Path};
+
+use clap::ValueEnum;
+use footlights_engine::configs::Config;
+use serde_yaml::{
+    value::{Tag, TaggedValue},
+    Mapping, Value,
+};
+use thiserror::Error;
+
+use crate::template::Condition;
+
+/// The style fields holding an enum, see [`Format::parse_value`].
+const ENUM_FIELDS: [&str; 3] = ["position", "size", "color"];
+
+/// A config file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// YAML, the default.
+    Yaml,
+    /// TOML.
+    Toml,
+    /// JSON.
+    Json,
+}
+
+/// The error type for reading and writing configs.
+#[derive(Debug, Error)]
+pub enum FormatError {
+    /// The YAML config is invalid.
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    /// The TOML config is invalid.
+    #[error(transparent)]
+    TomlRead(#[from] toml::de::Error),
+    /// The config cannot be written as TOML.
+    #[error(transparent)]
+    TomlWrite(#[from] toml::ser::Error),
+    /// The JSON config is invalid.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl FormatError {
+    /// The line and column of the error in `text`, starting from 1.
+    pub fn location(&self, text: &str) -> Option<(usize, usize)> {
+        match self {
+            Self::Yaml(e) => e.location().map(|l| (l.line(), l.column())),
+            Self::TomlRead(e) => e.span().map(|span| line_column(text, span.start)),
+            Self::TomlWrite(_) => None,
+            Self::Json(e) if e.line() > 0 => Some((e.line(), e.column())),
+            Self::Json(_) => None,
+        }
+    }
+
+    /// The message of the error, without its location.
+    pub fn message(&self) -> String {
+        match self {
+            Self::TomlRead(e) => e.message().to_owned(),
+            e => {
+                let message = e.to_string();
+                // serde_yaml and serde_json append " at line 1 column 2".
+                match message.rfind(" at line ") {
+                    Some(end) => message[..end].to_owned(),
+                    None => message,
+                }
+            }
+        }
+    }
+}
+
+impl Format {
+    /// Detect the format of a file from its extension, defaults to YAML.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let extension = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("toml") => Self::Toml,
+            Some("json") => Self::Json,
+            _ => Self::Yaml,
+        }
+    }
+
+    /// Parse a config.
+    pub fn parse(self, text: &str) -> Result<Config, FormatError> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::from_str(text)?,
+            Self::Toml => toml::from_str(text)?,
+            Self::Json => serde_json::from_str(text)?,
+        })
+    }
+
+    /// Parse a config as a tree of values, without the config types.
+    ///
+    /// The enums of the styles are YAML tags, whatever the format: the
+    /// single-key tables of their fields become tags in TOML and JSON.
+    pub fn parse_value(self, text: &str) -> Result<Value, FormatError> {
+        let mut value = match self {
+            Self::Yaml => return Ok(serde_yaml::from_str(text)?),
+            Self::Toml => serde_yaml::to_value(toml::from_str::<toml::Table>(text)?)?,
+            Self::Json => serde_yaml::to_value(serde_json::from_str::<serde_json::Value>(text)?)?,
+        };
+
+        for_entries(&mut value, &mut |key, styles| {
+            if key == "styles" {
+                for_entries(styles, &mut |_, style| {
+                    for_entries(style, &mut |field, value| {
+                        if ENUM_FIELDS.contains(&field) {
+                            tag(value);
+                        }
+                    })
+                });
+            }
+        });
+        Ok(value)
+    }
+
+    /// Write a tree of values, see [`Format::parse_value`].
+    pub fn value_to_string(self, value: &Value) -> Result<String, FormatError> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::to_string(value)?,
+            Self::Toml => toml::to_string(&untag(value))?,
+            Self::Json => serde_json::to_string_pretty(&untag(value))? + "\n",
+        })
+    }
+
+    /// Read the `include` key of a config, before it is validated.
+    ///
+    /// Returns no includes if the config is invalid, the validation
+    /// reports it.
+    pub fn includes(self, text: &str) -> Vec<String> {
+        let include = match self {
+            Self::Yaml => serde_yaml::from_str::<serde_yaml::Value>(text)
+                .ok()
+                .and_then(|config| config.get("include").cloned())
+                .and_then(|include| serde_yaml::from_value(include).ok()),
+            Self::Toml => toml::from_str::<toml::Table>(text)
+                .ok()
+                .and_then(|mut config| config.remove("include"))
+                .and_then(|include| include.try_into().ok()),
+            Self::Json => serde_json::from_str::<serde_json::Value>(text)
+                .ok()
+                .and_then(|config| config.get("include").cloned())
+                .and_then(|include| serde_json::from_value(include).ok()),
+        };
+
+        include.unwrap_or_default()
+    }
+
+    /// Write a config.
+    pub fn to_string(self, config: &Config) -> Result<String, FormatError> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::to_string(config)?,
+            Self::Toml => toml::to_string(config)?,
+            Self::Json => serde_json::to_string_pretty(config)? + "\n",
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Yaml => "YAML",
+            Self::Toml => "TOML",
+            Self::Json => "JSON",
+        })
+    }
+}
+
+/// Call `f` with the entries of a mapping, and of its conditional blocks,
+/// see [`crate::template`].
+fn for_entries(value: &mut Value, f: &mut dyn FnMut(&str, &mut Value)) {
+    let Value::Mapping(mapping) = value else {
+        return;
+    };
+    for (key, value) in mapping.iter_mut() {
+        match key.as_str() {
+            Some(key) if Condition::parse(key).is_some() => for_entries(value, f),
+            Some(key) => f(key, value),
+            None => {}
+        }
+    }
+}
+
+/// Turn a single-key table into the tagged value of its key.
+fn tag(value: &mut Value) {
+    let Value::Mapping(mapping) = value else {
+        return;
+    };
+    let mut entries = mapping.iter();
+    if let (Some((Value::String(variant), inner)), None) = (entries.next(), entries.next()) {
+        *value = Value::Tagged(Box::new(TaggedValue {
+            tag: Tag::new(variant),
+            value: inner.clone(),
+        }));
+    }
+}
+
+/// Turn the tagged values into single-key tables.
+fn untag(value: &Value) -> Value {
+    match value {
+        Value::Tagged(tagged) => {
+            let variant = tagged.tag.to_string();
+            let mut mapping = Mapping::new();
+            mapping.insert(variant.trim_start_matches('!').into(), untag(&tagged.value));
+            Value::Mapping(mapping)
+        }
+        Value::Sequence(items) => Value::Sequence(items.iter().map(untag).collect()),
+        Value::Mapping(mapping) => Value::Mapping(
+            mapping
+                .iter()
+                .map(|(key, value)| (key.clone(), untag(value)))
+                .collect(),
+        ),
+        value => value.clone(),
+    }
+}
+
+/// The line and column of the byte `offset` in `text`, starting from 1.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_format() {
+        assert_eq!(Format::from_path("a/config.toml"), Format::Toml);
+        assert_eq!(Format::from_path("config.JSON"), Format::Json);
+        assert_eq!(Format::from_path("config.yml"), Format::Yaml);
+        assert_eq!(Format::from_path("config"), Format::Yaml);
+    }
+
+    #[test]
+    fn round_trip() {
+        let yaml = include_str!("../../examples/basic.yaml");
+        let yaml = format!(
+            "{}\nlayers:\n  - {{ ty: Background, id: bg, style: bg }}\n  - {{ ty: Image, id: img, style: img }}\n",
+            yaml.trim_end()
+        );
+        let mut config = Format::Yaml.parse(&yaml).unwrap();
+        config.include = vec!["base.yaml".to_owned()];
+        let expected = Format::Yaml.to_string(&config).unwrap();
+
+        let mut text = expected.clone();
+        for format in [Format::Toml, Format::Json, Format::Yaml] {
+            let config = Format::Yaml.parse(&text).unwrap();
+            let converted = format.to_string(&config).unwrap();
+            text = Format::Yaml
+                .to_string(&format.parse(&converted).unwrap())
+                .unwrap();
+        }
+        assert_eq!(text, expected);
+
+        let toml = Format::Toml.to_string(&config).unwrap();
+        assert!(toml.contains("[styles.bg.color.Linear]"), "{}", toml);
+        let json = Format::Json.to_string(&config).unwrap();
+        assert!(json.contains(r#""Linear": {"#), "{}", json);
+    }
+
+    #[test]
+    fn error_location() {
+        let text = "[styles.img]\nround = \"ten\"\n";
+        let error = Format::Toml.parse(text).unwrap_err();
+        assert_eq!(error.location(text), Some((2, 9)));
+
+        let text = "{\n  \"styles\": {},\n  \"layer\": []\n}";
+        let error = Format::Json.parse(text).unwrap_err();
+        assert_eq!(error.location(text), Some((3, 9)));
+        assert!(error.message().starts_with("unknown field `layer`"));
+    }
+}
@@ -0,0 +1,328 @@
+//! Expansion of the input patterns of `fl batch`.
+//!
+//! A pattern is a file, a directory, whose images are found recursively,
+//! or a glob. In a glob, `*` matches any characters but `/`, `?` a single
+//! character, `[abc]`, `[a-z]` and `[!abc]` a character of a set, and a
+//! `**` component any number of directories.
+//!
+//! A glob only reads the directories it can match, and entries that cannot be
+//! read are skipped with a warning.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// The extensions of the images found in a directory.
+const IMAGE_EXTENSIONS: [&str; 6] = ["png", "jpg", "jpeg", "gif", "webp", "bmp"];
+
+/// A file found by a pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The directory of the file, relative to the part of the pattern
+    /// without wildcards.
+    pub relative_dir: PathBuf,
+}
+
+/// Find the files of a pattern, sorted by path.
+pub fn expand(pattern: &str) -> Result<Vec<Match>> {
+    let path = Path::new(pattern);
+    let components: Vec<_> = path.components().collect();
+    let Some(start) = components
+        .iter()
+        .position(|component| is_glob(&component.as_os_str().to_string_lossy()))
+    else {
+        return expand_path(path);
+    };
+
+    let base: PathBuf = components[..start].iter().collect();
+    let globs: Vec<_> = components[start..]
+        .iter()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+
+    // Without `**`, the files are as deep as the glob.
+    let depth = (!globs.iter().any(|glob| glob == "**")).then_some(globs.len());
+    let mut files = Vec::new();
+    walk(&base, depth, &mut files)
+        .with_context(|| format!("Failed to read the directory of \"{}\".", pattern))?;
+
+    let mut matches: Vec<_> = files
+        .into_iter()
+        .filter_map(|path| {
+            let relative = path.strip_prefix(&base).ok()?;
+            let names: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let relative_dir = relative.parent().unwrap_or(Path::new("")).to_owned();
+            matches_path(&globs, &names).then_some(Match { path, relative_dir })
+        })
+        .collect();
+    matches.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(matches)
+}
+
+/// Find the file, or the images of the directory.
+fn expand_path(path: &Path) -> Result<Vec<Match>> {
+    if !path.is_dir() {
+        if !path.exists() {
+            bail!("\"{}\" does not exist.", path.display());
+        }
+        return Ok(vec![Match {
+            path: path.to_owned(),
+            relative_dir: PathBuf::new(),
+        }]);
+    }
+
+    let mut files = Vec::new();
+    walk(path, None, &mut files)
+        .with_context(|| format!("Failed to read the directory \"{}\".", path.display()))?;
+
+    let mut matches: Vec<_> = files
+        .into_iter()
+        .filter(|file| {
+            file.extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+        })
+        .map(|file| Match {
+            relative_dir: file
+                .strip_prefix(path)
+                .ok()
+                .and_then(Path::parent)
+                .unwrap_or(Path::new(""))
+                .to_owned(),
+            path: file,
+        })
+        .collect();
+    matches.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(matches)
+}
+
+/// Collect the files under `dir`, recursively, down to `depth` levels.
+///
+/// Only `dir` itself must be readable, the entries below it that cannot be
+/// read are skipped with a warning.
+fn walk(dir: &Path, depth: Option<usize>, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let read = if dir.as_os_str().is_empty() {
+        std::fs::read_dir(".")?
+    } else {
+        std::fs::read_dir(dir)?
+    };
+    let skip = |path: &Path, e: std::io::Error| {
+        eprintln!("warning: Skipped \"{}\": {}", path.display(), e);
+    };
+
+    for entry in read {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                skip(dir, e);
+                continue;
+            }
+        };
+        let path = dir.join(entry.file_name());
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => {
+                if depth.is_some_and(|depth| depth <= 1) {
+                    continue;
+                }
+                if let Err(e) = walk(&path, depth.map(|depth| depth - 1), files) {
+                    skip(&path, e);
+                }
+            }
+            Ok(_) => files.push(path),
+            Err(e) => skip(&path, e),
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a component of a pattern has wildcards.
+fn is_glob(component: &str) -> bool {
+    component.contains(['*', '?', '['])
+}
+
+/// Match the components of a path against the components of a glob.
+pub fn matches_path(globs: &[String], names: &[String]) -> bool {
+    match (globs.first(), names.first()) {
+        (None, None) => true,
+        (Some(glob), _) if glob == "**" => {
+            // Match no directory, or one more.
+            matches_path(&globs[1..], names)
+                || !names.is_empty() && matches_path(globs, &names[1..])
+        }
+        (Some(glob), Some(name)) => {
+            matches_name(glob, name) && matches_path(&globs[1..], &names[1..])
+        }
+        _ => false,
+    }
+}
+
+/// Match a file name against a component of a glob.
+pub fn matches_name(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    matches_chars(&glob, &name)
+}
+
+fn matches_chars(glob: &[char], name: &[char]) -> bool {
+    match glob.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| matches_chars(&glob[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && matches_chars(&glob[1..], &name[1..]),
+        Some('[') => match (class(&glob[1..]), name.first()) {
+            (Some((matches, len)), Some(&c)) => {
+                matches(c) && matches_chars(&glob[len + 1..], &name[1..])
+            }
+            // An unclosed bracket is a literal.
+            (None, Some('[')) => matches_chars(&glob[1..], &name[1..]),
+            _ => false,
+        },
+        Some(&c) => name.first() == Some(&c) && matches_chars(&glob[1..], &name[1..]),
+    }
+}
+
+/// Parse a character class after its `[`, returning the matcher and the
+/// length of the class with its `]`.
+fn class(glob: &[char]) -> Option<(impl Fn(char) -> bool + '_, usize)> {
+    let negated = matches!(glob.first(), Some('!' | '^'));
+    let start = usize::from(negated);
+    // A `]` right after the `[` is a member of the class.
+    let end = start + 1 + glob.get(start + 1..)?.iter().position(|&c| c == ']')?;
+    let members = &glob[start..end];
+
+    let matches = move |c: char| {
+        let mut i = 0;
+        let mut found = false;
+        while i < members.len() {
+            if i + 2 < members.len() && members[i + 1] == '-' {
+                found |= (members[i]..=members[i + 2]).contains(&c);
+                i += 3;
+            } else {
+                found |= members[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    };
+    Some((matches, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_globs() {
+        assert!(matches_name("*.png", "a.png"));
+        assert!(!matches_name("*.png", "a.jpg"));
+        assert!(matches_name("shot-??.png", "shot-01.png"));
+        assert!(matches_name("[a-c]*", "b.png"));
+        assert!(!matches_name("[!a-c]*", "b.png"));
+        assert!(matches_name("[]]", "]"));
+        assert!(matches_name("[x", "[x"));
+
+        let glob =
+            |pattern: &str| -> Vec<String> { pattern.split('/').map(str::to_owned).collect() };
+        let path = |path: &str| -> Vec<String> { path.split('/').map(str::to_owned).collect() };
+        assert!(matches_path(&glob("**/*.png"), &path("a.png")));
+        assert!(matches_path(&glob("**/*.png"), &path("a/b/c.png")));
+        assert!(matches_path(&glob("a/**/c.png"), &path("a/c.png")));
+        assert!(!matches_path(&glob("*.png"), &path("a/b.png")));
+        assert!(!matches_path(&glob("a/**"), &path("b/c.png")));
+    }
+
+    #[test]
+    fn expand_patterns() {
+        // The process id keeps concurrent test runs apart.
+        let dir =
+            std::env::temp_dir().join(format!("footlights-glob-expand-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in ["a.png", "b.jpg", "notes.txt", "sub/c.png", "sub/deep/d.PNG"] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let relative = |matches: Vec<Match>| -> Vec<(String, String)> {
+            matches
+                .into_iter()
+                .map(|m| {
+                    let path = m.path.strip_prefix(&dir).unwrap();
+                    (
+                        path.to_string_lossy().into_owned(),
+                        m.relative_dir.to_string_lossy().into_owned(),
+                    )
+                })
+                .collect()
+        };
+        let pattern = |glob: &str| dir.join(glob).to_string_lossy().into_owned();
+
+        assert_eq!(
+            relative(expand(&pattern("**/*.png")).unwrap()),
+            [("a.png", ""), ("sub/c.png", "sub")].map(|(a, b)| (a.to_owned(), b.to_owned()))
+        );
+        assert_eq!(
+            relative(expand(&pattern("sub")).unwrap()),
+            [("sub/c.png", ""), ("sub/deep/d.PNG", "deep")]
+                .map(|(a, b)| (a.to_owned(), b.to_owned()))
+        );
+        assert_eq!(expand(&pattern("*.txt")).unwrap().len(), 1);
+        assert!(expand(&pattern("missing.png")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn walk_depth() {
+        // The process id keeps concurrent test runs apart.
+        let dir = std::env::temp_dir().join(format!("footlights-glob-walk-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in ["a.png", "sub/b.png", "sub/deep/c.png"] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let names = |depth| {
+            let mut files = Vec::new();
+            walk(&dir, depth, &mut files).unwrap();
+            let mut names: Vec<_> = files
+                .iter()
+                .map(|file| {
+                    file.strip_prefix(&dir)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(Some(1)), ["a.png"]);
+        assert_eq!(names(Some(2)), ["a.png", "sub/b.png"]);
+        assert_eq!(names(None), ["a.png", "sub/b.png", "sub/deep/c.png"]);
+
+        // An unreadable directory is skipped, root can read it anyway.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let locked = dir.join("sub/deep");
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+            let readable = std::fs::read_dir(&locked).is_ok();
+            let names = names(None);
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+            if !readable {
+                assert_eq!(names, ["a.png", "sub/b.png"]);
+            }
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
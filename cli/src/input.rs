@@ -0,0 +1,375 @@
+//! Decoding of the input images.
+//!
+//! The format of an input is sniffed from its magic bytes, never from its
+//! name, so the MIME type in the generated data URL is always correct.
+//! Inputs that resvg cannot draw as-is (formats it does not support, or
+//! photos with a non-default EXIF orientation) are transcoded to PNG here.
+
+use std::{
+    fs::File,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use base64::{
+    alphabet,
+    engine::{general_purpose::GeneralPurpose, DecodePaddingMode, GeneralPurposeConfig},
+    Engine,
+};
+use footlights_engine::limits::{LimitError, Limits};
+use image::{io::Reader, DynamicImage, ImageFormat, ImageOutputFormat};
+use thiserror::Error;
+
+/// Base64 engine for data URLs, padding is optional when decoding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The error type for decoding input images.
+#[derive(Debug, Error)]
+pub enum InputError {
+    /// The input could not be read.
+    #[error("Failed to read image \"{0}\": {1}")]
+    Io(PathBuf, #[source] std::io::Error),
+    /// The data URL is malformed or not base64-encoded.
+    #[error("Invalid data URL: {0}")]
+    InvalidDataUrl(String),
+    /// The input is empty.
+    #[error("The image is empty.")]
+    Empty,
+    /// The magic bytes do not match any known image format.
+    #[error("Unsupported image format.")]
+    UnknownFormat,
+    /// The format is known, but is not supported as input.
+    #[error(
+        "Unsupported image format: {0:?}. Supported formats are PNG, JPEG, GIF, WebP and BMP."
+    )]
+    UnsupportedFormat(ImageFormat),
+    /// The image data is corrupt.
+    #[error("Failed to decode {0:?} image: {1}")]
+    Corrupt(ImageFormat, #[source] image::ImageError),
+    /// The file or the image is larger than the limits.
+    #[error(transparent)]
+    Limit(#[from] LimitError),
+}
+
+/// The slot of an image given without a name.
+pub const DEFAULT_SLOT: &str = "image";
+
+/// Split an `-i` argument into its slot and its source.
+///
+/// `logo=assets/logo.png` fills the `logo` slot, a source alone fills the
+/// [`DEFAULT_SLOT`]. The name of a slot is made of letters, digits, `_` and
+/// `-`, so URLs with a query are not mistaken for named slots.
+pub fn parse_slot(arg: &str) -> (&str, &str) {
+    match arg.split_once('=') {
+        Some((name, source))
+            if !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+        {
+            (name, source)
+        }
+        _ => (DEFAULT_SLOT, arg),
+    }
+}
+
+/// An input image, ready to be referenced by the canvas.
+#[derive(Debug, Clone)]
+pub struct InputImage {
+    /// The format of `bytes`.
+    pub format: ImageFormat,
+    /// The encoded image, in its upright orientation.
+    pub bytes: Vec<u8>,
+}
+
+impl InputImage {
+    /// Read and decode the image at `path`.
+    ///
+    /// The size of the file is checked before it is read.
+    pub fn open<P: AsRef<Path>>(path: P, limits: &Limits) -> Result<Self, InputError> {
+        let path = path.as_ref();
+        let io_error = |e| InputError::Io(path.to_owned(), e);
+        let file = File::open(path).map_err(io_error)?;
+        limits.check_file_size(file.metadata().map_err(io_error)?.len())?;
+
+        // The file can grow while it is read.
+        let mut bytes = Vec::new();
+        file.take(limits.max_file_size + 1)
+            .read_to_end(&mut bytes)
+            .map_err(io_error)?;
+
+        Self::from_bytes(bytes, limits)
+    }
+
+    /// Decode the image embedded in a base64 data URL.
+    ///
+    /// The MIME type in the URL is ignored, the format is sniffed instead.
+    pub fn from_data_url(url: &str, limits: &Limits) -> Result<Self, InputError> {
+        let (header, payload) = url
+            .strip_prefix("data:")
+            .and_then(|url| url.split_once(','))
+            .ok_or_else(|| InputError::InvalidDataUrl("missing \"data:\" header".to_owned()))?;
+        if !header.ends_with(";base64") {
+            return Err(InputError::InvalidDataUrl(
+                "only base64 data URLs are supported".to_owned(),
+            ));
+        }
+
+        let payload = payload.trim();
+        // Every 4 characters of base64 encode 3 bytes.
+        limits.check_file_size(payload.len() as u64 / 4 * 3)?;
+        let bytes = BASE64
+            .decode(payload)
+            .map_err(|e| InputError::InvalidDataUrl(e.to_string()))?;
+
+        Self::from_bytes(bytes, limits)
+    }
+
+    /// Decode an image from its encoded bytes.
+    ///
+    /// The dimensions are read from the header, and checked against the
+    /// limits before the pixels are decoded.
+    pub fn from_bytes(bytes: Vec<u8>, limits: &Limits) -> Result<Self, InputError> {
+        if bytes.is_empty() {
+            return Err(InputError::Empty);
+        }
+        limits.check_file_size(bytes.len() as u64)?;
+
+        let format = image::guess_format(&bytes).map_err(|_| InputError::UnknownFormat)?;
+        if mime_type(format).is_none() {
+            return Err(InputError::UnsupportedFormat(format));
+        }
+
+        // Reject images with a corrupt header, or too many pixels, early.
+        let (width, height) = Reader::with_format(Cursor::new(&bytes), format)
+            .into_dimensions()
+            .map_err(|e| InputError::Corrupt(format, e))?;
+        limits.check_input(width, height)?;
+
+        let orientation = exif_orientation(&bytes);
+
+        if orientation == 1 && is_renderable(format) {
+            return Ok(Self { format, bytes });
+        }
+
+        // Transcode into PNG, baking the orientation into the pixels.
+        let img = image::load_from_memory_with_format(&bytes, format)
+            .map_err(|e| InputError::Corrupt(format, e))?;
+        let img = apply_orientation(img, orientation);
+
+        let mut png = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)
+            .map_err(|e| InputError::Corrupt(format, e))?;
+
+        Ok(Self {
+            format: ImageFormat::Png,
+            bytes: png,
+        })
+    }
+
+    /// The MIME type of the encoded image.
+    pub fn mime_type(&self) -> &'static str {
+        // Only formats with a MIME type are accepted by `from_bytes`.
+        mime_type(self.format).unwrap_or("application/octet-stream")
+    }
+}
+
+/// The MIME type of the supported input formats.
+fn mime_type(format: ImageFormat) -> Option<&'static str> {
+    match format {
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Gif => Some("image/gif"),
+        ImageFormat::WebP => Some("image/webp"),
+        ImageFormat::Bmp => Some("image/bmp"),
+        _ => None,
+    }
+}
+
+/// Whether resvg can draw the format without transcoding.
+fn is_renderable(format: ImageFormat) -> bool {
+    matches!(
+        format,
+        ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif
+    )
+}
+
+/// Read the EXIF orientation (1-8) of the image.
+///
+/// Returns 1 (the default orientation) if there is no valid EXIF data.
+fn exif_orientation(bytes: &[u8]) -> u32 {
+    exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .filter(|orientation| (1..=8).contains(orientation))
+        .unwrap_or(1)
+}
+
+/// Transform the image so that it is displayed upright.
+///
+/// See [the EXIF specification](https://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf)
+/// for the meaning of each orientation.
+fn apply_orientation(img: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use footlights_engine::image_size::probe_bytes;
+    use image::{Rgba, RgbaImage};
+
+    fn encode(width: u32, height: u32, format: ImageOutputFormat) -> Vec<u8> {
+        let img =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 255])));
+        let img = match format {
+            ImageOutputFormat::Jpeg(_) => DynamicImage::ImageRgb8(img.to_rgb8()),
+            _ => img,
+        };
+
+        let mut bytes = Vec::new();
+        img.write_to(&mut Cursor::new(&mut bytes), format).unwrap();
+        bytes
+    }
+
+    fn data_url(image: &InputImage) -> String {
+        format!(
+            "data:{};base64,{}",
+            image.mime_type(),
+            BASE64.encode(&image.bytes)
+        )
+    }
+
+    #[test]
+    fn sniff_format() {
+        let png = InputImage::from_bytes(encode(3, 2, ImageOutputFormat::Png), &Limits::default())
+            .unwrap();
+        assert_eq!(png.format, ImageFormat::Png);
+        assert_eq!(probe_bytes(&png.bytes), Ok((3, 2)));
+        assert!(data_url(&png).starts_with("data:image/png;base64,"));
+
+        let from_url = InputImage::from_data_url(&data_url(&png), &Limits::default()).unwrap();
+        assert_eq!(from_url.bytes, png.bytes);
+
+        let jpeg = InputImage::from_bytes(
+            encode(3, 2, ImageOutputFormat::Jpeg(90)),
+            &Limits::default(),
+        )
+        .unwrap();
+        assert_eq!(jpeg.mime_type(), "image/jpeg");
+        assert_eq!(probe_bytes(&jpeg.bytes), Ok((3, 2)));
+    }
+
+    #[test]
+    fn transcode_unrenderable_format() {
+        let bmp = InputImage::from_bytes(encode(3, 2, ImageOutputFormat::Bmp), &Limits::default())
+            .unwrap();
+
+        assert_eq!(bmp.format, ImageFormat::Png);
+        assert_eq!(probe_bytes(&bmp.bytes), Ok((3, 2)));
+        assert_eq!(image::guess_format(&bmp.bytes).unwrap(), ImageFormat::Png);
+    }
+
+    #[test]
+    fn orientation() {
+        let img =
+            DynamicImage::ImageRgba8(RgbaImage::from_fn(3, 2, |x, _| Rgba([x as u8, 0, 0, 255])));
+
+        let rotated = apply_orientation(img.clone(), 6);
+        assert_eq!((rotated.width(), rotated.height()), (2, 3));
+
+        let mirrored = apply_orientation(img, 2).to_rgba8();
+        assert_eq!(mirrored.get_pixel(0, 0)[0], 2);
+    }
+
+    #[test]
+    fn input_limits() {
+        let png = encode(3, 2, ImageOutputFormat::Png);
+        let limits = Limits {
+            max_input_pixels: 5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            InputImage::from_bytes(png.clone(), &limits),
+            Err(InputError::Limit(LimitError::InputPixels {
+                width: 3,
+                height: 2,
+                limit: 5
+            }))
+        ));
+
+        let limits = Limits {
+            max_file_size: 16,
+            ..Default::default()
+        };
+        // The process id keeps concurrent test runs apart.
+        let path = std::env::temp_dir().join(format!(
+            "footlights-input-limits-{}.png",
+            std::process::id()
+        ));
+        std::fs::write(&path, &png).unwrap();
+        assert!(matches!(
+            InputImage::open(&path, &limits),
+            Err(InputError::Limit(LimitError::FileSize { limit: 16, .. }))
+        ));
+        std::fs::remove_file(&path).unwrap();
+        let png = InputImage::from_bytes(png, &Limits::default()).unwrap();
+        assert!(matches!(
+            InputImage::from_data_url(&data_url(&png), &limits),
+            Err(InputError::Limit(LimitError::FileSize { limit: 16, .. }))
+        ));
+    }
+
+    #[test]
+    fn image_slots() {
+        assert_eq!(
+            parse_slot("logo=assets/logo.png"),
+            ("logo", "assets/logo.png")
+        );
+        assert_eq!(parse_slot("input.png"), ("image", "input.png"));
+        assert_eq!(
+            parse_slot("https://example.com/a.png?size=2"),
+            ("image", "https://example.com/a.png?size=2")
+        );
+        assert_eq!(parse_slot("=a.png"), ("image", "=a.png"));
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert!(matches!(
+            InputImage::from_bytes(Vec::new(), &Limits::default()),
+            Err(InputError::Empty)
+        ));
+        assert!(matches!(
+            InputImage::from_bytes(b"definitely not an image".to_vec(), &Limits::default()),
+            Err(InputError::UnknownFormat)
+        ));
+        assert!(matches!(
+            InputImage::from_data_url("data:image/png,plain", &Limits::default()),
+            Err(InputError::InvalidDataUrl(_))
+        ));
+
+        let mut truncated = encode(3, 2, ImageOutputFormat::Png);
+        truncated.truncate(12);
+        assert!(matches!(
+            InputImage::from_bytes(truncated, &Limits::default()),
+            Err(InputError::Corrupt(ImageFormat::Png, _))
+        ));
+    }
+}
@@ -0,0 +1,425 @@
+//! Loading of config files with their includes, and merging them.
+//!
+//! The includes of a file are merged before the file itself, so that the
+//! including file overrides them. Files given later on the command line
+//! override earlier ones. Each file is merged once, at its first include.
+//!
+//! The first config that sets `layers`, in merge order, replaces the default
+//! layers, and the layers of later configs are merged over them by id.
+//!
+//! Relative paths in a file, of includes and of images, are relative to the
+//! directory of the file.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use footlights_engine::configs::Config;
+
+use crate::{
+    format::Format,
+    policy::{self, Policy},
+};
+
+/// A config file, as read from the disk.
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// The path of the file, with includes relative to the working directory.
+    pub file: String,
+    /// The content of the file.
+    pub text: String,
+    /// The format of the file.
+    pub format: Format,
+}
+
+/// Read the config files and their includes, in merge order.
+///
+/// The format of each file is detected from its extension, unless `format`
+/// is set. The includes are checked against the `policy`.
+pub fn read_sources(
+    files: &[String],
+    format: Option<Format>,
+    policy: &Policy,
+) -> Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for file in files {
+        let path = PathBuf::from(file);
+        read_source(path, format, policy, &mut Vec::new(), &mut sources)?;
+    }
+
+    Ok(sources)
+}
+
+/// Read `path` after its includes.
+///
+/// `chain` holds the files being included, to detect cycles.
+fn read_source(
+    path: PathBuf,
+    format: Option<Format>,
+    policy: &Policy,
+    chain: &mut Vec<PathBuf>,
+    sources: &mut Vec<Source>,
+) -> Result<()> {
+    let file = path.to_string_lossy().into_owned();
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("Failed to read config \"{}\".", file))?;
+
+    if let Some(start) = chain.iter().position(|included| *included == canonical) {
+        let cycle: Vec<_> = chain[start..]
+            .iter()
+            .chain([&canonical])
+            .map(|path| path.to_string_lossy())
+            .collect();
+        bail!(
+            "Configs include each other in a cycle: {}.",
+            cycle.join(" -> ")
+        );
+    }
+    if sources
+        .iter()
+        .any(|source| Path::new(&source.file).canonicalize().ok() == Some(canonical.clone()))
+    {
+        return Ok(());
+    }
+
+    // The canonical path is read, it is the one the policy checked.
+    let text = std::fs::read_to_string(&canonical)
+        .with_context(|| format!("Failed to read config \"{}\".", file))?;
+    let format = format.unwrap_or_else(|| Format::from_path(&path));
+
+    chain.push(canonical);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for include in format.includes(&text) {
+        let include = dir.join(include);
+        let include = policy.check_file(&include).with_context(|| {
+            format!(
+                "Config \"{}\" cannot include \"{}\".",
+                file,
+                include.display()
+            )
+        })?;
+        // Includes may have another format.
+        read_source(include, None, policy, chain, sources)?;
+    }
+    chain.pop();
+
+    sources.push(Source { file, text, format });
+    Ok(())
+}
+
+/// Resolve the relative image paths of `config` against the directory of
+/// `file`, the config that mentions them.
+///
+/// URLs and absolute paths are kept as is. Images given on the command line
+/// are made absolute before they fill a template, so they stay relative to
+/// the working directory.
+pub fn resolve_images(config: &mut Config, file: &str) {
+    let dir = Path::new(file).parent().unwrap_or(Path::new(""));
+    for (_, src) in config.styles.image_sources_mut() {
+        if !src.is_empty() && policy::scheme(src).is_none() && Path::new(src).is_relative() {
+            *src = dir.join(&*src).to_string_lossy().into_owned();
+        }
+    }
+}
+
+/// The file that set each value of a merged config.
+#[derive(Debug, Clone, Default)]
+pub struct Provenance {
+    /// The files, in merge order.
+    files: Vec<String>,
+    /// `styles.<id>.<field>` or `layers.<id>` -> index of the file.
+    values: BTreeMap<String, usize>,
+}
+
+impl Provenance {
+    /// Record the values set by `config`, read from `file`.
+    ///
+    /// The files must be recorded in merge order.
+    pub fn record(&mut self, file: &str, config: &Config) {
+        let index = self.files.len();
+        self.files.push(file.to_owned());
+
+        for (id, style) in config.styles.iter() {
+            for field in style.set_fields() {
+                self.values
+                    .insert(format!("styles.{}.{}", id, field), index);
+            }
+        }
+        for layer in config.layers.iter().flat_map(|layers| layers.iter()) {
+            self.values.insert(format!("layers.{}", layer.id()), index);
+        }
+    }
+
+    /// Record a single value set by `origin`, at the dotted `path` of a
+    /// config. Only the fields of styles are recorded.
+    pub fn record_path(&mut self, origin: &str, path: &str) {
+        let segments: Vec<_> = path.splitn(4, '.').collect();
+        let ["styles", id, field, ..] = segments[..] else {
+            return;
+        };
+
+        if self.files.last().map(String::as_str) != Some(origin) {
+            self.files.push(origin.to_owned());
+        }
+        let index = self.files.len() - 1;
+        self.values
+            .insert(format!("styles.{}.{}", id, field), index);
+    }
+
+    /// The last merged file that set a field of the style.
+    pub fn style_file(&self, id: &str) -> Option<&str> {
+        let prefix = format!("styles.{}.", id);
+        self.values
+            .range(prefix.clone()..)
+            .take_while(|(path, _)| path.starts_with(&prefix))
+            .map(|(_, index)| *index)
+            .max()
+            .map(|index| self.files[index].as_str())
+    }
+
+    /// The file that set the layer.
+    pub fn layer_file(&self, id: &str) -> Option<&str> {
+        self.values
+            .get(&format!("layers.{}", id))
+            .map(|index| self.files[*index].as_str())
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.values.keys().map(String::len).max().unwrap_or(0);
+        for (path, index) in &self.values {
+            writeln!(f, "{:width$}  {}", path, self.files[*index], width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// Merge the configs, in order, and record where each value comes from.
+///
+/// The first config that sets `layers` replaces the default layers.
+pub fn merge(configs: impl IntoIterator<Item = (String, Config)>) -> (Config, Provenance) {
+    let mut merged = Config::default();
+    let mut provenance = Provenance::default();
+
+    for (file, config) in configs {
+        provenance.record(&file, &config);
+        if merged.layers.is_none() && config.layers.is_some() {
+            merged.styles.merge(config.styles);
+            merged.layers = config.layers;
+        } else {
+            merged.merge(config);
+        }
+    }
+
+    (merged, provenance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Scheme;
+
+    fn write(dir: &Path, name: &str, text: &str) -> String {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// A fresh directory for a test, the process id keeps concurrent test
+    /// runs apart.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn includes() {
+        let dir = temp_dir("footlights-load-includes");
+        write(
+            &dir,
+            "shared/base.yaml",
+            "styles:\n  img: { image: a.png, round: 20 }\n  bg: { color: !Pure white }\n",
+        );
+        write(
+            &dir,
+            "shared/theme.yaml",
+            "include: [base.yaml]\nstyles:\n  bg: { color: !Pure black }\n",
+        );
+        let project = write(
+            &dir,
+            "project.yaml",
+            "include: [shared/theme.yaml, shared/base.yaml]\nstyles:\n  img: { round: 5 }\n",
+        );
+        let local = write(&dir, "local.yaml", "styles:\n  img: { image: b.png }\n");
+
+        let sources =
+            read_sources(&[project.clone(), local.clone()], None, &Policy::default()).unwrap();
+        let files: Vec<_> = sources
+            .iter()
+            .map(|source| Path::new(&source.file).file_name().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            files,
+            ["base.yaml", "theme.yaml", "project.yaml", "local.yaml"]
+        );
+
+        let (config, provenance) = merge(sources.iter().map(|source| {
+            (
+                source.file.clone(),
+                serde_yaml::from_str(&source.text).unwrap(),
+            )
+        }));
+        assert!(config.check().is_empty());
+        assert_eq!(provenance.style_file("img"), Some(local.as_str()));
+        assert_eq!(provenance.style_file("bg"), Some(sources[1].file.as_str()));
+        assert_eq!(provenance.layer_file("bg"), None);
+
+        let table = provenance.to_string();
+        assert!(table.contains(&format!("styles.img.round  {}", project)));
+    }
+
+    #[test]
+    fn merge_layers() {
+        let parse = |text: &str| serde_yaml::from_str::<Config>(text).unwrap();
+        let layers = "layers:\n  - { ty: Background, id: bg, style: bg }\n";
+        let logo = "layers:\n  - { ty: Image, id: logo, style: logo }\n";
+
+        let ids = |configs: Vec<(&str, &str)>| {
+            let configs = configs
+                .into_iter()
+                .map(|(file, text)| (file.to_owned(), parse(text)));
+            let (config, _) = merge(configs);
+            let ids: Vec<_> = config
+                .structure()
+                .iter()
+                .map(|l| l.id().to_owned())
+                .collect();
+            ids
+        };
+
+        // Without layers, the default layers are used.
+        assert_eq!(ids(vec![("a.yaml", "styles: {}\n")]), ["bg", "img"]);
+
+        // The first config that sets layers replaces the default layers, even
+        // after configs without layers, and later layers are merged over it.
+        assert_eq!(ids(vec![("a.yaml", layers)]), ["bg"]);
+        assert_eq!(
+            ids(vec![("a.yaml", "styles: {}\n"), ("b.yaml", logo)]),
+            ["logo"]
+        );
+        assert_eq!(
+            ids(vec![
+                ("a.yaml", "styles: {}\n"),
+                ("b.yaml", layers),
+                ("c.yaml", logo)
+            ]),
+            ["bg", "logo"]
+        );
+    }
+
+    #[test]
+    fn include_without_layers() {
+        let dir = temp_dir("footlights-load-include-layers");
+        write(&dir, "base.yaml", "styles:\n  bg: { color: !Pure white }\n");
+        let only = write(
+            &dir,
+            "only.yaml",
+            "include: [base.yaml]\nstyles:\n  logo: { image: a.png }\nlayers:\n  - { ty: Image, id: logo, style: logo }\n",
+        );
+
+        // The included config sets no layers, so the layers of the including
+        // config replace the default layers.
+        let sources = read_sources(&[only], None, &Policy::default()).unwrap();
+        let (config, _) = merge(sources.iter().map(|source| {
+            (
+                source.file.clone(),
+                serde_yaml::from_str(&source.text).unwrap(),
+            )
+        }));
+        let structure = config.structure();
+        let ids: Vec<_> = structure.iter().map(|l| l.id()).collect();
+        assert_eq!(ids, ["logo"]);
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = temp_dir("footlights-load-cycle");
+        let a = write(&dir, "a.yaml", "include: [b.yaml]\nstyles: {}\n");
+        write(&dir, "b.yaml", "include: [a.yaml]\nstyles: {}\n");
+
+        let error = read_sources(&[a], None, &Policy::default())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Configs include each other in a cycle:"),
+            "{}",
+            error
+        );
+        assert!(error.ends_with("a.yaml."), "{}", error);
+
+        let error = read_sources(
+            &[dir.join("missing.yaml").to_string_lossy().into_owned()],
+            None,
+            &Policy::default(),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("missing.yaml"), "{}", error);
+    }
+
+    #[test]
+    fn resolve_image_paths() {
+        let mut config: Config = serde_yaml::from_str(
+            "styles:\n  a: { image: logo.png }\n  b: { image: ../shots/a.png }\n  c: { image: /tmp/a.png }\n  d: { image: 'https://example.com/a.png' }\n  e: { image: 'data:image/png;base64,' }\n",
+        )
+        .unwrap();
+        resolve_images(&mut config, "configs/project.yaml");
+
+        let sources: BTreeMap<_, _> = config.styles.image_sources().collect();
+        assert_eq!(
+            sources,
+            BTreeMap::from([
+                ("a", "configs/logo.png"),
+                ("b", "configs/../shots/a.png"),
+                ("c", "/tmp/a.png"),
+                ("d", "https://example.com/a.png"),
+                ("e", "data:image/png;base64,"),
+            ])
+        );
+
+        // A config in the working directory.
+        let mut config: Config =
+            serde_yaml::from_str("styles:\n  a: { image: logo.png }\n").unwrap();
+        resolve_images(&mut config, "project.yaml");
+        assert_eq!(
+            config.styles.image_sources().next(),
+            Some(("a", "logo.png"))
+        );
+    }
+
+    #[test]
+    fn include_policy() {
+        let dir = temp_dir("footlights-load-policy");
+        write(&dir, "secret.yaml", "styles: {}\n");
+        let config = write(
+            &dir,
+            "root/config.yaml",
+            "include: [../secret.yaml]\nstyles: {}\n",
+        );
+
+        let policy = Policy::new(Some(&dir.join("root")), vec![Scheme::File], vec![]).unwrap();
+        let error = read_sources(&[config], None, &policy).unwrap_err();
+        assert!(
+            format!("{:#}", error).contains("is outside of the root directory"),
+            "{:#}",
+            error
+        );
+    }
+}
@@ -1,24 +1,150 @@
-use base64::Engine;
-use clap::Parser;
-use image::io::Reader;
-use std::{io::Cursor, path::Path};
-
-use anyhow::Result;
-use footlights_engine::configs::{
-    structure::{ImageSizeProvider, Structure},
-    style::StyleCollection,
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use footlights_engine::{
+    configs::{
+        style::{Style, StyleCollection},
+        Config, ConfigError,
+    },
+    limits::{LimitError, Limits},
+    presets,
+    render::{
+        raster::{fontdb, system_fonts, tiny_skia::Pixmap, Raster, RasterOptions},
+        Svg,
+    },
+    resources::Resources,
+    Canvas,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-mod svg_render;
+mod batch;
+mod fetch;
+mod format;
+mod glob;
+mod input;
+mod load;
+mod output;
+mod overrides;
+mod policy;
+mod quick;
+mod serve;
+mod suggest;
+mod template;
+mod validate;
+mod watch;
+mod webp;
 
-use tokio::io::{stdin, AsyncRead, AsyncReadExt, AsyncWriteExt};
+use fetch::FetchOptions;
+use format::Format;
+use input::InputImage;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct UserInput {
-    #[arg(short, long)]
-    config: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check config files and report every problem with its location.
+    Validate {
+        /// The config files to check.
+        #[arg(required = true)]
+        configs: Vec<String>,
+
+        /// The format of the configs, detected from the extensions by default.
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+
+        #[command(flatten)]
+        template: TemplateArgs,
+    },
+    /// Print the JSON Schema of the config format.
+    Schema,
+    /// Print the styles of the merged configs, with inheritance resolved.
+    Styles {
+        /// The config files, later files override earlier ones.
+        #[arg(required = true)]
+        configs: Vec<String>,
+
+        /// The format of the configs, detected from the extensions by default.
+        #[arg(long, value_enum)]
+        format: Option<Format>,
+
+        #[command(flatten)]
+        template: TemplateArgs,
+    },
+    /// List and show the built-in presets.
+    Presets {
+        #[command(subcommand)]
+        command: PresetsCommand,
+    },
+    /// Render many images with one config, in parallel.
+    ///
+    /// The config and the fonts are loaded once. A failed image does not stop the others,
+    /// the failures are listed at the end.
+    Batch(Box<BatchArgs>),
+    /// Serve renders over HTTP.
+    ///
+    /// POST /render takes the image as the body, with the query parameters format (png, webp
+    /// or svg), preset (can be repeated), config (a JSON config) and style (the JSON of the
+    /// "img" style). GET /health checks that the service is up. Errors are JSON objects.
+    Serve(Box<ServeArgs>),
+    /// Convert a config between YAML, TOML and JSON.
+    ///
+    /// Includes are kept as they are, not merged.
+    Convert {
+        /// The config file to convert.
+        input: String,
+
+        /// The converted config file, printed to stdout by default.
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// The format of the input, detected from the extension by default.
+        #[arg(long, value_enum)]
+        from: Option<Format>,
+
+        /// The format of the output, detected from the extension of the output by default.
+        #[arg(long, value_enum)]
+        to: Option<Format>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PresetsCommand {
+    /// List the presets with their kind and description.
+    List,
+    /// Print the config of a preset.
+    Show {
+        /// The name of the preset.
+        name: String,
+    },
+}
+
+// The arguments of the default command, which renders an image.
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    configs: ConfigArgs,
 
+    /// The rendered image, the extension picks the format: .png or .webp.
     #[arg(short, long)]
     output: Option<String>,
 
@@ -30,81 +156,601 @@ pub struct UserInput {
     #[arg(long)]
     stdout: bool,
 
-    #[arg(short, long)]
-    image: Option<String>,
+    /// The input images, as name=path for the "{name}" placeholders of the config, can be
+    /// repeated. A path alone fills "{image}".
+    #[arg(short, long, value_name = "[NAME=]PATH")]
+    image: Vec<String>,
 
     #[arg(long)]
     debug: bool,
+
+    /// Render again each time the configs, their includes or the images change.
+    #[arg(long, requires = "output", conflicts_with_all = ["stdin", "stdout"])]
+    watch: bool,
+
+    #[command(flatten)]
+    resources: ResourceArgs,
+}
+
+// The arguments of `fl batch`.
+#[derive(Args, Debug)]
+pub struct BatchArgs {
+    /// The input images: files, directories of images or globs like 'shots/**/*.png'.
+    #[arg(required = true)]
+    inputs: Vec<String>,
+
+    /// The directory of the rendered images. The directories of the inputs below the
+    /// patterns are kept.
+    #[arg(long, value_name = "DIR")]
+    out_dir: String,
+
+    /// The file name of each rendered image, with the placeholders {stem} and {name} of the
+    /// input and {ext} of the output. The extension picks the format, .png or .webp.
+    #[arg(long, default_value = "{stem}-fl.{ext}")]
+    name: String,
+
+    /// The number of images rendered at once, the number of CPUs by default.
+    #[arg(short, long)]
+    jobs: Option<usize>,
+
+    #[command(flatten)]
+    configs: ConfigArgs,
+
+    #[command(flatten)]
+    resources: ResourceArgs,
+}
+
+// The arguments of `fl serve`.
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// The address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// The largest request body, in bytes.
+    #[arg(long, default_value_t = 32 * 1024 * 1024)]
+    max_body: usize,
+
+    /// The number of renders at once, the number of CPUs by default. Further requests are
+    /// refused with 503.
+    #[arg(long)]
+    max_concurrent: Option<usize>,
+
+    /// The time to receive a request, in seconds.
+    #[arg(long, default_value_t = 30)]
+    read_timeout: u64,
+
+    /// The configs of every render. The presets of a request are merged under them, its
+    /// config and style over them.
+    #[command(flatten)]
+    configs: ConfigArgs,
+
+    #[command(flatten)]
+    resources: ResourceArgs,
+}
+
+// The configs of a render, and the flags that override them.
+#[derive(Args, Debug)]
+pub struct ConfigArgs {
+    /// The config files, can be repeated. Later files override earlier ones.
+    ///
+    /// Without configs and presets, the "basic" preset is used.
+    #[arg(short = 'c', long = "config")]
+    files: Vec<String>,
+
+    /// The format of the configs, detected from the extensions by default.
+    #[arg(long, value_enum)]
+    format: Option<Format>,
+
+    /// Built-in presets to merge the configs over, can be repeated. See `fl presets list`.
+    #[arg(long)]
+    preset: Vec<String>,
+
+    #[command(flatten)]
+    template: TemplateArgs,
+
+    #[command(flatten)]
+    quick: quick::QuickArgs,
+
+    /// Override a field of the config, as a dotted path, can be repeated:
+    /// --set styles.img.round=30.
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    set: Vec<overrides::Override>,
+}
+
+// How the images and the includes of the configs are read.
+#[derive(Args, Debug)]
+pub struct ResourceArgs {
+    /// Only read local images and includes under this directory.
+    #[arg(long, value_name = "DIR")]
+    root: Option<String>,
+
+    /// The kinds of references that can be read, can be repeated. All of them by default,
+    /// file and data for `fl serve`.
+    #[arg(long = "allow", value_enum, value_name = "SCHEME")]
+    allow: Vec<policy::Scheme>,
+
+    /// Never read a reference that matches, can be repeated: a glob of paths, like '.ssh' or
+    /// '/etc', or a pattern of URL hosts, like '*.internal'.
+    #[arg(long = "deny", value_name = "PATTERN")]
+    deny: Vec<String>,
+
+    /// Timeout for downloading a remote image, in seconds.
+    #[arg(long, default_value_t = 30)]
+    http_timeout: u64,
+
+    /// The maximum size of a remote image, in bytes.
+    #[arg(long, default_value_t = 64 * 1024 * 1024)]
+    http_max_size: u64,
+
+    /// The maximum size of an input image file, in bytes.
+    #[arg(long, value_name = "BYTES", default_value_t = Limits::default().max_file_size)]
+    max_file_size: u64,
+
+    /// The maximum number of pixels of an input image, checked before it is decoded.
+    #[arg(long, value_name = "PIXELS", default_value_t = Limits::default().max_input_pixels)]
+    max_input_pixels: u64,
+
+    /// The maximum width and height of the canvas.
+    #[arg(long, value_name = "PIXELS", default_value_t = Limits::default().max_canvas_size)]
+    max_canvas_size: u32,
+
+    /// The maximum memory of the rendered image, in bytes.
+    #[arg(long, value_name = "BYTES", default_value_t = Limits::default().max_raster_memory)]
+    max_raster_memory: u64,
+}
+
+impl ResourceArgs {
+    /// The options of the images, with the access policy.
+    ///
+    /// A `restricted` policy, for `fl serve`, only reads local files and data
+    /// URLs by default, under the working directory.
+    fn options(&self, restricted: bool) -> Result<FetchOptions> {
+        let allow = match (self.allow.is_empty(), restricted) {
+            (false, _) => self.allow.clone(),
+            (true, true) => vec![policy::Scheme::File, policy::Scheme::Data],
+            (true, false) => policy::Scheme::value_variants().to_vec(),
+        };
+        let root = match (&self.root, restricted) {
+            (Some(root), _) => Some(PathBuf::from(root)),
+            (None, true) => Some(PathBuf::from(".")),
+            (None, false) => None,
+        };
+
+        Ok(FetchOptions {
+            timeout: Duration::from_secs(self.http_timeout),
+            max_size: self.http_max_size,
+            policy: policy::Policy::new(root.as_deref(), allow, self.deny.clone())?,
+            limits: self.limits(),
+            ..Default::default()
+        })
+    }
+
+    /// The limits of the images and of the render.
+    fn limits(&self) -> Limits {
+        Limits {
+            max_file_size: self.max_file_size,
+            max_input_pixels: self.max_input_pixels,
+            max_canvas_size: self.max_canvas_size,
+            max_raster_memory: self.max_raster_memory,
+        }
+    }
+}
+
+// The variables of the config templates.
+#[derive(Args, Debug)]
+pub struct TemplateArgs {
+    /// Set a template variable, can be repeated. The value is typed like YAML: 10 is a number.
+    #[arg(long = "var", value_name = "KEY=VALUE")]
+    vars: Vec<String>,
+
+    /// Read template variables from a JSON object, can be repeated.
+    #[arg(long = "vars", value_name = "FILE")]
+    var_files: Vec<String>,
 }
 
-struct CliImageSizeProvider;
-
-impl ImageSizeProvider for CliImageSizeProvider {
-    fn get_image_size(&self, src: &str) -> (u32, u32) {
-        if Path::new(src).exists() {
-            // case 1: src is a path to a file.
-            image::image_dimensions(src).unwrap()
-        } else if src.starts_with("http") {
-            // case 2: src is a url.
-            panic!("Not implemented yet. (case 2)");
-        } else if src.starts_with("data:image") {
-            // Case 3: `src` is a base64 string.
-            let src = src.split(',').last().unwrap();
-            let bytes = base64::engine::general_purpose::STANDARD_NO_PAD
-                .decode(src.as_bytes())
-                .unwrap();
-
-            let img = Reader::new(Cursor::new(&bytes))
-                .with_guessed_format()
-                .unwrap()
-                .decode()
-                .unwrap();
-            (img.width(), img.height())
+impl TemplateArgs {
+    /// The variables, `--var` overrides the files.
+    fn vars(&self) -> Result<template::Vars> {
+        let mut vars = template::Vars::default();
+        for file in &self.var_files {
+            vars.insert_file(file)?;
+        }
+        for arg in &self.vars {
+            vars.insert_arg(arg)?;
+        }
+        Ok(vars)
+    }
+}
+
+/// Load every image referenced by the styles into the resources.
+///
+/// Local files, remote URLs and data URLs are decoded into a format that
+/// the renderer supports, and their sources are replaced by their handles,
+/// so that each image is read (or downloaded) only once. Handles are kept
+/// as is.
+fn load_images(
+    styles: &mut StyleCollection,
+    resources: &mut Resources,
+    fetch_options: &FetchOptions,
+) -> Result<()> {
+    // Original source -> handle.
+    let mut rewritten: HashMap<String, String> = HashMap::new();
+
+    for (id, src) in styles.image_sources_mut() {
+        if Resources::is_handle(src) {
+            continue;
+        }
+        if let Some(handle) = rewritten.get(src.as_str()) {
+            *src = handle.clone();
+            continue;
+        }
+        let local = fetch_options
+            .policy
+            .check(src)
+            .with_context(|| format!("The image of style \"{}\" is refused", id))?;
+
+        let image = if let Some(path) = local {
+            InputImage::open(path, &fetch_options.limits).map_err(anyhow::Error::from)
+        } else if src.starts_with("data:") {
+            InputImage::from_data_url(src, &fetch_options.limits).map_err(anyhow::Error::from)
         } else {
-            panic!("Invalid image source: {}", src);
+            // The policy only allows http(s) URLs besides.
+            fetch::fetch(src, fetch_options)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(InputImage::from_bytes(bytes, &fetch_options.limits)?))
         }
+        .with_context(|| format!("Failed to load the image of style \"{}\"", id))?;
+
+        let handle = resources
+            .insert(image.mime_type(), image.bytes)
+            .with_context(|| format!("Failed to load the image of style \"{}\"", id))?;
+        rewritten.insert(std::mem::replace(src, handle.clone()), handle);
     }
+
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = UserInput::parse();
+/// Validate the config text, and print the problems.
+///
+/// A `partial` config is merged with other configs.
+/// Fails if the config has errors.
+fn check_config(
+    file: &str,
+    text: &str,
+    format: Format,
+    partial: bool,
+    vars: Option<&template::Vars>,
+) -> Result<()> {
+    let report = validate::validate_format(format, file, text, partial, vars);
+    eprint!("{}", report);
 
-    let mut tt = tinytemplate::TinyTemplate::new();
-    let mut map: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    if report.has_errors() {
+        bail!(
+            "{} error(s) found in \"{}\".",
+            report.count(validate::Severity::Error),
+            file
+        );
+    }
 
-    if args.stdin {
-        // 1. Try read data from stdin.
-        let mut buffer = Vec::new();
-        let mut stdin = tokio::io::stdin();
-        stdin.read_to_end(&mut buffer).await.unwrap();
-        // 2. Encode the image into data URLs.
-        let encoded = base64::engine::general_purpose::STANDARD_NO_PAD.encode(&buffer);
+    Ok(())
+}
+
+/// Apply the templates of a config.
+fn render_template(
+    file: &str,
+    text: &str,
+    format: Format,
+    vars: &template::Vars,
+) -> Result<String> {
+    let rendered = template::render(format, text, vars)
+        .map_err(|e| match e {
+            // An unset variable of an image field is a missing input image.
+            template::TemplateError::Unset { name, path, .. } if path.ends_with(".image") => {
+                anyhow!(
+                    "Image \"{}\" is not given, used at \"{}\". Pass it with -i {}=<path>.",
+                    name,
+                    path,
+                    name
+                )
+            }
+            e => e.into(),
+        })
+        .with_context(|| format!("Failed to apply the templates of config \"{}\".", file))?;
+    Ok(rendered.into_owned())
+}
+
+fn validate_configs(
+    configs: &[String],
+    format: Option<Format>,
+    template: &TemplateArgs,
+) -> Result<()> {
+    // The input images are not known, so their placeholders are kept.
+    let vars = template.vars()?.keep_unset();
+    let mut failed = 0;
+    for file in configs {
+        let format = format.unwrap_or_else(|| Format::from_path(file));
+        let result = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read config \"{}\".", file))
+            .and_then(|text| check_config(file, &text, format, false, Some(&vars)));
+
+        if let Err(e) = result {
+            eprintln!("{:#}", e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} config(s) are invalid.", failed, configs.len());
+    }
+
+    Ok(())
+}
+
+fn print_schema() -> Result<()> {
+    let schema = footlights_engine::schema::config_schema();
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+
+    Ok(())
+}
+
+/// The preset used without configs and presets.
+const DEFAULT_PRESET: &str = "basic";
+
+/// Get a built-in preset, suggesting the closest name if it does not exist.
+fn find_preset(name: &str) -> Result<&'static presets::Preset> {
+    presets::get(name).ok_or_else(|| {
+        let names = presets::iter().map(|preset| preset.name.as_str());
+        match suggest::closest(name, names) {
+            Some(closest) => anyhow!(
+                "Preset \"{}\" does not exist, did you mean \"{}\"?",
+                name,
+                closest
+            ),
+            None => anyhow!("Preset \"{}\" does not exist, see `fl presets list`.", name),
+        }
+    })
+}
+
+/// Read, validate and merge the presets, the config files and their includes.
+///
+/// The templates of each file are applied with `vars`, and printed with
+/// `debug`. The merged config is not checked, see [`check_merged`].
+fn load_config(
+    presets: &[String],
+    files: &[String],
+    format: Option<Format>,
+    policy: &policy::Policy,
+    vars: &template::Vars,
+    debug: bool,
+) -> Result<(Config, load::Provenance)> {
+    let sources = load::read_sources(files, format, policy)?;
+    let partial = presets.len() + sources.len() > 1;
+
+    let mut configs = Vec::new();
+    // The presets set no layers, so the layers of the configs still replace
+    // the default layers, see `load::merge`.
+    for name in presets {
+        let preset = find_preset(name)?;
+        configs.push((format!("preset:{}", name), preset.config().clone()));
+    }
+    for source in &sources {
+        let text = render_template(&source.file, &source.text, source.format, vars)?;
+        if debug {
+            eprintln!("# {}\n{}", source.file, text);
+        }
+        // The original text is validated, so the problems point into the file.
+        check_config(
+            &source.file,
+            &source.text,
+            source.format,
+            partial,
+            Some(vars),
+        )?;
+
+        let mut config = source
+            .format
+            .parse(&text)
+            .with_context(|| format!("Failed to parse config \"{}\".", source.file))?;
+        load::resolve_images(&mut config, &source.file);
+        configs.push((source.file.clone(), config));
+    }
+
+    Ok(load::merge(configs))
+}
+
+/// Check the merged config, and print the errors with the file to blame.
+fn check_merged(config: &Config, provenance: &load::Provenance) -> Result<()> {
+    let errors = merged_errors(config, provenance);
+    for error in &errors {
+        eprintln!("{}", error);
+    }
+
+    if !errors.is_empty() {
+        bail!("{} error(s) found in the merged config.", errors.len());
+    }
+
+    Ok(())
+}
+
+/// The errors of the merged config, with the file to blame.
+fn merged_errors(config: &Config, provenance: &load::Provenance) -> Vec<String> {
+    let errors = config.check();
+    let mut messages = Vec::new();
+    for error in &errors {
+        let file = match error {
+            ConfigError::UnknownStyle(path) => provenance.layer_file(&path.layer),
+            ConfigError::DuplicateLayerId(id) => provenance.layer_file(id),
+            ConfigError::UnknownParent { style, .. }
+            | ConfigError::UnknownPreset { style, .. }
+            | ConfigError::CssConflict { style, .. } => provenance.style_file(style),
+            ConfigError::InheritanceCycle(cycle) => provenance.style_file(&cycle[0]),
+            error => error
+                .path()
+                .and_then(|path| provenance.style_file(&path.style)),
+        };
+        messages.push(format!(
+            "{}: error: {}",
+            file.unwrap_or("<merged config>"),
+            error
+        ));
+    }
+
+    messages
+}
+
+fn print_styles(files: &[String], format: Option<Format>, template: &TemplateArgs) -> Result<()> {
+    let vars = template.vars()?.keep_unset();
+    let policy = policy::Policy::default();
+    let (config, provenance) = load_config(&[], files, format, &policy, &vars, false)?;
+    check_merged(&config, &provenance)?;
+    let styles = config.styles.resolve()?;
+    print!("{}", serde_yaml::to_string(&styles)?);
+
+    Ok(())
+}
+
+fn print_presets(command: PresetsCommand) -> Result<()> {
+    match command {
+        PresetsCommand::List => {
+            let width = presets::iter()
+                .map(|preset| preset.name.len())
+                .max()
+                .unwrap_or(0);
+            for preset in presets::iter() {
+                println!(
+                    "{:width$}  {:8}  {}",
+                    preset.name,
+                    preset.kind.to_string(),
+                    preset.description,
+                    width = width
+                );
+            }
+        }
+        PresetsCommand::Show { name } => {
+            let preset = find_preset(&name)?;
+            println!("# {}: {}", preset.kind, preset.description);
+            print!("{}", serde_yaml::to_string(preset.config())?);
+        }
+    }
+
+    Ok(())
+}
+
+fn convert(
+    input: &str,
+    output: Option<&str>,
+    from: Option<Format>,
+    to: Option<Format>,
+) -> Result<()> {
+    let from = from.unwrap_or_else(|| Format::from_path(input));
+    let to = match (to, output) {
+        (Some(to), _) => to,
+        (None, Some(output)) => Format::from_path(output),
+        (None, None) => bail!("The output format is required, set it with --to."),
+    };
+
+    let text = std::fs::read_to_string(input)
+        .with_context(|| format!("Failed to read config \"{}\".", input))?;
+    let converted = match from.parse_value(&text) {
+        // The placeholders may not have the type of their field until the
+        // templates are applied, so the values are converted as they are.
+        Ok(value) if template::has_templates(&value) => to.value_to_string(&value)?,
+        _ => {
+            // Overrides and includes are converted too, they need not render alone.
+            check_config(input, &text, from, true, None)?;
+            to.to_string(&from.parse(&text)?)?
+        }
+    };
 
-        let data_url = format!("data:image/png;base64,{}", encoded);
-        map.insert("image".to_string(), data_url);
-    } else if let Some(image) = args.image {
-        map.insert("image".to_string(), image);
+    match output {
+        Some(output) => std::fs::write(output, converted)
+            .with_context(|| format!("Failed to write config \"{}\".", output))?,
+        None => print!("{}", converted),
     }
 
-    // read yaml file from args[1]
-    let yaml = std::fs::read_to_string(args.config)?;
+    Ok(())
+}
 
-    tt.add_template("style_collections", &yaml)?;
-    let rendered_yaml = tt.render("style_collections", &map)?;
+#[tokio::main]
+async fn main() -> Result<()> {
+    let input = UserInput::parse();
 
-    if args.debug {
-        eprintln!("{}", rendered_yaml);
+    match input.command {
+        Some(Command::Validate {
+            configs,
+            format,
+            template,
+        }) => validate_configs(&configs, format, &template),
+        Some(Command::Schema) => print_schema(),
+        Some(Command::Styles {
+            configs,
+            format,
+            template,
+        }) => print_styles(&configs, format, &template),
+        Some(Command::Presets { command }) => print_presets(command),
+        Some(Command::Convert {
+            input,
+            output,
+            from,
+            to,
+        }) => convert(&input, output.as_deref(), from, to),
+        Some(Command::Batch(args)) => batch(*args),
+        Some(Command::Serve(args)) => serve(*args).await,
+        None => render(input.render).await,
     }
+}
 
-    let styles: StyleCollection = serde_yaml::from_str(&rendered_yaml)?;
-    let structure = Structure::default();
+async fn render(args: RenderArgs) -> Result<()> {
+    let mut resources = Resources::default();
+    // An unsupported output fails before the render.
+    let output_format = args
+        .output
+        .as_deref()
+        .map(|output| output::ImageFormat::from_path(Path::new(output)))
+        .transpose()?;
+
+    // Slot name -> source of the image.
+    let mut slots = BTreeMap::new();
+    for arg in &args.image {
+        let (name, source) = input::parse_slot(arg);
+        if slots.insert(name.to_owned(), source.to_owned()).is_some() {
+            bail!("Image \"{}\" is given more than once.", name);
+        }
+    }
 
-    let canvas = structure.build_canvas(&styles, CliImageSizeProvider {})?;
+    if args.stdin {
+        // 1. Try read data from stdin, one byte more than the limit tells
+        // that it is too large.
+        let limits = args.resources.limits();
+        let mut buffer = Vec::new();
+        let mut stdin = tokio::io::stdin().take(limits.max_file_size + 1);
+        stdin.read_to_end(&mut buffer).await?;
+        // 2. Add the image to the resources.
+        let image =
+            InputImage::from_bytes(buffer, &limits).context("Failed to read image from stdin.")?;
+        let handle = resources
+            .insert(image.mime_type(), image.bytes)
+            .context("Failed to read image from stdin.")?;
 
-    let svg_string = canvas.to_svg_string()?;
+        if slots
+            .insert(input::DEFAULT_SLOT.to_owned(), handle)
+            .is_some()
+        {
+            bail!("Image \"image\" is given by both -i and --stdin.");
+        }
+    }
 
-    let pixmap = svg_render::svg_string_to_pixmap(&svg_string)?;
+    let fontdb = system_fonts();
+    if args.watch {
+        watch(
+            &args,
+            &slots,
+            &fontdb,
+            output_format.unwrap_or(output::ImageFormat::Png),
+        );
+    }
+    let pixmap = render_slots(&args, &slots, &mut resources, &fontdb, None)?;
 
     // Output data to stdout.
     if args.stdout {
@@ -113,9 +759,604 @@ async fn main() -> Result<()> {
     }
 
     // Write data to a file.
-    if let Some(output) = args.output {
-        pixmap.save_png(&output).unwrap();
+    if let (Some(output), Some(format)) = (args.output, output_format) {
+        std::fs::write(&output, format.encode(&pixmap)?)
+            .with_context(|| format!("Failed to write \"{}\".", output))?;
+    }
+
+    Ok(())
+}
+
+/// Render the configs, with the images of the slots.
+///
+/// The local files that the render reads are added to `files`, for
+/// `--watch`, also when it fails.
+fn render_slots(
+    args: &RenderArgs,
+    slots: &BTreeMap<String, String>,
+    resources: &mut Resources,
+    fontdb: &fontdb::Database,
+    mut files: Option<&mut BTreeSet<PathBuf>>,
+) -> Result<Pixmap> {
+    let fetch_options = args.resources.options(false)?;
+    let policy = &fetch_options.policy;
+    let is_local = |src: &str| {
+        !src.starts_with("data:") && !fetch::is_remote(src) && !Resources::is_handle(src)
+    };
+    if let Some(files) = files.as_deref_mut() {
+        files.extend(args.configs.template.var_files.iter().map(PathBuf::from));
+        files.extend(args.configs.files.iter().map(PathBuf::from));
+        // The includes, if they can be read.
+        let sources = load::read_sources(&args.configs.files, args.configs.format, policy);
+        if let Ok(sources) = sources {
+            files.extend(sources.into_iter().map(|source| PathBuf::from(source.file)));
+        }
+        files.extend(
+            slots
+                .values()
+                .filter(|src| is_local(src))
+                .map(PathBuf::from),
+        );
+    }
+
+    let mut vars = args.configs.template.vars()?;
+    for (name, source) in slots {
+        // Relative paths of configs are relative to the configs, see
+        // `load::resolve_images`, the images of the command line are made
+        // absolute to stay relative to the working directory.
+        let source = match policy::scheme(source) {
+            None => std::path::absolute(source).map_or_else(
+                |_| source.clone(),
+                |path| path.to_string_lossy().into_owned(),
+            ),
+            Some(_) => source.clone(),
+        };
+        vars.insert(name.clone(), source.into());
+    }
+
+    let default_image = slots.get(input::DEFAULT_SLOT).map(String::as_str);
+    let (config, default_used) =
+        prepare_config(&args.configs, &vars, default_image, policy, args.debug)?;
+
+    let used = |name: &str| vars.is_used(name) || name == input::DEFAULT_SLOT && default_used;
+    for name in slots.keys().filter(|name| !used(name)) {
+        eprintln!("warning: Image \"{}\" is not used by the config.", name);
+    }
+    if let Some(files) = files {
+        let images = config.styles.image_sources().map(|(_, src)| src);
+        files.extend(images.filter(|src| is_local(src)).map(PathBuf::from));
+    }
+
+    render_pixmap(config, resources, &fetch_options, fontdb)
+}
+
+/// Render to the output each time a file of the render changes.
+///
+/// The errors are printed, and the previous image is kept.
+fn watch(
+    args: &RenderArgs,
+    slots: &BTreeMap<String, String>,
+    fontdb: &fontdb::Database,
+    format: output::ImageFormat,
+) -> ! {
+    let output = args.output.as_deref().expect("--watch requires --output");
+    let mut watcher = watch::Watcher::default();
+
+    loop {
+        let start = Instant::now();
+        let mut files = BTreeSet::new();
+        let result = render_slots(
+            args,
+            slots,
+            &mut Resources::default(),
+            fontdb,
+            Some(&mut files),
+        )
+        .and_then(|pixmap| {
+            std::fs::write(output, format.encode(&pixmap)?)
+                .with_context(|| format!("Failed to write \"{}\".", output))
+        });
+        match result {
+            Ok(()) => eprintln!(
+                "Rendered \"{}\" in {} ms.",
+                output,
+                start.elapsed().as_millis()
+            ),
+            Err(e) => eprintln!("error: {:#}", e),
+        }
+
+        watcher.watch(files);
+        eprintln!("Watching {} file(s), press Ctrl-C to stop.", watcher.len());
+        let changed = watcher.wait();
+        let names: Vec<_> = changed.iter().map(|path| path.to_string_lossy()).collect();
+        eprintln!("\nChanged: {}", names.join(", "));
+    }
+}
+
+/// Load the presets and the configs, and apply the flags over them.
+///
+/// The `img` style shows `default_image`, unless the configs set an image.
+/// Returns the config, and whether it shows `default_image`.
+fn prepare_config(
+    args: &ConfigArgs,
+    vars: &template::Vars,
+    default_image: Option<&str>,
+    policy: &policy::Policy,
+    debug: bool,
+) -> Result<(Config, bool)> {
+    let (mut config, mut provenance) = load_with_flags(args, &[], vars, policy, debug)?;
+    let default_used = use_default_image(&mut config, &mut provenance, default_image)?;
+    check_merged(&config, &provenance)?;
+
+    if debug {
+        eprintln!("# Provenance\n{}", provenance);
+    }
+
+    Ok((config, default_used))
+}
+
+/// Load the presets, the `presets` of a request and the configs, and apply
+/// the flags over them. The merged config is not checked.
+fn load_with_flags(
+    args: &ConfigArgs,
+    presets: &[String],
+    vars: &template::Vars,
+    policy: &policy::Policy,
+    debug: bool,
+) -> Result<(Config, load::Provenance)> {
+    let presets = if args.files.is_empty() && args.preset.is_empty() && presets.is_empty() {
+        vec![DEFAULT_PRESET.to_owned()]
+    } else {
+        [&args.preset, presets].concat()
+    };
+    let (mut config, mut provenance) =
+        load_config(&presets, &args.files, args.format, policy, vars, debug)?;
+
+    // The flags override the configs.
+    let flags = Config {
+        styles: args.quick.styles()?,
+        ..Default::default()
+    };
+    provenance.record("<command line>", &flags);
+    config.merge(flags);
+    if !args.set.is_empty() {
+        config = overrides::apply(&config, &args.set)?;
+        for o in &args.set {
+            provenance.record_path("<--set>", &o.path());
+        }
+    }
+
+    Ok((config, provenance))
+}
+
+/// Show `default_image` in the `img` style, unless the config sets an image.
+///
+/// Returns whether the default image is used.
+fn use_default_image(
+    config: &mut Config,
+    provenance: &mut load::Provenance,
+    default_image: Option<&str>,
+) -> Result<bool> {
+    let needs_image = config
+        .structure()
+        .iter()
+        .any(|layer| layer.style() == "img")
+        && config.styles.resolve().is_ok_and(|styles| {
+            styles
+                .get("img")
+                .is_none_or(|style| !style.set_fields().contains(&"image"))
+        });
+    match default_image {
+        Some(image) if needs_image => {
+            let mut styles = StyleCollection::default();
+            styles.add("img".to_owned(), Style::default().with_image(image));
+            let image = Config {
+                styles,
+                ..Default::default()
+            };
+            provenance.record("<command line>", &image);
+            config.merge(image);
+        }
+        None if needs_image => {
+            bail!("An input image is required, pass it with -i or --stdin.")
+        }
+        _ => {}
+    }
+
+    Ok(needs_image && default_image.is_some())
+}
+
+/// Load the images of the config, and render it.
+///
+/// The images are added to `resources`, see [`load_images`].
+fn render_pixmap(
+    config: Config,
+    resources: &mut Resources,
+    fetch_options: &FetchOptions,
+    fontdb: &fontdb::Database,
+) -> Result<Pixmap> {
+    let canvas = build_canvas(config, resources, fetch_options, false)?;
+
+    canvas.render::<Raster>(RasterOptions {
+        resources,
+        fontdb,
+        limits: fetch_options.limits,
+    })
+}
+
+/// Load the images of the config, and render it to SVG.
+///
+/// The SVG refers to the images by their handles in `resources`, unless
+/// they are `inline`d as data URLs, for SVG output.
+fn render_svg(
+    config: Config,
+    resources: &mut Resources,
+    fetch_options: &FetchOptions,
+    inline: bool,
+) -> Result<String> {
+    build_canvas(config, resources, fetch_options, inline)?.render::<Svg>(())
+}
+
+/// Load the images of the config, and build its canvas, within the limits.
+fn build_canvas(
+    mut config: Config,
+    resources: &mut Resources,
+    fetch_options: &FetchOptions,
+    inline: bool,
+) -> Result<Canvas> {
+    load_images(&mut config.styles, resources, fetch_options)?;
+    if inline {
+        resources.inline(&mut config.styles);
     }
 
+    let canvas = config.build_canvas(&*resources)?;
+    fetch_options.limits.check_canvas(canvas.size())?;
+
+    Ok(canvas)
+}
+
+async fn serve(args: ServeArgs) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(&args.listen)
+        .await
+        .with_context(|| format!("Failed to listen on \"{}\".", args.listen))?;
+    let limits = serve::Limits {
+        max_body: args.max_body,
+        max_concurrent: args.max_concurrent.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        }),
+        read_timeout: Duration::from_secs(args.read_timeout),
+    };
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+
+    let fetch_options = args.resources.options(true)?;
+    let fontdb = system_fonts();
+    let renderer = move |request| render_request(&args, &fetch_options, &fontdb, request);
+    serve::run(listener, limits, Arc::new(renderer)).await?;
+
     Ok(())
 }
+
+/// Render a request of `fl serve`.
+fn render_request(
+    args: &ServeArgs,
+    fetch_options: &FetchOptions,
+    fontdb: &fontdb::Database,
+    request: serve::RenderRequest,
+) -> Result<Vec<u8>, serve::HttpError> {
+    let invalid_config =
+        |e: anyhow::Error| serve::HttpError::new(422, "invalid_config", format!("{:#}", e));
+    // An image or a canvas over the limits is too large, rather than invalid.
+    let render_failed = |e: anyhow::Error| match e.chain().any(|e| e.is::<LimitError>()) {
+        true => serve::HttpError::new(413, "too_large", format!("{:#}", e)),
+        false => serve::HttpError::new(422, "render_failed", format!("{:#}", e)),
+    };
+
+    let invalid_image = |e: String| serve::HttpError::new(422, "invalid_image", e);
+
+    let image =
+        InputImage::from_bytes(request.image, &fetch_options.limits).map_err(|e| match e {
+            input::InputError::Limit(e) => serve::HttpError::new(413, "too_large", e.to_string()),
+            e => invalid_image(e.to_string()),
+        })?;
+    let mut resources = Resources::default();
+    let image = resources
+        .insert(image.mime_type(), image.bytes)
+        .map_err(|e| invalid_image(e.to_string()))?;
+    let mut vars = args.configs.template.vars().map_err(invalid_config)?;
+    vars.insert(input::DEFAULT_SLOT.to_owned(), image.clone().into());
+
+    let (mut config, mut provenance) = load_with_flags(
+        &args.configs,
+        &request.presets,
+        &vars,
+        &fetch_options.policy,
+        false,
+    )
+    .map_err(invalid_config)?;
+    if let Some(text) = &request.config {
+        let report = validate::validate_format(Format::Json, "config", text, true, None);
+        if report.has_errors() {
+            return Err(serve::HttpError::new(
+                422,
+                "invalid_config",
+                report.to_string().trim_end(),
+            ));
+        }
+        let request_config = Format::Json
+            .parse(text)
+            .map_err(|e| invalid_config(e.into()))?;
+        if !request_config.include.is_empty() {
+            return Err(serve::HttpError::new(
+                422,
+                "invalid_config",
+                "The config of a request cannot include other configs.",
+            ));
+        }
+        provenance.record("config", &request_config);
+        config.merge(request_config);
+    }
+    if let Some(text) = &request.style {
+        let style: Style = serde_json::from_str(text)
+            .map_err(|e| serve::HttpError::new(422, "invalid_style", e.to_string()))?;
+        let mut styles = StyleCollection::default();
+        styles.add("img".to_owned(), style);
+        let style = Config {
+            styles,
+            ..Default::default()
+        };
+        provenance.record("style", &style);
+        config.merge(style);
+    }
+    use_default_image(&mut config, &mut provenance, Some(&image)).map_err(invalid_config)?;
+    let errors = merged_errors(&config, &provenance);
+    if !errors.is_empty() {
+        return Err(serve::HttpError::new(
+            422,
+            "invalid_config",
+            errors.join("\n"),
+        ));
+    }
+
+    match request.format {
+        serve::OutputFormat::Svg => render_svg(config, &mut resources, fetch_options, true)
+            .map(String::into_bytes)
+            .map_err(render_failed),
+        format => {
+            let pixmap = render_pixmap(config, &mut resources, fetch_options, fontdb)
+                .map_err(render_failed)?;
+            match format {
+                serve::OutputFormat::Webp => {
+                    webp::encode(&pixmap).map_err(|e| render_failed(e.into()))
+                }
+                _ => pixmap.encode_png().map_err(|e| render_failed(e.into())),
+            }
+        }
+    }
+}
+
+/// The image source that stands for the input of each job of a batch.
+///
+/// It is a handle of no table, so that [`load_images`] keeps it while the
+/// other images are loaded.
+const BATCH_INPUT: &str = "resource:batch-input";
+
+fn batch(args: BatchArgs) -> Result<()> {
+    let start = Instant::now();
+    let jobs = batch::jobs(&args.inputs, Path::new(&args.out_dir), &args.name)?;
+    if jobs.is_empty() {
+        bail!("No input image found.");
+    }
+
+    // The config is loaded once, with a placeholder for the input image.
+    let fetch_options = args.resources.options(false)?;
+    let mut vars = args.configs.template.vars()?;
+    vars.insert(input::DEFAULT_SLOT.to_owned(), BATCH_INPUT.into());
+    let (mut config, default_used) = prepare_config(
+        &args.configs,
+        &vars,
+        Some(BATCH_INPUT),
+        &fetch_options.policy,
+        false,
+    )?;
+    if !default_used && !vars.is_used(input::DEFAULT_SLOT) {
+        eprintln!("warning: The config does not use the input images.");
+    }
+
+    // Load the other images of the config once.
+    let mut resources = Resources::default();
+    load_images(&mut config.styles, &mut resources, &fetch_options)?;
+    let fontdb = system_fonts();
+
+    let threads = args.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+    });
+    let failures = batch::run(&jobs, threads, |job| {
+        let mut config = config.clone();
+        let input = job.input.to_string_lossy();
+        for (_, src) in config.styles.image_sources_mut() {
+            if src.contains(BATCH_INPUT) {
+                *src = src.replace(BATCH_INPUT, &input);
+            }
+        }
+
+        let pixmap = render_pixmap(config, &mut resources.clone(), &fetch_options, &fontdb)?;
+        if let Some(dir) = job.output.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create \"{}\".", dir.display()))?;
+        }
+        let format = output::ImageFormat::from_path(&job.output)?;
+        std::fs::write(&job.output, format.encode(&pixmap)?)
+            .with_context(|| format!("Failed to write \"{}\".", job.output.display()))
+    });
+
+    eprint!(
+        "{}",
+        batch::Summary {
+            total: jobs.len(),
+            failures: &failures,
+            elapsed: start.elapsed(),
+        }
+    );
+    if !failures.is_empty() {
+        bail!("{} of {} image(s) failed.", failures.len(), jobs.len());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASIC: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../examples/basic.yaml");
+
+    fn no_vars() -> TemplateArgs {
+        TemplateArgs {
+            vars: Vec::new(),
+            var_files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn check_templated_configs() {
+        // The placeholders of the input images are not an error.
+        let files = [BASIC.to_owned()];
+        validate_configs(&files, None, &no_vars()).unwrap();
+        print_styles(&files, None, &no_vars()).unwrap();
+    }
+
+    #[test]
+    fn presets_keep_layers() {
+        let dir = std::env::temp_dir().join(format!(
+            "footlights-main-preset-layers-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let only = dir.join("only.yaml");
+        std::fs::write(
+            &only,
+            "styles:\n  bg: { color: !Pure red }\nlayers:\n  - { ty: Background, id: bg, style: bg }\n",
+        )
+        .unwrap();
+        let files = [only.to_string_lossy().into_owned()];
+
+        // A preset sets styles, the layers still come from the config.
+        let layers = |presets: &[String]| {
+            let policy = policy::Policy::default();
+            let vars = template::Vars::default();
+            let (config, _) = load_config(presets, &files, None, &policy, &vars, false).unwrap();
+            let structure = config.structure();
+            let ids: Vec<_> = structure.iter().map(|l| l.id().to_owned()).collect();
+            ids
+        };
+        assert_eq!(layers(&[]), ["bg"]);
+        assert_eq!(layers(&["peach".to_owned()]), ["bg"]);
+    }
+
+    #[test]
+    fn convert_partial() {
+        let dir =
+            std::env::temp_dir().join(format!("footlights-main-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let over = dir.join("over.yaml");
+        std::fs::write(&over, "styles:\n  img:\n    round: 5\n").unwrap();
+        let toml = dir.join("over.toml");
+
+        // An override is converted, although it does not render alone.
+        convert(
+            &over.to_string_lossy(),
+            Some(&toml.to_string_lossy()),
+            None,
+            None,
+        )
+        .unwrap();
+        let text = std::fs::read_to_string(&toml).unwrap();
+        assert_eq!(text, "[styles.img]\nround = 5\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn image_errors() {
+        let error = |options: &FetchOptions| {
+            let text = "styles:\n  img: { image: \"data:image/png;base64,AAAA\" }\n";
+            let mut config = Format::Yaml.parse(text).unwrap();
+            let e = load_images(&mut config.styles, &mut Resources::default(), options);
+            format!("{:#}", e.unwrap_err())
+        };
+
+        // The contexts are followed by their cause.
+        let e = error(&FetchOptions::default());
+        assert!(
+            e.starts_with("Failed to load the image of style \"img\": "),
+            "{}",
+            e
+        );
+        let options = FetchOptions {
+            policy: policy::Policy::new(None, vec![policy::Scheme::File], Vec::new()).unwrap(),
+            ..Default::default()
+        };
+        let e = error(&options);
+        assert!(
+            e.starts_with("The image of style \"img\" is refused: \"data:"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn convert_templated() {
+        let dir = std::env::temp_dir().join(format!(
+            "footlights-main-convert-templated-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let yaml = dir.join("config.yaml");
+        let text = r#"
+styles:
+  img:
+    image: "{image}"
+    round: "{round}"
+  bg:
+    color: !Pure "{bg|white}"
+    if big:
+      size: !FitContent 200
+"#;
+        std::fs::write(&yaml, text).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let original = Format::Yaml.parse_value(text).unwrap();
+
+        // The placeholders are kept, and the tags survive the round trip.
+        for name in ["config.toml", "config.json"] {
+            convert(&path("config.yaml"), Some(&path(name)), None, None).unwrap();
+            convert(
+                &path(name),
+                Some(&path("back.yaml")),
+                None,
+                Some(Format::Yaml),
+            )
+            .unwrap();
+            let back = std::fs::read_to_string(dir.join("back.yaml")).unwrap();
+            assert_eq!(
+                Format::Yaml.parse_value(&back).unwrap(),
+                original,
+                "{}",
+                back
+            );
+
+            // The converted config renders once the templates are applied.
+            let format = Format::from_path(name);
+            let converted = std::fs::read_to_string(dir.join(name)).unwrap();
+            let mut vars = template::Vars::default();
+            vars.insert_arg("round=5").unwrap();
+            vars.insert_arg("big=1").unwrap();
+            vars.insert_arg("image=a.png").unwrap();
+            let rendered = template::render(format, &converted, &vars).unwrap();
+            assert!(format.parse(&rendered).is_ok(), "{}", rendered);
+        }
+        let toml = std::fs::read_to_string(dir.join("config.toml")).unwrap();
+        assert!(toml.contains("round = \"{round}\""), "{}", toml);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
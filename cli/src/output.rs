@@ -0,0 +1,67 @@
+//! The raster formats of the rendered files, for `fl render -o` and
+//! `fl batch`.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use footlights_engine::render::raster::tiny_skia::Pixmap;
+
+use crate::webp;
+
+/// The format of a rendered file, chosen by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Webp,
+}
+
+impl ImageFormat {
+    /// The format of `path`: `.webp` is WebP, `.png` or no extension is PNG,
+    /// other extensions are an error.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+        match extension.as_deref() {
+            None | Some("png") => Ok(Self::Png),
+            Some("webp") => Ok(Self::Webp),
+            Some(extension) => bail!(
+                "Unsupported output \"{}\": unknown extension \".{}\", expected .png or .webp.",
+                path.display(),
+                extension
+            ),
+        }
+    }
+
+    /// Encode the pixmap in this format.
+    pub fn encode(self, pixmap: &Pixmap) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Png => pixmap.encode_png()?,
+            Self::Webp => webp::encode(pixmap)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_from_extensions() {
+        let format = |path: &str| ImageFormat::from_path(Path::new(path));
+        assert_eq!(format("out/a.png").unwrap(), ImageFormat::Png);
+        assert_eq!(format("out/a.PNG").unwrap(), ImageFormat::Png);
+        assert_eq!(format("out/a").unwrap(), ImageFormat::Png);
+        assert_eq!(format("out/a.webp").unwrap(), ImageFormat::Webp);
+        assert_eq!(
+            format("out/a.jpg").unwrap_err().to_string(),
+            "Unsupported output \"out/a.jpg\": unknown extension \".jpg\", expected .png or .webp."
+        );
+
+        let pixmap = Pixmap::new(3, 2).unwrap();
+        let png = ImageFormat::Png.encode(&pixmap).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+        let webp = ImageFormat::Webp.encode(&pixmap).unwrap();
+        assert_eq!(&webp[8..12], b"WEBP");
+    }
+}
@@ -0,0 +1,395 @@
+//! `--set path=value` overrides of single fields of the config.
+//!
+//! The path is checked against the JSON Schema of the config, so that a
+//! misspelled key is reported with the closest valid key, and the value is
+//! parsed as the type of the field: `--set styles.img.image=10` is a string,
+//! `--set styles.img.round=10` a number. Other values are parsed as YAML, so
+//! `--set 'styles.bg.color=!Pure red'` sets a whole color.
+//!
+//! Enum variants are keys of the path, as in TOML and JSON configs:
+//! `styles.bg.color.Linear.degree`. Layers are indexed by position:
+//! `layers.0.style`.
+
+use std::str::FromStr;
+
+use footlights_engine::configs::Config;
+use serde_json::Value as Schema;
+use serde_yaml::{value::TaggedValue, Mapping, Value};
+use thiserror::Error;
+
+use crate::suggest;
+
+/// The error type for `--set` overrides.
+#[derive(Debug, Error, PartialEq)]
+pub enum OverrideError {
+    /// The override is not `path=value`.
+    #[error("Invalid --set \"{0}\", expected path=value.")]
+    Syntax(String),
+    /// A key of the path does not exist.
+    #[error("Invalid --set path \"{path}\": unknown key \"{key}\"{} Expected one of {}.", did_you_mean(.suggestion), quoted(.expected))]
+    UnknownKey {
+        /// The path up to the key.
+        path: String,
+        /// The unknown key.
+        key: String,
+        /// The closest valid key.
+        suggestion: Option<String>,
+        /// The valid keys.
+        expected: Vec<String>,
+    },
+    /// The path continues below a value that has no fields.
+    #[error("Invalid --set path \"{path}\": \"{parent}\" has no fields.")]
+    NotAMapping {
+        /// The whole path.
+        path: String,
+        /// The path of the value without fields.
+        parent: String,
+    },
+    /// A layer index is not a number, or is out of range.
+    #[error("Invalid --set path \"{path}\": there is no layer \"{index}\", the config has {len}.")]
+    Index {
+        /// The whole path.
+        path: String,
+        /// The index, as written.
+        index: String,
+        /// The number of layers.
+        len: usize,
+    },
+    /// The value does not have the type of the field.
+    #[error("Invalid --set value for \"{path}\": expected {expected}, found \"{value}\".")]
+    Type {
+        /// The path of the field.
+        path: String,
+        /// The expected type.
+        expected: &'static str,
+        /// The value, as written.
+        value: String,
+    },
+    /// The config is invalid after the overrides.
+    #[error("The config is invalid after --set: {0}")]
+    Invalid(String),
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(key) => format!(", did you mean \"{}\"?", key),
+        None => ".".to_owned(),
+    }
+}
+
+fn quoted(keys: &[String]) -> String {
+    keys.iter()
+        .map(|key| format!("\"{}\"", key))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A `path=value` override.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    path: Vec<String>,
+    value: String,
+}
+
+impl Override {
+    /// The dotted path of the field.
+    pub fn path(&self) -> String {
+        self.path.join(".")
+    }
+}
+
+impl FromStr for Override {
+    type Err = OverrideError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (path, value) = text
+            .split_once('=')
+            .ok_or_else(|| OverrideError::Syntax(text.to_owned()))?;
+        let path: Vec<_> = path.trim().split('.').map(str::to_owned).collect();
+        if path.iter().any(String::is_empty) {
+            return Err(OverrideError::Syntax(text.to_owned()));
+        }
+
+        Ok(Self {
+            path,
+            value: value.to_owned(),
+        })
+    }
+}
+
+/// Apply the overrides to the config, in order.
+pub fn apply(config: &Config, overrides: &[Override]) -> Result<Config, OverrideError> {
+    // Write the layers, so that they can be overridden by index.
+    let config = Config {
+        layers: Some(config.structure().into_owned()),
+        ..config.clone()
+    };
+    let mut value =
+        serde_yaml::to_value(&config).map_err(|e| OverrideError::Invalid(e.to_string()))?;
+
+    let schema = footlights_engine::schema::config_schema();
+    for o in overrides {
+        let field = field_schema(&schema, &o.path)?;
+        let new = parse_value(&schema, field, o)?;
+        set(&mut value, &o.path, new, o)?;
+    }
+
+    serde_yaml::from_value(value).map_err(|e| OverrideError::Invalid(e.to_string()))
+}
+
+/// Follow `$ref`s and property-level `allOf`s.
+fn deref<'s>(root: &'s Schema, mut schema: &'s Schema) -> &'s Schema {
+    loop {
+        if let Some(name) = schema
+            .get("$ref")
+            .and_then(Schema::as_str)
+            .and_then(|r| r.strip_prefix("#/definitions/"))
+        {
+            schema = &root["definitions"][name];
+        } else if let Some([inner]) = schema
+            .get("allOf")
+            .and_then(Schema::as_array)
+            .map(Vec::as_slice)
+        {
+            schema = inner;
+        } else {
+            return schema;
+        }
+    }
+}
+
+/// The alternatives of a schema, recursively, or the schema itself.
+///
+/// The untagged payloads that the schema accepts for editors are wrapped in
+/// an `allOf`, they are not alternatives of the path.
+fn alternatives<'s>(root: &'s Schema, schema: &'s Schema) -> Vec<&'s Schema> {
+    match schema.get("anyOf").or_else(|| schema.get("oneOf")) {
+        Some(Schema::Array(items)) => items
+            .iter()
+            .filter(|item| item.get("allOf").is_none())
+            .flat_map(|item| alternatives(root, deref(root, item)))
+            .collect(),
+        _ => vec![schema],
+    }
+}
+
+/// The schema of the field at `path`.
+fn field_schema<'s>(root: &'s Schema, path: &[String]) -> Result<&'s Schema, OverrideError> {
+    let mut schema = root;
+    for (depth, key) in path.iter().enumerate() {
+        let parent = path[..depth].join(".");
+        let alternatives = alternatives(root, deref(root, schema));
+
+        let mut expected = Vec::new();
+        let mut found = None;
+        for alternative in &alternatives {
+            if let Some(items) = alternative.get("items") {
+                // Layers are checked against the config when the value is set.
+                if key.parse::<usize>().is_ok() {
+                    found = Some(items);
+                }
+            } else if let Some(Schema::Object(properties)) = alternative.get("properties") {
+                match properties.get(key.as_str()) {
+                    Some(property) => found = Some(property),
+                    None => expected.extend(properties.keys().cloned()),
+                }
+            } else if let Some(additional @ Schema::Object(_)) =
+                alternative.get("additionalProperties")
+            {
+                found = Some(additional);
+            }
+            if found.is_some() {
+                break;
+            }
+        }
+
+        schema = match found {
+            Some(found) => found,
+            None if expected.is_empty() => {
+                return Err(OverrideError::NotAMapping {
+                    path: path.join("."),
+                    parent,
+                })
+            }
+            None => {
+                let suggestion =
+                    suggest::closest(key, expected.iter().map(String::as_str)).map(str::to_owned);
+                return Err(OverrideError::UnknownKey {
+                    path: path.join("."),
+                    key: key.clone(),
+                    suggestion,
+                    expected,
+                });
+            }
+        };
+    }
+
+    Ok(schema)
+}
+
+/// Parse the value of an override as the type of its field.
+fn parse_value(root: &Schema, field: &Schema, o: &Override) -> Result<Value, OverrideError> {
+    let types: Vec<&str> = alternatives(root, deref(root, field))
+        .iter()
+        .flat_map(|alternative| match alternative.get("type") {
+            Some(Schema::String(ty)) => vec![ty.as_str()],
+            Some(Schema::Array(types)) => types.iter().filter_map(Schema::as_str).collect(),
+            _ => vec!["object"],
+        })
+        .filter(|ty| *ty != "null")
+        .collect();
+
+    if types == ["string"] {
+        return Ok(Value::String(o.value.clone()));
+    }
+    let value = serde_yaml::from_str(&o.value).unwrap_or_else(|_| Value::String(o.value.clone()));
+
+    let expected = match types[..] {
+        ["integer"] if !value.is_u64() => Some("a non-negative integer"),
+        ["number"] if !value.is_number() => Some("a number"),
+        ["boolean"] if !value.is_bool() => Some("true or false"),
+        _ => None,
+    };
+    match expected {
+        Some(expected) => Err(OverrideError::Type {
+            path: o.path(),
+            expected,
+            value: o.value.clone(),
+        }),
+        None => Ok(value),
+    }
+}
+
+/// Set the value at `path`, creating the missing mappings and variants.
+fn set(value: &mut Value, path: &[String], new: Value, o: &Override) -> Result<(), OverrideError> {
+    let Some((key, rest)) = path.split_first() else {
+        *value = new;
+        return Ok(());
+    };
+
+    let child = match value {
+        Value::Sequence(items) => {
+            let len = items.len();
+            let index_error = || OverrideError::Index {
+                path: o.path(),
+                index: key.clone(),
+                len,
+            };
+            let index: usize = key.parse().map_err(|_| index_error())?;
+            items.get_mut(index).ok_or_else(index_error)?
+        }
+        // The path names the variant of an enum.
+        Value::Tagged(tagged) if tagged.tag == key.as_str() => &mut tagged.value,
+        Value::Mapping(mapping) => mapping
+            .entry(Value::String(key.clone()))
+            .or_insert(Value::Null),
+        other => {
+            // A missing field, or another variant of an enum. The path is
+            // valid, and variants are capitalized while fields are not.
+            *other = if key.starts_with(char::is_uppercase) {
+                Value::Tagged(Box::new(TaggedValue {
+                    tag: serde_yaml::value::Tag::new(key.clone()),
+                    value: Value::Null,
+                }))
+            } else {
+                Value::Mapping(Mapping::new())
+            };
+            return set(other, path, new, o);
+        }
+    };
+
+    set(child, rest, new, o)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        serde_yaml::from_str(include_str!("../../examples/basic.yaml")).unwrap()
+    }
+
+    fn apply_all(overrides: &[&str]) -> Result<Config, OverrideError> {
+        let overrides: Vec<Override> = overrides
+            .iter()
+            .map(|o| o.parse())
+            .collect::<Result<_, _>>()?;
+        apply(&config(), &overrides)
+    }
+
+    fn yaml(config: &Config, id: &str) -> String {
+        serde_yaml::to_string(config.styles.get(id).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn typed_overrides() {
+        let config = apply_all(&[
+            "styles.img.round=30",
+            "styles.img.image=10",
+            "styles.img.shadow.opacity=0.3",
+            "styles.bg.color.Linear.degree=90",
+            "layers.1.style=logo",
+            "styles.logo.image=logo.png",
+        ])
+        .unwrap();
+
+        let img = yaml(&config, "img");
+        assert!(img.contains("image: '10'"), "{}", img);
+        assert!(img.contains("round: 30"), "{}", img);
+        assert!(img.contains("opacity: 0.3"), "{}", img);
+        assert!(yaml(&config, "bg").contains("degree: 90.0"));
+        assert_eq!(config.structure().iter().nth(1).unwrap().style(), "logo");
+
+        let config = apply_all(&[
+            "styles.bg.color=!Pure red",
+            "styles.img.css=border-radius: 3px",
+        ])
+        .unwrap();
+        assert_eq!(yaml(&config, "bg"), "color: !Pure red\n");
+
+        // Another variant replaces the color.
+        let config = apply_all(&["styles.bg.color.Pure=blue"]).unwrap();
+        assert_eq!(yaml(&config, "bg"), "color: !Pure blue\n");
+    }
+
+    #[test]
+    fn override_errors() {
+        assert_eq!(
+            apply_all(&["styles.img.rond=30"]).unwrap_err().to_string(),
+            r#"Invalid --set path "styles.img.rond": unknown key "rond", did you mean "round"? Expected one of "color", "css", "extends", "image", "position", "round", "shadow", "size"."#
+        );
+        assert_eq!(
+            apply_all(&["style.img.round=30"]).unwrap_err(),
+            OverrideError::UnknownKey {
+                path: "style.img.round".to_owned(),
+                key: "style".to_owned(),
+                suggestion: Some("styles".to_owned()),
+                expected: vec!["include".into(), "layers".into(), "styles".into()],
+            }
+        );
+        assert_eq!(
+            apply_all(&["styles.img.round.x=1"])
+                .unwrap_err()
+                .to_string(),
+            r#"Invalid --set path "styles.img.round.x": "styles.img.round" has no fields."#
+        );
+        assert_eq!(
+            apply_all(&["styles.img.round=big"])
+                .unwrap_err()
+                .to_string(),
+            r#"Invalid --set value for "styles.img.round": expected a non-negative integer, found "big"."#
+        );
+        assert_eq!(
+            apply_all(&["layers.5.style=x"]).unwrap_err().to_string(),
+            r#"Invalid --set path "layers.5.style": there is no layer "5", the config has 2."#
+        );
+        assert_eq!(
+            apply_all(&["styles.img"]).unwrap_err(),
+            OverrideError::Syntax("styles.img".to_owned())
+        );
+        assert!(matches!(
+            apply_all(&["styles.bg.color.Linear.stops=red"]),
+            Err(OverrideError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+//! The access policy of the resources that configs reference.
+//!
+//! Configs reference images and include other configs. Before a reference
+//! is read, it is checked against the policy: its scheme must be allowed,
+//! a local file must be under the root directory, and no pattern of the
+//! denylist may match it.
+//!
+//! Fonts are only loaded from the system font database, and the renderer
+//! only gets the loaded images, by their handles, see
+//! [`footlights_engine::render::raster`], so nothing else is read.
+
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use thiserror::Error;
+
+use crate::glob;
+
+/// A scheme of references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Scheme {
+    /// Local files, as paths or `file://` URLs.
+    File,
+    /// Data URLs.
+    Data,
+    /// HTTP and HTTPS URLs.
+    Http,
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::File => "file",
+            Self::Data => "data",
+            Self::Http => "http",
+        })
+    }
+}
+
+/// The error type of references refused by the policy.
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    /// The scheme of the reference is not allowed.
+    #[error("\"{reference}\" is a {scheme} reference, which is not allowed. Allowed: {allowed}.")]
+    Scheme {
+        reference: String,
+        scheme: Scheme,
+        allowed: String,
+    },
+    /// The scheme of the reference is not supported at all.
+    #[error("\"{reference}\" has the unsupported scheme \"{scheme}\".")]
+    Unsupported { reference: String, scheme: String },
+    /// The file is not under the root directory.
+    #[error("\"{reference}\" is outside of the root directory \"{}\".", root.display())]
+    OutsideRoot { reference: String, root: PathBuf },
+    /// A pattern of the denylist matches the reference.
+    #[error("\"{reference}\" is denied by \"{pattern}\".")]
+    Denied { reference: String, pattern: String },
+    /// The file could not be resolved, to be checked.
+    #[error("Failed to resolve \"{reference}\": {source}")]
+    Resolve {
+        reference: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Where resources can be read from.
+///
+/// The default policy allows every scheme and every file.
+#[derive(Debug, Clone)]
+pub struct Policy {
+    /// The canonical root directory of local files.
+    root: Option<PathBuf>,
+    schemes: Vec<Scheme>,
+    /// Globs of paths, or patterns of URL hosts.
+    deny: Vec<String>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            root: None,
+            schemes: vec![Scheme::File, Scheme::Data, Scheme::Http],
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl Policy {
+    /// A policy that only reads local files under `root`, with the
+    /// `schemes`, and nothing that matches a pattern of `deny`.
+    ///
+    /// A pattern of `deny` is a glob of paths, see [`crate::glob`], which
+    /// also denies the files under the matched directories. A relative
+    /// pattern matches at any depth, `.ssh` denies every `.ssh` directory.
+    /// For URLs, the patterns are matched against the host.
+    pub fn new(
+        root: Option<&Path>,
+        schemes: Vec<Scheme>,
+        deny: Vec<String>,
+    ) -> Result<Self, PolicyError> {
+        let root = root
+            .map(|root| {
+                root.canonicalize().map_err(|source| PolicyError::Resolve {
+                    reference: root.display().to_string(),
+                    source,
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            root,
+            schemes,
+            deny,
+        })
+    }
+
+    /// Check an image reference.
+    ///
+    /// Local paths are relative to the working directory. For a local
+    /// reference, returns the path to read, see [`Policy::check_file`]: the
+    /// checked path must be read, not the reference, which could be changed
+    /// to a symbolic link in between.
+    pub fn check(&self, reference: &str) -> Result<Option<PathBuf>, PolicyError> {
+        match scheme(reference) {
+            Some("data") => self.allow(reference, Scheme::Data).map(|_| None),
+            Some("http" | "https") => self.check_url(reference).map(|_| None),
+            Some("file") => {
+                let path = reference.trim_start_matches("file://");
+                self.check_path(Path::new(path), reference).map(Some)
+            }
+            Some(scheme) => Err(PolicyError::Unsupported {
+                reference: reference.to_owned(),
+                scheme: scheme.to_owned(),
+            }),
+            None => self.check_path(Path::new(reference), reference).map(Some),
+        }
+    }
+
+    /// Check a local file, and return the path to read, canonical if the
+    /// policy restricts files.
+    pub fn check_file(&self, path: &Path) -> Result<PathBuf, PolicyError> {
+        self.check_path(path, &path.display().to_string())
+    }
+
+    fn check_path(&self, path: &Path, reference: &str) -> Result<PathBuf, PolicyError> {
+        self.allow(reference, Scheme::File)?;
+        if self.root.is_none() && self.deny.is_empty() {
+            return Ok(path.to_owned());
+        }
+
+        // Symbolic links and `..` are resolved, so that they cannot escape.
+        let canonical = path.canonicalize().map_err(|source| PolicyError::Resolve {
+            reference: reference.to_owned(),
+            source,
+        })?;
+        if let Some(root) = &self.root {
+            if !canonical.starts_with(root) {
+                return Err(PolicyError::OutsideRoot {
+                    reference: reference.to_owned(),
+                    root: root.clone(),
+                });
+            }
+        }
+        if let Some(pattern) = self.deny.iter().find(|pattern| denies(pattern, &canonical)) {
+            return Err(PolicyError::Denied {
+                reference: reference.to_owned(),
+                pattern: pattern.clone(),
+            });
+        }
+
+        Ok(canonical)
+    }
+
+    /// Check an HTTP(S) URL, also the targets of redirects.
+    pub fn check_url(&self, url: &str) -> Result<(), PolicyError> {
+        self.allow(url, Scheme::Http)?;
+
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase))
+            .unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Some(pattern) = self
+            .deny
+            .iter()
+            .find(|pattern| glob::matches_name(pattern, host))
+        {
+            return Err(PolicyError::Denied {
+                reference: url.to_owned(),
+                pattern: pattern.clone(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn allow(&self, reference: &str, scheme: Scheme) -> Result<(), PolicyError> {
+        if self.schemes.contains(&scheme) {
+            return Ok(());
+        }
+
+        let allowed: Vec<_> = self.schemes.iter().map(Scheme::to_string).collect();
+        Err(PolicyError::Scheme {
+            reference: shorten(reference),
+            scheme,
+            allowed: match allowed.is_empty() {
+                true => "nothing".to_owned(),
+                false => allowed.join(", "),
+            },
+        })
+    }
+}
+
+/// The scheme of a URL, `None` for a path.
+pub fn scheme(reference: &str) -> Option<&str> {
+    let (scheme, _) = reference.split_once(':')?;
+    // A single letter is a Windows drive.
+    let valid = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then_some(scheme)
+}
+
+/// Whether a pattern of the denylist matches a canonical path.
+fn denies(pattern: &str, path: &Path) -> bool {
+    let mut globs: Vec<_> = pattern
+        .split('/')
+        .filter(|glob| !glob.is_empty() && *glob != ".")
+        .map(str::to_owned)
+        .collect();
+    if !pattern.starts_with('/') {
+        globs.insert(0, "**".to_owned());
+    }
+    // The files under a denied directory are denied.
+    globs.push("**".to_owned());
+
+    let names: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    glob::matches_path(&globs, &names)
+}
+
+/// Shorten a data URL for an error.
+fn shorten(reference: &str) -> String {
+    match reference.char_indices().nth(48) {
+        Some((end, _)) if reference.starts_with("data:") => format!("{}...", &reference[..end]),
+        _ => reference.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_references() {
+        // The process id keeps concurrent test runs apart.
+        let dir = std::env::temp_dir().join(format!("footlights-policy-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for file in ["root/a.png", "root/keys/b.png", "root/c.key", "outside.png"] {
+            let path = dir.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+
+        let policy = Policy::new(
+            Some(&dir.join("root")),
+            vec![Scheme::File, Scheme::Http],
+            vec![
+                "keys".to_owned(),
+                "*.key".to_owned(),
+                "*.internal".to_owned(),
+            ],
+        )
+        .unwrap();
+        // The local references resolve to the canonical path to read.
+        let canonical = dir.join("root/a.png").canonicalize().unwrap();
+        assert_eq!(
+            policy.check(&path("root/keys/../a.png")).unwrap(),
+            Some(canonical.clone())
+        );
+        assert_eq!(
+            policy
+                .check(&format!("file://{}", path("root/a.png")))
+                .unwrap(),
+            Some(canonical)
+        );
+        assert_eq!(policy.check("https://example.com/a.png").unwrap(), None);
+
+        let error = |reference: &str| policy.check(reference).unwrap_err();
+        assert!(matches!(
+            error(&path("outside.png")),
+            PolicyError::OutsideRoot { .. }
+        ));
+        assert!(matches!(
+            error(&path("root/../outside.png")),
+            PolicyError::OutsideRoot { .. }
+        ));
+        assert!(matches!(
+            error(&path("root/keys/b.png")),
+            PolicyError::Denied { pattern, .. } if pattern == "keys"
+        ));
+        assert!(matches!(
+            error(&path("root/c.key")),
+            PolicyError::Denied { pattern, .. } if pattern == "*.key"
+        ));
+        assert!(matches!(
+            error("http://metadata.internal/latest"),
+            PolicyError::Denied { .. }
+        ));
+        assert!(matches!(
+            error(&path("root/missing.png")),
+            PolicyError::Resolve { .. }
+        ));
+        assert_eq!(
+            error("data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk").to_string(),
+            "\"data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAA...\" is a data reference, which is not allowed. Allowed: file, http."
+        );
+        assert_eq!(
+            error("ftp://example.com/a.png").to_string(),
+            "\"ftp://example.com/a.png\" has the unsupported scheme \"ftp\"."
+        );
+
+        let permissive = Policy::default();
+        assert!(permissive.check(&path("outside.png")).is_ok());
+        assert!(permissive.check("data:image/png;base64,").is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
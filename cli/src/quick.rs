@@ -0,0 +1,224 @@
+//! Quick flags, which style a render without a config.
+//!
+//! The flags build the `bg` and `img` styles, the styles of the default
+//! layers, and are merged over the configs.
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use footlights_engine::{
+    background::BackgroundType,
+    configs::style::{DropShadow, SizeOption, Style, StyleCollection},
+    css::Declarations,
+};
+
+/// The style of the background layer.
+const BACKGROUND_STYLE: &str = "bg";
+/// The style of the image layer.
+const IMAGE_STYLE: &str = "img";
+
+// The quick flags, see the module documentation.
+#[derive(Args, Debug, Default)]
+pub struct QuickArgs {
+    /// The background: a CSS color or linear-gradient(), or preset:<name> for a gradient preset.
+    #[arg(long, value_name = "BACKGROUND")]
+    bg: Option<String>,
+
+    /// The rounded corner radius of the image, in px.
+    #[arg(long, value_name = "PX")]
+    round: Option<usize>,
+
+    /// The shadow of the image, as x,y[,blur[,opacity]].
+    #[arg(long, value_name = "X,Y[,BLUR[,OPACITY]]", value_parser = parse_shadow)]
+    shadow: Option<DropShadow>,
+
+    /// The padding around the image, as all or horizontal,vertical in px.
+    #[arg(long, value_name = "PX[,PX]", value_parser = parse_padding)]
+    padding: Option<SizeOption>,
+}
+
+impl QuickArgs {
+    /// The styles set by the flags.
+    pub fn styles(&self) -> Result<StyleCollection> {
+        let mut bg = Style::default();
+        if let Some(value) = &self.bg {
+            bg = match value.strip_prefix("preset:") {
+                Some(name) => bg.with_color(BackgroundType::Preset(name.to_owned())),
+                None => {
+                    let css: Declarations = format!("background: {}", value)
+                        .parse()
+                        .with_context(|| format!("Invalid --bg \"{}\".", value))?;
+                    bg.with_css(css)
+                }
+            };
+        }
+        if let Some(padding) = self.padding {
+            bg = bg.with_size(padding);
+        }
+
+        let mut img = Style::default();
+        if let Some(round) = self.round {
+            img = img.with_round(round);
+        }
+        if let Some(shadow) = &self.shadow {
+            img = img.with_shadow(shadow.clone());
+        }
+
+        let mut styles = StyleCollection::default();
+        for (id, style) in [(BACKGROUND_STYLE, bg), (IMAGE_STYLE, img)] {
+            if !style.set_fields().is_empty() {
+                styles.add(id.to_owned(), style);
+            }
+        }
+        // Apply the CSS, so that the fields override the configs.
+        Ok(styles.resolve()?)
+    }
+}
+
+/// Parse `x,y[,blur[,opacity]]`.
+fn parse_shadow(value: &str) -> Result<DropShadow> {
+    let parts: Vec<_> = value.split(',').map(str::trim).collect();
+    if !(2..=4).contains(&parts.len()) {
+        bail!("expected x,y[,blur[,opacity]]");
+    }
+
+    let number = |i: usize| -> Result<Option<usize>> {
+        parts
+            .get(i)
+            .map(|part| {
+                part.parse()
+                    .with_context(|| format!("\"{}\" is not a length in px", part))
+            })
+            .transpose()
+    };
+    let default = DropShadow::default();
+    Ok(DropShadow {
+        x: number(0)?.unwrap_or(default.x),
+        y: number(1)?.unwrap_or(default.y),
+        blur: number(2)?.unwrap_or(default.blur),
+        opacity: match parts.get(3) {
+            Some(part) => part
+                .parse()
+                .ok()
+                .filter(|opacity| (0.0..=1.0).contains(opacity))
+                .with_context(|| format!("\"{}\" is not an opacity from 0 to 1", part))?,
+            None => default.opacity,
+        },
+    })
+}
+
+/// Parse `all` or `horizontal,vertical`.
+fn parse_padding(value: &str) -> Result<SizeOption> {
+    let parts = value
+        .split(',')
+        .map(|part| {
+            part.trim()
+                .parse()
+                .with_context(|| format!("\"{}\" is not a length in px", part.trim()))
+        })
+        .collect::<Result<Vec<u32>>>()?;
+
+    match parts[..] {
+        [all] => Ok(SizeOption::FitContent(all)),
+        [x, y] => Ok(SizeOption::FitContentXY(x, y)),
+        _ => bail!("expected px or horizontal,vertical"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use footlights_engine::configs::Config;
+
+    use super::*;
+
+    #[test]
+    fn parse_flags() {
+        assert_eq!(
+            parse_shadow("5, 5").unwrap(),
+            DropShadow {
+                x: 5,
+                y: 5,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            parse_shadow("1,2,3,0.5").unwrap(),
+            DropShadow {
+                x: 1,
+                y: 2,
+                blur: 3,
+                opacity: 0.5
+            }
+        );
+        assert!(parse_shadow("5").is_err());
+        assert!(parse_shadow("5,5,7,2").is_err());
+
+        assert_eq!(parse_padding("80").unwrap(), SizeOption::FitContent(80));
+        assert_eq!(
+            parse_padding("80,40").unwrap(),
+            SizeOption::FitContentXY(80, 40)
+        );
+        assert!(parse_padding("80px").is_err());
+    }
+
+    #[test]
+    fn build_styles() {
+        let args = QuickArgs {
+            bg: Some("linear-gradient(90deg, red, blue)".to_owned()),
+            round: Some(16),
+            padding: Some(SizeOption::FitContent(80)),
+            ..Default::default()
+        };
+        let styles = args.styles().unwrap();
+
+        let bg = styles.get("bg").unwrap();
+        assert_eq!(bg.set_fields(), ["size", "color"]);
+        let img = styles.get("img").unwrap();
+        assert_eq!(img.set_fields(), ["round"]);
+
+        let args = QuickArgs {
+            bg: Some("preset:sunst".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            args.styles().unwrap_err().to_string(),
+            r#"Style "bg" uses the preset "sunst", which is not a gradient preset."#
+        );
+        assert!(QuickArgs::default()
+            .styles()
+            .unwrap()
+            .ids()
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn override_css() {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+styles:
+  img: { image: a.png, css: "border-radius: 12px" }
+  bg: { css: "background: red" }
+"#,
+        )
+        .unwrap();
+        let args = QuickArgs {
+            bg: Some("blue".to_owned()),
+            round: Some(4),
+            ..Default::default()
+        };
+        config.merge(Config {
+            styles: args.styles().unwrap(),
+            ..Default::default()
+        });
+
+        // The flags override the fields set by the CSS of the config.
+        let styles = config.styles.resolve().unwrap();
+        let field =
+            |id, field| serde_yaml::to_value(styles.get(id).unwrap()).unwrap()[field].clone();
+        assert_eq!(field("img", "round"), 4);
+        assert_eq!(
+            field("bg", "color"),
+            serde_yaml::from_str::<serde_yaml::Value>("!Pure blue").unwrap()
+        );
+    }
+}
@@ -0,0 +1,530 @@
+//! The HTTP render service, `fl serve`.
+//!
+//! A small HTTP/1.1 server on tokio, one request per connection:
+//!
+//! - `GET /health` answers `{"status": "ok"}`.
+//! - `POST /render` takes the image as the body, and returns the render.
+//!   The query sets the output `format` (`png`, `webp` or `svg`), the
+//!   `preset`s, a `config` and a `style` for the image, as JSON.
+//!
+//! Bodies larger than the limit are refused, and at most a number of
+//! renders run at once, further requests are refused until one finishes,
+//! before their body is read.
+//! Errors are JSON objects: `{"error": {"code": "...", "message": "..."}}`.
+
+use std::{sync::Arc, time::Duration};
+
+use clap::ValueEnum;
+use serde_json::json;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpListener,
+    sync::Semaphore,
+};
+
+/// The largest request line and headers.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// The format of a render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Png,
+    Webp,
+    Svg,
+}
+
+impl OutputFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Webp => "image/webp",
+            Self::Svg => "image/svg+xml",
+        }
+    }
+}
+
+/// What a client asks to render.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderRequest {
+    /// The bytes of the input image.
+    pub image: Vec<u8>,
+    pub format: OutputFormat,
+    /// Presets to merge under the config.
+    pub presets: Vec<String>,
+    /// A config as JSON, merged over the configs of the server.
+    pub config: Option<String>,
+    /// The style of the image as JSON, merged over the `img` style.
+    pub style: Option<String>,
+}
+
+/// A refused request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpError {
+    pub status: u16,
+    /// A stable identifier of the error, for clients.
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl HttpError {
+    pub fn new(status: u16, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// An answer to a request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+}
+
+impl From<HttpError> for Response {
+    fn from(e: HttpError) -> Self {
+        Self::json(
+            e.status,
+            json!({ "error": { "code": e.code, "message": e.message } }),
+        )
+    }
+}
+
+/// The limits of the service.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// The largest request body, in bytes.
+    pub max_body: usize,
+    /// The number of renders at once.
+    pub max_concurrent: usize,
+    /// The time to read a request.
+    pub read_timeout: Duration,
+}
+
+/// Renders a request into the bytes of the output format.
+pub type Renderer = dyn Fn(RenderRequest) -> Result<Vec<u8>, HttpError> + Send + Sync;
+
+/// Serve requests until the process stops.
+pub async fn run(
+    listener: TcpListener,
+    limits: Limits,
+    renderer: Arc<Renderer>,
+) -> std::io::Result<()> {
+    let permits = Arc::new(Semaphore::new(limits.max_concurrent));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let (limits, renderer, permits) = (limits.clone(), renderer.clone(), permits.clone());
+
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let response = handle(BufReader::new(reader), &limits, &renderer, &permits).await;
+            if response.status >= 500 {
+                eprintln!(
+                    "{}: error: {}",
+                    peer,
+                    String::from_utf8_lossy(&response.body)
+                );
+            }
+            let _ = write_response(&mut writer, &response).await;
+        });
+    }
+}
+
+/// Read a request, and route it.
+async fn handle<R>(
+    mut reader: R,
+    limits: &Limits,
+    renderer: &Arc<Renderer>,
+    permits: &Arc<Semaphore>,
+) -> Response
+where
+    R: AsyncBufRead + Unpin,
+{
+    // The head and the body are received within the same time.
+    let deadline = tokio::time::Instant::now() + limits.read_timeout;
+    let timed_out = || HttpError::new(408, "timeout", "The request was not received in time.");
+    let mut request = match tokio::time::timeout_at(deadline, read_head(&mut reader, limits)).await
+    {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => return e.into(),
+        Err(_) => return timed_out().into(),
+    };
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => Response::json(200, json!({ "status": "ok" })),
+        ("POST", "/render") => {
+            // The permit is taken before the body is read, so only the
+            // renders at once hold a body in memory.
+            let Ok(permit) = permits.clone().try_acquire_owned() else {
+                return HttpError::new(503, "busy", "Too many renders at once, retry later.")
+                    .into();
+            };
+            match tokio::time::timeout_at(deadline, read_body(&mut reader, &mut request)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return e.into(),
+                Err(_) => return timed_out().into(),
+            }
+            let render = match parse_render(request) {
+                Ok(render) => render,
+                Err(e) => return e.into(),
+            };
+
+            let format = render.format;
+            let renderer = renderer.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                renderer(render)
+            })
+            .await;
+            match result {
+                Ok(Ok(body)) => Response {
+                    status: 200,
+                    content_type: format.content_type(),
+                    body,
+                },
+                Ok(Err(e)) => e.into(),
+                Err(e) => HttpError::new(500, "internal", e.to_string()).into(),
+            }
+        }
+        (_, "/health" | "/render") => HttpError::new(
+            405,
+            "method_not_allowed",
+            format!("{} is not allowed on {}.", request.method, request.path),
+        )
+        .into(),
+        (_, path) => HttpError::new(404, "not_found", format!("No endpoint at {}.", path)).into(),
+    }
+}
+
+/// A parsed HTTP request.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    /// The length of the body, at most the limit.
+    content_length: usize,
+    /// The body, once [`read_body`] has read it.
+    body: Vec<u8>,
+}
+
+/// Read the head of a request, and check the length of its body.
+async fn read_head<R>(mut reader: R, limits: &Limits) -> Result<Request, HttpError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let bad_request = |message: &str| HttpError::new(400, "bad_request", message);
+
+    let mut lines = Vec::new();
+    let mut head_size = 0;
+    loop {
+        let mut line = String::new();
+        let read = (&mut reader)
+            .take((MAX_HEAD_SIZE - head_size + 1) as u64)
+            .read_line(&mut line)
+            .await
+            .map_err(|_| bad_request("The request could not be read."))?;
+        head_size += read;
+        if head_size > MAX_HEAD_SIZE {
+            return Err(HttpError::new(
+                431,
+                "headers_too_large",
+                "The request headers are too large.",
+            ));
+        }
+        if read == 0 {
+            return Err(bad_request("The request ended before its headers."));
+        }
+        let line = line.trim_end_matches(['\r', '\n']).to_owned();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+
+    let mut request_line = lines.first().map(String::as_str).unwrap_or("").split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err(bad_request("The request line is invalid."));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let mut content_length = None;
+    for line in &lines[1..] {
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(bad_request("A header is invalid."));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(
+                value
+                    .parse::<usize>()
+                    .map_err(|_| bad_request("The Content-Length is invalid."))?,
+            );
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            return Err(HttpError::new(
+                411,
+                "length_required",
+                "Chunked bodies are not supported, set the Content-Length.",
+            ));
+        }
+    }
+
+    let content_length = match content_length {
+        Some(length) if length > limits.max_body => {
+            return Err(HttpError::new(
+                413,
+                "body_too_large",
+                format!(
+                    "The body is {} bytes, the limit is {} bytes.",
+                    length, limits.max_body
+                ),
+            ))
+        }
+        Some(length) => length,
+        None if method == "POST" => {
+            return Err(HttpError::new(
+                411,
+                "length_required",
+                "The Content-Length is required.",
+            ))
+        }
+        None => 0,
+    };
+
+    Ok(Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        query: form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect(),
+        content_length,
+        body: Vec::new(),
+    })
+}
+
+/// Read the body of a request, after its head.
+async fn read_body<R>(mut reader: R, request: &mut Request) -> Result<(), HttpError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    request.body.resize(request.content_length, 0);
+    reader
+        .read_exact(&mut request.body)
+        .await
+        .map_err(|_| HttpError::new(400, "bad_request", "The request ended before its body."))?;
+    Ok(())
+}
+
+/// Read the parameters of a render from the query.
+fn parse_render(request: Request) -> Result<RenderRequest, HttpError> {
+    let invalid = |message: String| HttpError::new(400, "invalid_parameter", message);
+
+    let mut render = RenderRequest {
+        image: request.body,
+        format: OutputFormat::Png,
+        presets: Vec::new(),
+        config: None,
+        style: None,
+    };
+    for (key, value) in request.query {
+        match key.as_str() {
+            "format" => {
+                render.format = OutputFormat::from_str(&value, true).map_err(|_| {
+                    invalid(format!(
+                        "Unknown format \"{}\", expected png, webp or svg.",
+                        value
+                    ))
+                })?
+            }
+            "preset" => render.presets.push(value),
+            "config" => render.config = Some(value),
+            "style" => render.style = Some(value),
+            _ => {
+                return Err(invalid(format!(
+                    "Unknown parameter \"{}\", expected format, preset, config or style.",
+                    key
+                )))
+            }
+        }
+    }
+    if render.image.is_empty() {
+        return Err(HttpError::new(
+            400,
+            "missing_image",
+            "The body must be the input image.",
+        ));
+    }
+
+    Ok(render)
+}
+
+async fn write_response<W>(writer: &mut W, response: &Response) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len()
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(&response.body).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> Limits {
+        Limits {
+            max_body: 16,
+            max_concurrent: 1,
+            read_timeout: Duration::from_secs(1),
+        }
+    }
+
+    async fn request(text: &[u8], permits: usize) -> (Response, Option<RenderRequest>) {
+        let received = Arc::new(std::sync::Mutex::new(None));
+        let renderer: Arc<Renderer> = Arc::new({
+            let received = received.clone();
+            move |render: RenderRequest| {
+                *received.lock().unwrap() = Some(render.clone());
+                match render.presets.first().map(String::as_str) {
+                    Some("broken") => Err(HttpError::new(422, "invalid_config", "broken")),
+                    _ => Ok(b"rendered".to_vec()),
+                }
+            }
+        });
+        let permits = Arc::new(Semaphore::new(permits));
+
+        let response = handle(text, &limits(), &renderer, &permits).await;
+        let received = received.lock().unwrap().take();
+        (response, received)
+    }
+
+    fn body(response: &Response) -> serde_json::Value {
+        serde_json::from_slice(&response.body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn route_requests() {
+        let (response, _) = request(b"GET /health HTTP/1.1\r\nHost: x\r\n\r\n", 1).await;
+        assert_eq!(response.status, 200);
+        assert_eq!(body(&response), json!({ "status": "ok" }));
+
+        let (response, render) = request(
+            b"POST /render?format=svg&preset=ocean&style=%7B%22round%22%3A4%7D HTTP/1.1\r\nContent-Length: 4\r\n\r\nPNG!",
+            1,
+        )
+        .await;
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type, "image/svg+xml");
+        assert_eq!(response.body, b"rendered");
+        assert_eq!(
+            render.unwrap(),
+            RenderRequest {
+                image: b"PNG!".to_vec(),
+                format: OutputFormat::Svg,
+                presets: vec!["ocean".to_owned()],
+                config: None,
+                style: Some(r#"{"round":4}"#.to_owned()),
+            }
+        );
+
+        let (response, _) = request(
+            b"POST /render?preset=broken HTTP/1.1\r\nContent-Length: 4\r\n\r\nPNG!",
+            1,
+        )
+        .await;
+        assert_eq!(response.status, 422);
+        assert_eq!(
+            body(&response),
+            json!({ "error": { "code": "invalid_config", "message": "broken" } })
+        );
+    }
+
+    #[tokio::test]
+    async fn refuse_requests() {
+        let status = |response: Response| {
+            let code = body(&response)["error"]["code"]
+                .as_str()
+                .unwrap()
+                .to_owned();
+            (response.status, code)
+        };
+
+        let cases: [(&[u8], usize, (u16, &str)); 8] = [
+            (b"GET /nope HTTP/1.1\r\n\r\n", 1, (404, "not_found")),
+            (
+                b"GET /render HTTP/1.1\r\n\r\n",
+                1,
+                (405, "method_not_allowed"),
+            ),
+            (
+                b"POST /render HTTP/1.1\r\n\r\n",
+                1,
+                (411, "length_required"),
+            ),
+            (
+                b"POST /render HTTP/1.1\r\nContent-Length: 17\r\n\r\n",
+                1,
+                (413, "body_too_large"),
+            ),
+            (
+                b"POST /render?format=gif HTTP/1.1\r\nContent-Length: 1\r\n\r\nx",
+                1,
+                (400, "invalid_parameter"),
+            ),
+            (
+                b"POST /render HTTP/1.1\r\nContent-Length: 0\r\n\r\n",
+                1,
+                (400, "missing_image"),
+            ),
+            (
+                b"POST /render HTTP/1.1\r\nContent-Length: 1\r\n\r\nx",
+                0,
+                (503, "busy"),
+            ),
+            // The body of a refused render is not read.
+            (
+                b"POST /render HTTP/1.1\r\nContent-Length: 8\r\n\r\n",
+                0,
+                (503, "busy"),
+            ),
+        ];
+        for (text, permits, (code, name)) in cases {
+            let (response, render) = request(text, permits).await;
+            assert_eq!(status(response), (code, name.to_owned()));
+            assert!(render.is_none());
+        }
+    }
+}
@@ -0,0 +1,591 @@
+//! Templating of configs with variables and conditional blocks.
+//!
+//! Templates are applied after parsing, on the values of the config, so a
+//! variable can never break the syntax of the file:
+//!
+//! * `"{name}"` is replaced by the value of the variable, keeping its type,
+//!   so `round: "{round}"` becomes a number. `"{name|default}"` falls back to
+//!   the default when the variable is not set.
+//! * Inside a longer string, `"{name}.png"`, the value is written as text.
+//!   `{{` and `}}` are literal braces.
+//! * `{env.NAME}` reads the environment variable `NAME`.
+//! * A key `if name` (or `if !name`) of a mapping holds a mapping, which is
+//!   merged into the parent when the variable is set and not `false`, `0`,
+//!   `null` or empty. In a sequence, an item `- if name: [...]` is replaced by
+//!   the items of its sequence.
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+};
+
+use anyhow::Context;
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+use crate::{format::Format, suggest};
+
+/// The error type for applying templates.
+#[derive(Debug, Error, PartialEq)]
+pub enum TemplateError {
+    /// A variable is used but not set, and has no default.
+    #[error("Variable \"{name}\" is not set, used at \"{path}\".{}", did_you_mean(.suggestion))]
+    Unset {
+        /// The name of the variable.
+        name: String,
+        /// The path of the value that uses it.
+        path: String,
+        /// The closest variable that is set.
+        suggestion: Option<String>,
+    },
+    /// A variable holding a sequence or a mapping is used inside a string.
+    #[error("Variable \"{name}\" is not a scalar, it cannot be part of the string at \"{path}\".")]
+    NotScalar {
+        /// The name of the variable.
+        name: String,
+        /// The path of the string.
+        path: String,
+    },
+    /// A placeholder or a conditional block is malformed.
+    #[error("Invalid template at \"{path}\": {reason}")]
+    Syntax {
+        /// The path of the value.
+        path: String,
+        /// What is wrong with it.
+        reason: String,
+    },
+    /// The templated config cannot be written back in its format.
+    #[error("Failed to write the templated config: {0}")]
+    Write(String),
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(name) => format!(" Did you mean \"{}\"?", name),
+        None => String::new(),
+    }
+}
+
+/// The variables of a template.
+#[derive(Debug, Clone, Default)]
+pub struct Vars {
+    values: BTreeMap<String, Value>,
+    /// The variables read by the templates.
+    used: RefCell<BTreeSet<String>>,
+    /// Keep the placeholders of unset variables, see [`Vars::keep_unset`].
+    keep_unset: bool,
+    /// The variables read by `env.`, the process environment if `None`.
+    env: Option<BTreeMap<String, String>>,
+}
+
+impl Vars {
+    /// Set a variable.
+    pub fn insert(&mut self, name: impl Into<String>, value: Value) {
+        self.values.insert(name.into(), value);
+    }
+
+    /// Set a variable from a `key=value` argument.
+    ///
+    /// The value is typed like a YAML scalar: `10` is a number, `true` a
+    /// boolean and anything else a string.
+    pub fn insert_arg(&mut self, arg: &str) -> anyhow::Result<()> {
+        let (name, value) = arg
+            .split_once('=')
+            .with_context(|| format!("Invalid variable \"{}\", expected key=value.", arg))?;
+        self.insert(name.trim(), parse_scalar(value));
+        Ok(())
+    }
+
+    /// Set the variables of a JSON (or YAML) object.
+    pub fn insert_file(&mut self, file: &str) -> anyhow::Result<()> {
+        let text = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read variables \"{}\".", file))?;
+        let values: BTreeMap<String, Value> = serde_yaml::from_str(&text).with_context(|| {
+            format!(
+                "Failed to parse variables \"{}\", expected an object.",
+                file
+            )
+        })?;
+        self.values.extend(values);
+        Ok(())
+    }
+
+    /// Keep the strings with unset variables as they are, instead of failing.
+    ///
+    /// This checks a config without knowing all its variables, such as the
+    /// input images.
+    pub fn keep_unset(mut self) -> Self {
+        self.keep_unset = true;
+        self
+    }
+
+    /// Whether a template has read the variable, in a placeholder or a
+    /// condition.
+    pub fn is_used(&self, name: &str) -> bool {
+        self.used.borrow().contains(name)
+    }
+
+    /// Read `env.` variables from `env` instead of the process environment,
+    /// which tests must not change while other threads read it.
+    #[cfg(test)]
+    fn with_env(mut self, env: &[(&str, &str)]) -> Self {
+        let env = env
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()));
+        self.env = Some(env.collect());
+        self
+    }
+
+    /// The value of a variable, `env.` reads the environment.
+    fn get(&self, name: &str) -> Option<Value> {
+        match name.strip_prefix("env.") {
+            Some(name) => match &self.env {
+                Some(env) => env.get(name).cloned(),
+                None => std::env::var(name).ok(),
+            }
+            .map(|value| parse_scalar(&value)),
+            None => {
+                self.used.borrow_mut().insert(name.to_owned());
+                self.values.get(name).cloned()
+            }
+        }
+    }
+
+    fn is_truthy(&self, name: &str) -> bool {
+        match self.get(name) {
+            None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+            Some(Value::String(s)) => !s.is_empty(),
+            Some(Value::Number(n)) => n.as_f64() != Some(0.0),
+            Some(_) => true,
+        }
+    }
+}
+
+/// Parse a YAML scalar, keeping anything else as a string.
+fn parse_scalar(text: &str) -> Value {
+    match serde_yaml::from_str(text) {
+        Ok(value @ (Value::Bool(_) | Value::Number(_) | Value::String(_))) => value,
+        _ => Value::String(text.to_owned()),
+    }
+}
+
+/// Apply the templates of a config written in `format`.
+///
+/// Returns the text unchanged if it has no templates, so that diagnostics
+/// point into the original file, or if it cannot be parsed, which the
+/// validation reports.
+pub fn render<'a>(
+    format: Format,
+    text: &'a str,
+    vars: &Vars,
+) -> Result<Cow<'a, str>, TemplateError> {
+    let Ok(value) = format.parse_value(text) else {
+        return Ok(Cow::Borrowed(text));
+    };
+
+    let rendered = apply(&value, vars, &mut Vec::new())?;
+    if rendered == value {
+        return Ok(Cow::Borrowed(text));
+    }
+
+    format
+        .value_to_string(&rendered)
+        .map(Cow::Owned)
+        .map_err(|e| TemplateError::Write(e.to_string()))
+}
+
+/// Whether a config has placeholders or conditional blocks.
+pub fn has_templates(value: &Value) -> bool {
+    match value {
+        Value::String(s) => parse_pieces(s).is_ok_and(|pieces| {
+            pieces
+                .iter()
+                .any(|piece| matches!(piece, Piece::Var { .. }))
+        }),
+        Value::Sequence(items) => items.iter().any(has_templates),
+        Value::Mapping(mapping) => mapping.iter().any(|(key, value)| {
+            key.as_str().and_then(Condition::parse).is_some() || has_templates(value)
+        }),
+        Value::Tagged(tagged) => has_templates(&tagged.value),
+        _ => false,
+    }
+}
+
+/// Apply the templates of `value`, at `path`.
+fn apply(value: &Value, vars: &Vars, path: &mut Vec<String>) -> Result<Value, TemplateError> {
+    Ok(match value {
+        Value::String(s) => apply_string(s, vars, path)?,
+        Value::Sequence(items) => {
+            let mut rendered = Vec::new();
+            for (i, item) in items.iter().enumerate() {
+                path.push(format!("[{}]", i));
+                match conditional_items(item, path)? {
+                    Some((condition, items)) => {
+                        if condition.holds(vars) {
+                            for item in items {
+                                rendered.push(apply(item, vars, path)?);
+                            }
+                        }
+                    }
+                    None => rendered.push(apply(item, vars, path)?),
+                }
+                path.pop();
+            }
+            Value::Sequence(rendered)
+        }
+        Value::Mapping(mapping) => Value::Mapping(apply_mapping(mapping, vars, path)?),
+        Value::Tagged(tagged) => {
+            let mut tagged = tagged.clone();
+            tagged.value = apply(&tagged.value, vars, path)?;
+            Value::Tagged(tagged)
+        }
+        value => value.clone(),
+    })
+}
+
+fn apply_mapping(
+    mapping: &Mapping,
+    vars: &Vars,
+    path: &mut Vec<String>,
+) -> Result<Mapping, TemplateError> {
+    let mut rendered = Mapping::new();
+    for (key, value) in mapping {
+        let name = key.as_str().map(str::to_owned).unwrap_or_default();
+        path.push(name.clone());
+
+        match Condition::parse(&name) {
+            Some(condition) => {
+                let Value::Mapping(block) = value else {
+                    return Err(syntax_error(path, "a conditional block must be a mapping."));
+                };
+                if condition.holds(vars) {
+                    path.pop();
+                    // The entries of the block belong to the parent.
+                    rendered.extend(apply_mapping(block, vars, path)?);
+                    continue;
+                }
+            }
+            None => {
+                rendered.insert(key.clone(), apply(value, vars, path)?);
+            }
+        }
+        path.pop();
+    }
+
+    Ok(rendered)
+}
+
+/// The condition and the items of a conditional sequence item.
+fn conditional_items<'v>(
+    item: &'v Value,
+    path: &[String],
+) -> Result<Option<(Condition<'v>, &'v [Value])>, TemplateError> {
+    let Value::Mapping(mapping) = item else {
+        return Ok(None);
+    };
+    let mut entries = mapping.iter();
+    let (Some((key, value)), None) = (entries.next(), entries.next()) else {
+        return Ok(None);
+    };
+    let Some(condition) = key.as_str().and_then(Condition::parse) else {
+        return Ok(None);
+    };
+
+    match value {
+        Value::Sequence(items) => Ok(Some((condition, items))),
+        _ => Err(syntax_error(
+            path,
+            "a conditional item must hold a sequence.",
+        )),
+    }
+}
+
+/// The condition of an `if name` or `if !name` key.
+pub(crate) struct Condition<'a> {
+    name: &'a str,
+    negated: bool,
+}
+
+impl<'a> Condition<'a> {
+    pub(crate) fn parse(key: &'a str) -> Option<Self> {
+        let name = key.strip_prefix("if ")?.trim();
+        Some(match name.strip_prefix('!') {
+            Some(name) => Self {
+                name: name.trim(),
+                negated: true,
+            },
+            None => Self {
+                name,
+                negated: false,
+            },
+        })
+    }
+
+    pub(crate) fn holds(&self, vars: &Vars) -> bool {
+        vars.is_truthy(self.name) != self.negated
+    }
+}
+
+/// A piece of a templated string.
+enum Piece<'a> {
+    Text(Cow<'a, str>),
+    Var {
+        name: &'a str,
+        default: Option<&'a str>,
+    },
+}
+
+/// Apply the placeholders of a string, at `path`.
+pub(crate) fn apply_string(
+    text: &str,
+    vars: &Vars,
+    path: &[String],
+) -> Result<Value, TemplateError> {
+    let pieces = parse_pieces(text).map_err(|reason| syntax_error(path, &reason))?;
+
+    let lookup = |name: &str, default: Option<&str>| {
+        vars.get(name)
+            .or_else(|| default.map(parse_scalar))
+            .ok_or_else(|| TemplateError::Unset {
+                name: name.to_owned(),
+                path: join(path),
+                suggestion: suggest::closest(name, vars.values.keys().map(String::as_str))
+                    .map(str::to_owned),
+            })
+    };
+
+    match render_pieces(&pieces, lookup, path) {
+        Err(TemplateError::Unset { .. }) if vars.keep_unset => Ok(Value::String(text.to_owned())),
+        result => result,
+    }
+}
+
+fn render_pieces(
+    pieces: &[Piece<'_>],
+    lookup: impl Fn(&str, Option<&str>) -> Result<Value, TemplateError>,
+    path: &[String],
+) -> Result<Value, TemplateError> {
+    // A string of a single placeholder takes the type of the variable.
+    if let [Piece::Var { name, default }] = pieces {
+        return lookup(name, *default);
+    }
+
+    let mut rendered = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => rendered.push_str(text),
+            Piece::Var { name, default } => match lookup(name, *default)? {
+                Value::String(s) => rendered.push_str(&s),
+                Value::Number(n) => rendered.push_str(&n.to_string()),
+                Value::Bool(b) => rendered.push_str(&b.to_string()),
+                Value::Null => {}
+                _ => {
+                    return Err(TemplateError::NotScalar {
+                        name: (*name).to_owned(),
+                        path: join(path),
+                    })
+                }
+            },
+        }
+    }
+
+    Ok(Value::String(rendered))
+}
+
+/// Split a string into text and placeholders.
+fn parse_pieces(text: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        if start > 0 {
+            pieces.push(Piece::Text(Cow::Borrowed(&rest[..start])));
+        }
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+
+        // `{{` and `}}` are escaped braces, a lone `}` is kept as is.
+        if brace == "}" || rest.starts_with('{') && brace == "{" {
+            pieces.push(Piece::Text(Cow::Borrowed(brace)));
+            rest = rest.strip_prefix(brace).unwrap_or(rest);
+            continue;
+        }
+
+        let end = rest
+            .find('}')
+            .ok_or_else(|| format!("\"{{\" is not closed in \"{}\".", text))?;
+        let (name, default) = match rest[..end].split_once('|') {
+            Some((name, default)) => (name.trim(), Some(default.trim())),
+            None => (rest[..end].trim(), None),
+        };
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+        {
+            return Err(format!(
+                "\"{}\" is not a valid variable name, use {{{{ and }}}} for literal braces.",
+                name
+            ));
+        }
+        pieces.push(Piece::Var { name, default });
+        rest = &rest[end + 1..];
+    }
+
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(Cow::Borrowed(rest)));
+    }
+    Ok(pieces)
+}
+
+/// Write a path as `layers[1].style`.
+fn join(path: &[String]) -> String {
+    let mut joined = String::new();
+    for segment in path {
+        if !joined.is_empty() && !segment.starts_with('[') {
+            joined.push('.');
+        }
+        joined.push_str(segment);
+    }
+    joined
+}
+
+pub(crate) fn syntax_error(path: &[String], reason: &str) -> TemplateError {
+    TemplateError::Syntax {
+        path: join(path),
+        reason: reason.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(args: &[&str]) -> Vars {
+        let mut vars = Vars::default();
+        for arg in args {
+            vars.insert_arg(arg).unwrap();
+        }
+        vars
+    }
+
+    fn render_yaml(text: &str, vars: &Vars) -> Result<String, TemplateError> {
+        render(Format::Yaml, text, vars).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn typed_variables() {
+        let text = "styles:\n  img: { image: \"{dir}/{name}.png\", round: \"{round}\" }\n";
+        let vars = vars(&["dir=assets", "name=a", "round=10", "unused=1"]);
+        let rendered = render_yaml(text, &vars).unwrap();
+        assert_eq!(
+            rendered,
+            "styles:\n  img:\n    image: assets/a.png\n    round: 10\n"
+        );
+        assert!(vars.is_used("name"));
+        assert!(!vars.is_used("unused"));
+
+        let text = "styles:\n  bg: { color: !Pure \"{bg|white}\", round: \"{round|0}\" }\n";
+        let rendered = render_yaml(text, &Vars::default()).unwrap();
+        assert_eq!(
+            rendered,
+            "styles:\n  bg:\n    color: !Pure white\n    round: 0\n"
+        );
+
+        // Braces of flow mappings and escaped braces are not placeholders.
+        let text = "styles: { img: { image: \"{{image}}\" } }\n";
+        let rendered = render_yaml(text, &Vars::default()).unwrap();
+        assert_eq!(rendered, "styles:\n  img:\n    image: '{image}'\n");
+
+        // Configs without templates are kept as they are.
+        let text = "styles: { img: { round: 1 } }  # comment\n";
+        assert_eq!(render_yaml(text, &Vars::default()).unwrap(), text);
+    }
+
+    #[test]
+    fn environment_variables() {
+        let text = "styles:\n  img:\n    round: \"{env.ROUND|0}\"\n";
+        let env = Vars::default().with_env(&[("ROUND", "12")]);
+        let rendered = render_yaml(text, &env).unwrap();
+        assert_eq!(rendered, "styles:\n  img:\n    round: 12\n");
+
+        let rendered = render_yaml(text, &Vars::default().with_env(&[])).unwrap();
+        assert_eq!(rendered, "styles:\n  img:\n    round: 0\n");
+    }
+
+    #[test]
+    fn conditionals() {
+        let text = r#"
+styles:
+  img:
+    round: 5
+    if shadow:
+      shadow: { x: 5, y: 5, blur: 7 }
+    if !shadow:
+      round: 0
+layers:
+  - { ty: Background, id: bg, style: bg }
+  - if logo:
+    - { ty: Image, id: logo, style: logo }
+"#;
+        let with = render_yaml(text, &vars(&["shadow=true", "logo=1"])).unwrap();
+        assert_eq!(
+            with,
+            "styles:\n  img:\n    round: 5\n    shadow:\n      x: 5\n      y: 5\n      blur: 7\nlayers:\n- ty: Background\n  id: bg\n  style: bg\n- ty: Image\n  id: logo\n  style: logo\n"
+        );
+
+        let without = render_yaml(text, &vars(&["shadow=false"])).unwrap();
+        assert_eq!(
+            without,
+            "styles:\n  img:\n    round: 0\nlayers:\n- ty: Background\n  id: bg\n  style: bg\n"
+        );
+    }
+
+    #[test]
+    fn other_formats() {
+        let text = "[styles.img]\nimage = \"{image}\"\nround = \"{round}\"\n";
+        let rendered = render(Format::Toml, text, &vars(&["image=a.png", "round=3"])).unwrap();
+        assert_eq!(rendered, "[styles.img]\nimage = \"a.png\"\nround = 3\n");
+
+        let text = r#"{"styles": {"img": {"if big": {"round": 30}}}}"#;
+        let rendered = render(Format::Json, text, &vars(&["big=yes"])).unwrap();
+        assert!(rendered.contains(r#""round": 30"#), "{}", rendered);
+        assert!(Format::Json.parse(&rendered).is_ok());
+    }
+
+    #[test]
+    fn template_errors() {
+        let text = "styles:\n  img:\n    image: \"{imag}\"\n";
+        assert_eq!(
+            render_yaml(text, &vars(&["image=a.png"]))
+                .unwrap_err()
+                .to_string(),
+            r#"Variable "imag" is not set, used at "styles.img.image". Did you mean "image"?"#
+        );
+        let kept = render_yaml(text, &vars(&["image=a.png"]).keep_unset()).unwrap();
+        assert_eq!(kept, text);
+
+        let text = "styles:\n  img:\n    image: \"{image\"\n";
+        assert_eq!(
+            render_yaml(text, &Vars::default()).unwrap_err().to_string(),
+            r#"Invalid template at "styles.img.image": "{" is not closed in "{image"."#
+        );
+
+        let text = "styles:\n  img:\n    if shadow: true\n";
+        assert_eq!(
+            render_yaml(text, &vars(&["shadow=1"]))
+                .unwrap_err()
+                .to_string(),
+            r#"Invalid template at "styles.img.if shadow": a conditional block must be a mapping."#
+        );
+
+        let mut vars = Vars::default();
+        vars.insert("list", Value::Sequence(vec![]));
+        let text = "styles:\n  img:\n    image: \"{list}.png\"\n";
+        assert_eq!(
+            render_yaml(text, &vars).unwrap_err().to_string(),
+            r#"Variable "list" is not a scalar, it cannot be part of the string at "styles.img.image"."#
+        );
+    }
+}
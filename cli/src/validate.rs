@@ -0,0 +1,1111 @@
+//! Validation of config files, with source locations.
+//!
+//! The config is parsed into a tree of nodes that remember their position,
+//! then every style field and every layer is type-checked on its own against
+//! the engine types, so that all problems are reported at once.
+//! Finally, the fields that passed are checked together with
+//! [`Config::check`] for semantic errors.
+//!
+//! A config that is merged with other configs is partial: the semantic
+//! checks only make sense for the merged config, so they are skipped.
+//!
+//! The templates are applied to the tree, see [`crate::template`], so the
+//! problems of a templated config point into the original file.
+
+use std::fmt;
+
+use footlights_engine::configs::{
+    structure::{Layer, Structure},
+    style::{Style, StyleCollection},
+    Config, ConfigError,
+};
+use serde_yaml::{value::TaggedValue, Mapping, Value};
+use yaml_rust2::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::{Marker, TScalarStyle},
+};
+
+use crate::{
+    format::Format,
+    suggest,
+    template::{self, Condition, TemplateError, Vars},
+};
+
+/// The severity of a [`Diagnostic`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The config cannot be rendered.
+    Error,
+    /// The config can be rendered, but is probably not what the user meant.
+    Warning,
+}
+
+/// A problem found in a config file.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// The severity of the problem.
+    pub severity: Severity,
+    /// The line of the problem, starting from 1, or 0 if it is unknown.
+    pub line: usize,
+    /// The column of the problem, starting from 1, or 0 if it is unknown.
+    pub column: usize,
+    /// The description of the problem.
+    pub message: String,
+}
+
+/// All the problems found in a config file.
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The name of the config file.
+    pub file: String,
+    /// The problems, in the order they are found.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    /// The number of problems of the severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .count()
+    }
+
+    /// Whether the config cannot be rendered.
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for d in &self.diagnostics {
+            let severity = match d.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            if d.line == 0 {
+                writeln!(f, "{}: {}: {}", self.file, severity, d.message)?;
+            } else {
+                writeln!(
+                    f,
+                    "{}:{}:{}: {}: {}",
+                    self.file, d.line, d.column, severity, d.message
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validate the config `text` of any format, read from `file`, applying its
+/// templates with `vars`.
+///
+/// A `partial` config is merged with other configs, so it does not need to
+/// be complete, and neither does a config with includes.
+///
+/// YAML configs get every check with locations. Other formats report the
+/// first parse error, then the semantic errors without locations.
+pub fn validate_format(
+    format: Format,
+    file: &str,
+    text: &str,
+    partial: bool,
+    vars: Option<&Vars>,
+) -> Report {
+    if format == Format::Yaml {
+        return run(file, text, partial, vars);
+    }
+
+    let error = |(line, column), message| Diagnostic {
+        severity: Severity::Error,
+        line,
+        column,
+        message,
+    };
+    let report = |diagnostics| Report {
+        file: file.to_owned(),
+        diagnostics,
+    };
+    // A text that cannot be parsed is returned as is, so the parse errors
+    // keep their locations.
+    let text = match vars.map(|vars| template::render(format, text, vars)) {
+        Some(Ok(rendered)) => rendered,
+        Some(Err(e)) => return report(vec![error((0, 0), e.to_string())]),
+        None => text.into(),
+    };
+    let diagnostics = match format.parse(&text) {
+        Err(e) => vec![error(
+            e.location(&text).unwrap_or((0, 0)),
+            format!("Invalid {}: {}", format, e.message()),
+        )],
+        Ok(config) if partial || !config.include.is_empty() => Vec::new(),
+        Ok(config) => config
+            .check()
+            .into_iter()
+            .map(|e| error((0, 0), e.to_string()))
+            .collect(),
+    };
+
+    report(diagnostics)
+}
+
+fn run(file: &str, text: &str, partial: bool, vars: Option<&Vars>) -> Report {
+    let mut validator = Validator {
+        partial,
+        vars,
+        ..Default::default()
+    };
+    validator.validate(text);
+
+    Report {
+        file: file.to_owned(),
+        diagnostics: validator.diagnostics,
+    }
+}
+
+/// A YAML node with its position in the source.
+#[derive(Debug, Clone)]
+struct Node {
+    kind: NodeKind,
+    tag: Option<String>,
+    mark: Marker,
+}
+
+#[derive(Debug, Clone)]
+enum NodeKind {
+    Scalar(String, TScalarStyle),
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+    /// Aliases are kept unresolved, they are rare in configs.
+    Alias,
+}
+
+impl Node {
+    fn as_str(&self) -> Option<&str> {
+        match &self.kind {
+            NodeKind::Scalar(s, _) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn entries(&self) -> &[(Node, Node)] {
+        match &self.kind {
+            NodeKind::Mapping(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    /// Get the entry of a mapping by key.
+    fn entry(&self, key: &str) -> Option<&(Node, Node)> {
+        self.entries().iter().find(|(k, _)| k.as_str() == Some(key))
+    }
+
+    fn kind_name(&self) -> &'static str {
+        match &self.kind {
+            NodeKind::Scalar(..) => "a scalar",
+            NodeKind::Sequence(_) => "a sequence",
+            NodeKind::Mapping(_) => "a mapping",
+            NodeKind::Alias => "an alias",
+        }
+    }
+
+    /// Find the first node in the subtree matching `pred`, in source order.
+    fn find(&self, pred: &dyn Fn(&Node) -> bool) -> Option<&Node> {
+        if pred(self) {
+            return Some(self);
+        }
+        match &self.kind {
+            NodeKind::Sequence(items) => items.iter().find_map(|item| item.find(pred)),
+            NodeKind::Mapping(entries) => entries
+                .iter()
+                .find_map(|(k, v)| k.find(pred).or_else(|| v.find(pred))),
+            _ => None,
+        }
+    }
+
+    /// A node holding `value`, at `mark`.
+    fn from_value(value: &Value, mark: Marker) -> Node {
+        let kind = match value {
+            Value::Tagged(tagged) => {
+                let node = Node::from_value(&tagged.value, mark);
+                let tag = tagged.tag.to_string();
+                return Node {
+                    tag: Some(tag.trim_start_matches('!').to_owned()),
+                    ..node
+                };
+            }
+            Value::String(s) => NodeKind::Scalar(s.clone(), TScalarStyle::DoubleQuoted),
+            Value::Sequence(items) => NodeKind::Sequence(
+                items
+                    .iter()
+                    .map(|item| Node::from_value(item, mark))
+                    .collect(),
+            ),
+            Value::Mapping(mapping) => NodeKind::Mapping(
+                mapping
+                    .iter()
+                    .map(|(k, v)| (Node::from_value(k, mark), Node::from_value(v, mark)))
+                    .collect(),
+            ),
+            // Null, booleans and numbers are resolved again by `to_value`.
+            scalar => {
+                let text = serde_yaml::to_string(scalar).unwrap_or_default();
+                NodeKind::Scalar(text.trim_end().to_owned(), TScalarStyle::Plain)
+            }
+        };
+
+        Node {
+            kind,
+            tag: None,
+            mark,
+        }
+    }
+
+    /// Convert the node into a [`Value`] for deserialization.
+    fn to_value(&self) -> Value {
+        let value = match &self.kind {
+            NodeKind::Scalar(s, TScalarStyle::Plain) => {
+                // Let serde_yaml resolve the plain scalar into null/bool/number/string.
+                serde_yaml::from_str(s).unwrap_or_else(|_| Value::String(s.clone()))
+            }
+            NodeKind::Scalar(s, _) => Value::String(s.clone()),
+            NodeKind::Sequence(items) => {
+                Value::Sequence(items.iter().map(Node::to_value).collect())
+            }
+            NodeKind::Mapping(entries) => Value::Mapping(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.to_value(), v.to_value()))
+                    .collect(),
+            ),
+            NodeKind::Alias => Value::Null,
+        };
+
+        match &self.tag {
+            Some(tag) => Value::Tagged(Box::new(TaggedValue {
+                tag: serde_yaml::value::Tag::new(tag),
+                value,
+            })),
+            None => value,
+        }
+    }
+}
+
+/// Builds a tree of [`Node`] from the parser events.
+#[derive(Default)]
+struct TreeBuilder {
+    /// The unfinished sequences and mappings.
+    stack: Vec<(Node, Vec<Node>)>,
+    root: Option<Node>,
+}
+
+impl TreeBuilder {
+    fn push(&mut self, node: Node) {
+        match self.stack.last_mut() {
+            Some((_, children)) => children.push(node),
+            // Only the first document is used.
+            None if self.root.is_none() => self.root = Some(node),
+            None => {}
+        }
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        let tag_name = |tag: Option<yaml_rust2::parser::Tag>| {
+            tag.map(|tag| {
+                if tag.handle == "!" {
+                    tag.suffix
+                } else {
+                    format!("{}{}", tag.handle, tag.suffix)
+                }
+            })
+        };
+
+        match ev {
+            Event::Scalar(value, style, _, tag) => self.push(Node {
+                kind: NodeKind::Scalar(value, style),
+                tag: tag_name(tag),
+                mark,
+            }),
+            Event::Alias(_) => self.push(Node {
+                kind: NodeKind::Alias,
+                tag: None,
+                mark,
+            }),
+            Event::SequenceStart(_, tag) => self.stack.push((
+                Node {
+                    kind: NodeKind::Sequence(Vec::new()),
+                    tag: tag_name(tag),
+                    mark,
+                },
+                Vec::new(),
+            )),
+            Event::MappingStart(_, tag) => self.stack.push((
+                Node {
+                    kind: NodeKind::Mapping(Vec::new()),
+                    tag: tag_name(tag),
+                    mark,
+                },
+                Vec::new(),
+            )),
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some((mut node, children)) = self.stack.pop() {
+                    node.kind = match node.kind {
+                        NodeKind::Sequence(_) => NodeKind::Sequence(children),
+                        _ => {
+                            let mut children = children.into_iter();
+                            let mut entries = Vec::new();
+                            while let (Some(k), Some(v)) = (children.next(), children.next()) {
+                                entries.push((k, v));
+                            }
+                            NodeKind::Mapping(entries)
+                        }
+                    };
+                    self.push(node);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A template error, at the node that has it.
+type TemplateResult<T> = Result<T, (Marker, TemplateError)>;
+
+/// Apply the templates of the tree at `path`, like [`template::render`].
+///
+/// The nodes keep their position, and the value of a placeholder gets the
+/// position of the placeholder.
+fn apply_templates(node: &Node, vars: &Vars, path: &mut Vec<String>) -> TemplateResult<Node> {
+    let kind = match &node.kind {
+        NodeKind::Scalar(..) => {
+            let untagged = Node {
+                tag: None,
+                ..node.clone()
+            };
+            let Value::String(s) = untagged.to_value() else {
+                return Ok(node.clone());
+            };
+            let value = template::apply_string(&s, vars, path).map_err(|e| (node.mark, e))?;
+            if value == Value::String(s) {
+                return Ok(node.clone());
+            }
+            let rendered = Node::from_value(&value, node.mark);
+            return Ok(Node {
+                tag: node.tag.clone().or(rendered.tag),
+                ..rendered
+            });
+        }
+        NodeKind::Sequence(items) => {
+            let mut rendered = Vec::new();
+            for (i, item) in items.iter().enumerate() {
+                path.push(format!("[{}]", i));
+                match conditional_items(item, path)? {
+                    Some((condition, items)) => {
+                        if condition.holds(vars) {
+                            for item in items {
+                                rendered.push(apply_templates(item, vars, path)?);
+                            }
+                        }
+                    }
+                    None => rendered.push(apply_templates(item, vars, path)?),
+                }
+                path.pop();
+            }
+            NodeKind::Sequence(rendered)
+        }
+        NodeKind::Mapping(entries) => NodeKind::Mapping(apply_entries(entries, vars, path)?),
+        NodeKind::Alias => NodeKind::Alias,
+    };
+
+    Ok(Node {
+        kind,
+        ..node.clone()
+    })
+}
+
+fn apply_entries(
+    entries: &[(Node, Node)],
+    vars: &Vars,
+    path: &mut Vec<String>,
+) -> TemplateResult<Vec<(Node, Node)>> {
+    // A later entry replaces the entry with the same key, like in a mapping.
+    fn insert(entries: &mut Vec<(Node, Node)>, (key, value): (Node, Node)) {
+        match entries
+            .iter_mut()
+            .find(|(k, _)| k.as_str().is_some() && k.as_str() == key.as_str())
+        {
+            Some(entry) => entry.1 = value,
+            None => entries.push((key, value)),
+        }
+    }
+
+    let mut rendered = Vec::new();
+    for (key, value) in entries {
+        let name = key.as_str().unwrap_or_default();
+        path.push(name.to_owned());
+
+        match Condition::parse(name) {
+            Some(condition) => {
+                let NodeKind::Mapping(block) = &value.kind else {
+                    let reason = "a conditional block must be a mapping.";
+                    return Err((value.mark, template::syntax_error(path, reason)));
+                };
+                if condition.holds(vars) {
+                    path.pop();
+                    // The entries of the block belong to the parent.
+                    for entry in apply_entries(block, vars, path)? {
+                        insert(&mut rendered, entry);
+                    }
+                    continue;
+                }
+            }
+            None => insert(
+                &mut rendered,
+                (key.clone(), apply_templates(value, vars, path)?),
+            ),
+        }
+        path.pop();
+    }
+
+    Ok(rendered)
+}
+
+/// The condition and the items of a conditional sequence item.
+fn conditional_items<'n>(
+    item: &'n Node,
+    path: &[String],
+) -> TemplateResult<Option<(Condition<'n>, &'n [Node])>> {
+    let [(key, value)] = item.entries() else {
+        return Ok(None);
+    };
+    let Some(condition) = key.as_str().and_then(Condition::parse) else {
+        return Ok(None);
+    };
+
+    match &value.kind {
+        NodeKind::Sequence(items) => Ok(Some((condition, items))),
+        _ => Err((
+            value.mark,
+            template::syntax_error(path, "a conditional item must hold a sequence."),
+        )),
+    }
+}
+
+#[derive(Default)]
+struct Validator<'a> {
+    diagnostics: Vec<Diagnostic>,
+    /// Skip the checks that need a complete config.
+    partial: bool,
+    /// The variables of the templates, or `None` to check the text as is.
+    vars: Option<&'a Vars>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, severity: Severity, mark: &Marker, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            line: mark.line(),
+            column: mark.col() + 1,
+            message,
+        });
+    }
+
+    fn error(&mut self, mark: &Marker, message: String) {
+        self.report(Severity::Error, mark, message);
+    }
+
+    fn validate(&mut self, text: &str) {
+        let mut builder = TreeBuilder::default();
+        if let Err(e) = Parser::new_from_str(text).load(&mut builder, false) {
+            self.error(e.marker(), format!("Invalid YAML: {}", e.info()));
+            return;
+        }
+
+        let root = match (builder.root, self.vars) {
+            (Some(root), Some(vars)) => match apply_templates(&root, vars, &mut Vec::new()) {
+                Ok(root) => root,
+                Err((mark, e)) => {
+                    self.error(&mark, e.to_string());
+                    return;
+                }
+            },
+            (Some(root), None) => root,
+            (None, _) => {
+                self.diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    line: 1,
+                    column: 1,
+                    message: "The config is empty.".to_owned(),
+                });
+                return;
+            }
+        };
+        if !matches!(root.kind, NodeKind::Mapping(_)) {
+            self.error(
+                &root.mark,
+                format!("The config must be a mapping, found {}.", root.kind_name()),
+            );
+            return;
+        }
+
+        // Only check the root keys here, the values are checked below.
+        for (key, _) in root.entries() {
+            match key.as_str() {
+                Some("include" | "styles" | "layers") => {}
+                Some(name) => {
+                    let message =
+                        unknown_key_message("key", name, &["include", "styles", "layers"]);
+                    self.error(&key.mark, message);
+                }
+                None => self.error(&key.mark, "Config keys must be strings.".to_owned()),
+            }
+        }
+
+        if let Some((_, include)) = root.entry("include") {
+            if serde_yaml::from_value::<Vec<String>>(include.to_value()).is_err() {
+                self.error(
+                    &include.mark,
+                    "\"include\" must be a sequence of file paths.".to_owned(),
+                );
+            }
+            // The included configs complete this one.
+            self.partial = true;
+        }
+
+        let styles = match root.entry("styles") {
+            Some((_, styles)) => self.check_styles(styles),
+            None if self.partial => StyleCollection::default(),
+            None => {
+                self.error(&root.mark, "Missing key \"styles\".".to_owned());
+                return;
+            }
+        };
+        let layers = match root.entry("layers") {
+            Some((_, layers)) => self.check_layers(layers).map(Some),
+            None => Some(None),
+        };
+
+        // Semantic checks need the whole structure, skip them if it is broken.
+        if let (Some(layers), false) = (layers, self.partial) {
+            let config = Config {
+                include: Vec::new(),
+                styles,
+                layers,
+            };
+            self.check_config(&root, &config);
+        }
+    }
+
+    /// Check every style field on its own.
+    ///
+    /// Returns the styles with the fields that passed the check.
+    fn check_styles(&mut self, styles: &Node) -> StyleCollection {
+        let mut collection = StyleCollection::default();
+
+        if !matches!(styles.kind, NodeKind::Mapping(_)) {
+            self.error(
+                &styles.mark,
+                format!(
+                    "\"styles\" must be a mapping of style ids to styles, found {}.",
+                    styles.kind_name()
+                ),
+            );
+            return collection;
+        }
+
+        for (id, style) in styles.entries() {
+            let id = match id.as_str() {
+                Some(id) => id,
+                None => {
+                    self.error(&id.mark, "Style ids must be strings.".to_owned());
+                    continue;
+                }
+            };
+            if !matches!(style.kind, NodeKind::Mapping(_)) {
+                self.error(
+                    &style.mark,
+                    format!(
+                        "Style \"{}\" must be a mapping of fields, found {}.",
+                        id,
+                        style.kind_name()
+                    ),
+                );
+                continue;
+            }
+
+            let mut valid = Mapping::new();
+            for (field, value) in style.entries() {
+                let mut single = Mapping::new();
+                single.insert(field.to_value(), value.to_value());
+
+                match serde_yaml::from_value::<Style>(Value::Mapping(single.clone())) {
+                    Ok(_) => valid.extend(single),
+                    Err(e) => {
+                        let message = e.to_string();
+                        let mark = locate(field, value, &message);
+                        self.error(mark, format!("In style \"{}\": {}", id, friendly(message)));
+                    }
+                }
+            }
+
+            if let Ok(style) = serde_yaml::from_value::<Style>(Value::Mapping(valid)) {
+                collection.add(id.to_owned(), style);
+            }
+        }
+
+        collection
+    }
+
+    /// Check every layer on its own.
+    ///
+    /// Returns the structure if every layer passed the check.
+    fn check_layers(&mut self, layers: &Node) -> Option<Structure> {
+        let items = match &layers.kind {
+            NodeKind::Sequence(items) => items,
+            _ => {
+                self.error(
+                    &layers.mark,
+                    format!(
+                        "\"layers\" must be a sequence, found {}.",
+                        layers.kind_name()
+                    ),
+                );
+                return None;
+            }
+        };
+
+        let mut valid = Vec::new();
+        for (i, item) in items.iter().enumerate() {
+            match serde_yaml::from_value::<Layer>(item.to_value()) {
+                Ok(layer) => valid.push(layer),
+                Err(e) => {
+                    let message = e.to_string();
+                    let mark = locate(item, item, &message);
+                    self.error(mark, format!("In layer {}: {}", i, friendly(message)));
+                }
+            }
+        }
+
+        (valid.len() == items.len()).then(|| Structure::from_vec(valid))
+    }
+
+    /// Report the semantic errors of the config at the closest location.
+    fn check_config(&mut self, root: &Node, config: &Config) {
+        let styles = root.entry("styles").map(|(_, styles)| styles);
+        let layers = root.entry("layers").map(|(_, layers)| layers);
+
+        let style_key = |id: &str| styles.and_then(|styles| styles.entry(id)).map(|(k, _)| k);
+        let style_node = |id: &str| styles.and_then(|styles| styles.entry(id)).map(|(_, v)| v);
+        // The last layer with the id, so that duplicates point at the second one.
+        let layer_node = |id: &str| match layers.map(|layers| &layers.kind) {
+            Some(NodeKind::Sequence(items)) => items
+                .iter()
+                .rev()
+                .find(|item| matches!(item.entry("id"), Some((_, v)) if v.as_str() == Some(id))),
+            _ => None,
+        };
+        let extends_node = |id: &str| {
+            style_node(id)
+                .and_then(|style| style.entry("extends"))
+                .map(|(_, v)| v)
+        };
+        // Fall back to the "layers" key, or the "styles" key for the default layers.
+        let fallback = layers.or(styles).map_or(root.mark, |node| node.mark);
+
+        for error in config.check() {
+            let mark = match &error {
+                ConfigError::UnknownStyle(path) => layer_node(&path.layer)
+                    .and_then(|layer| layer.entry("style"))
+                    .map(|(_, v)| v.mark),
+                ConfigError::DuplicateLayerId(id) => layer_node(id)
+                    .and_then(|layer| layer.entry("id"))
+                    .map(|(_, v)| v.mark),
+                ConfigError::MissingField { path, .. } => style_key(&path.style).map(|k| k.mark),
+                // The field may be set by a CSS declaration.
+                ConfigError::UnexpectedField { path, field, .. } => style_node(&path.style)
+                    .and_then(|style| style.entry(field).or_else(|| style.entry("css")))
+                    .map(|(k, _)| k.mark),
+                ConfigError::InvalidValue { path, field, .. } => {
+                    style_node(&path.style).map(|style| {
+                        // Descend as deep as possible into the dotted field path.
+                        let mut node = style;
+                        for segment in field.split('.') {
+                            match node.entry(segment) {
+                                Some((_, v)) => node = v,
+                                None => break,
+                            }
+                        }
+                        node.mark
+                    })
+                }
+                ConfigError::ImageSize { path, .. } => style_key(&path.style).map(|k| k.mark),
+                ConfigError::UnknownParent { style, parent } => {
+                    extends_node(style).map(|extends| {
+                        // Point at the missing id in a list of parents.
+                        match &extends.kind {
+                            NodeKind::Sequence(items) => items
+                                .iter()
+                                .find(|item| item.as_str() == Some(parent))
+                                .map_or(extends.mark, |item| item.mark),
+                            _ => extends.mark,
+                        }
+                    })
+                }
+                ConfigError::InheritanceCycle(cycle) => extends_node(&cycle[0]).map(|v| v.mark),
+                // The color may be inherited, then point at the style.
+                ConfigError::UnknownPreset { style, .. } => style_node(style)
+                    .and_then(|node| node.entry("color"))
+                    .map(|(_, v)| v.mark)
+                    .or_else(|| style_key(style).map(|k| k.mark)),
+                ConfigError::CssConflict { style, .. } => style_node(style)
+                    .and_then(|style| style.entry("css"))
+                    .map(|(k, _)| k.mark),
+            };
+
+            self.error(&mark.unwrap_or(fallback), error.to_string());
+        }
+
+        for id in config.unreferenced_styles() {
+            if let Some(key) = style_key(id) {
+                self.report(
+                    Severity::Warning,
+                    &key.mark,
+                    format!("Style \"{}\" is not used by any layer.", id),
+                );
+            }
+        }
+    }
+}
+
+/// Rewrite a serde message, with a suggestion for unknown fields and
+/// variants at any depth.
+fn friendly(message: String) -> String {
+    for what in ["field", "variant"] {
+        if let Some(name) = unknown_name(&message, what) {
+            return unknown_key_message(what, name, &expected_names(&message));
+        }
+    }
+    capitalize(&message)
+}
+
+/// Extract the name from a serde "unknown field/variant `name`" message.
+fn unknown_name<'a>(message: &'a str, what: &str) -> Option<&'a str> {
+    let start = message.find(&format!("unknown {} `", what))? + what.len() + 10;
+    let len = message[start..].find('`')?;
+    Some(&message[start..start + len])
+}
+
+/// Extract the names from the "expected one of `a`, `b`" part of a serde message.
+fn expected_names(message: &str) -> Vec<&str> {
+    match message.find("expected") {
+        Some(start) => message[start..].split('`').skip(1).step_by(2).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Find the node that a serde error message is about.
+///
+/// Unknown fields and variants are searched in the subtree of `value`,
+/// and other errors are reported at `value`.
+fn locate<'a>(key: &'a Node, value: &'a Node, message: &str) -> &'a Marker {
+    if let Some(name) = unknown_name(message, "field") {
+        if key.as_str() == Some(name) {
+            return &key.mark;
+        }
+        let has_key = |node: &Node| node.entry(name).is_some();
+        return match value.find(&has_key).and_then(|node| node.entry(name)) {
+            Some((k, _)) => &k.mark,
+            None => &key.mark,
+        };
+    }
+
+    if let Some(name) = unknown_name(message, "variant") {
+        // A tagged block mapping starts at its first key, so point at the
+        // key holding the tagged value instead, which is on the tag's line.
+        let is_tagged = |node: &Node| node.tag.as_deref() == Some(name);
+        if is_tagged(value) && !std::ptr::eq(key, value) {
+            return &key.mark;
+        }
+        let has_tagged = |node: &Node| node.entries().iter().any(|(_, v)| is_tagged(v));
+        if let Some(node) = value.find(&has_tagged) {
+            if let Some((k, _)) = node.entries().iter().find(|(_, v)| is_tagged(v)) {
+                return &k.mark;
+            }
+        }
+        if let Some(node) = value.find(&|node: &Node| node.as_str() == Some(name)) {
+            return &node.mark;
+        }
+    }
+
+    &value.mark
+}
+
+fn unknown_key_message(what: &str, name: &str, expected_names: &[&str]) -> String {
+    let expected = expected_names
+        .iter()
+        .map(|name| format!("\"{}\"", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match suggest::closest(name, expected_names.iter().copied()) {
+        Some(closest) => format!(
+            "Unknown {} \"{}\", did you mean \"{}\"? Expected one of {}.",
+            what, name, closest, expected
+        ),
+        None => format!(
+            "Unknown {} \"{}\". Expected one of {}.",
+            what, name, expected
+        ),
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(file: &str, text: &str) -> Report {
+        run(file, text, false, None)
+    }
+
+    fn validate_partial(file: &str, text: &str) -> Report {
+        run(file, text, true, None)
+    }
+
+    fn messages(text: &str) -> Vec<String> {
+        validate("config.yaml", text)
+            .to_string()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn valid_config() {
+        let report = validate("basic.yaml", include_str!("../../examples/basic.yaml"));
+        assert!(report.diagnostics.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn report_every_problem() {
+        let text = r#"
+styles:
+  img:
+    image: "{image}"
+    colour: red
+    shadow:
+      x: 5
+      y: 5
+      blurr: 7
+  bg:
+    color: !Linaer
+      stops: []
+      degree: 35.0
+  extra:
+    round: ten
+layer: []
+"#;
+
+        assert_eq!(
+            messages(text),
+            [
+                r#"config.yaml:16:1: error: Unknown key "layer", did you mean "layers"? Expected one of "include", "styles", "layers"."#,
+                r#"config.yaml:5:5: error: In style "img": Unknown field "colour", did you mean "color"? Expected one of "extends", "position", "size", "image", "round", "shadow", "color", "css"."#,
+                r#"config.yaml:9:7: error: In style "img": Unknown field "blurr", did you mean "blur"? Expected one of "x", "y", "blur", "opacity"."#,
+                r#"config.yaml:11:5: error: In style "bg": Unknown variant "Linaer", did you mean "Linear"? Expected one of "Pure", "Linear", "Radial", "Preset"."#,
+                r#"config.yaml:15:12: error: In style "extra": Invalid type: string "ten", expected usize"#,
+                r#"config.yaml:10:3: error: Field "color" is required by layer "bg" (style "bg")."#,
+                r#"config.yaml:14:3: warning: Style "extra" is not used by any layer."#,
+            ]
+        );
+    }
+
+    #[test]
+    fn inheritance_problems() {
+        let text = r#"
+styles:
+  base:
+    round: 3
+  img:
+    extends: [base, shiny]
+    image: a.png
+  bg:
+    extends: bg
+"#;
+
+        assert_eq!(
+            messages(text),
+            [r#"config.yaml:9:14: error: Styles extend each other in a cycle: bg -> bg."#]
+        );
+
+        let text = "styles:\n  img:\n    extends: [base, shiny]\n    image: a.png\n  base: {}\n  bg: { color: !Pure red }\n";
+        assert_eq!(
+            messages(text),
+            [
+                r#"config.yaml:3:21: error: Style "img" extends "shiny", which is neither in the style collection nor a preset."#
+            ]
+        );
+
+        let text =
+            "styles:\n  img: { extends: window, image: a.png }\n  bg: { color: !Preset sunst }\n";
+        assert_eq!(
+            messages(text),
+            [
+                r#"config.yaml:3:24: error: Style "bg" uses the preset "sunst", which is not a gradient preset."#
+            ]
+        );
+    }
+
+    #[test]
+    fn css_problems() {
+        let text = r#"
+styles:
+  img:
+    image: a.png
+    round: 5
+    css: "border-radius: 20px"
+  bg:
+    css: "background: white; margin: 10px"
+"#;
+
+        assert_eq!(
+            messages(text),
+            [
+                r#"config.yaml:8:10: error: In style "bg": Unsupported CSS property "margin", supported properties are "background", "border-radius", "box-shadow" and "padding"."#,
+                r#"config.yaml:6:5: error: Style "img" sets both "round" and the CSS property "border-radius"."#,
+            ]
+        );
+    }
+
+    #[test]
+    fn partial_config() {
+        // The included config sets the image.
+        let text = "include: [base.yaml]\nstyles:\n  img:\n    round: 5\n";
+        assert!(messages(text).is_empty());
+
+        let text = "include: base.yaml\n";
+        assert_eq!(
+            messages(text),
+            [r#"config.yaml:1:10: error: "include" must be a sequence of file paths."#]
+        );
+
+        let report = validate_partial("config.yaml", "styles:\n  img:\n    round: 5\n");
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn other_formats() {
+        let report = validate_format(
+            Format::Toml,
+            "config.toml",
+            "[styles.img]\nround = \"ten\"\n",
+            false,
+            None,
+        );
+        assert_eq!(
+            report.to_string(),
+            "config.toml:2:9: error: Invalid TOML: invalid type: string \"ten\", expected usize\n"
+        );
+
+        let text = r#"{"styles": {"bg": {}, "img": {"image": "a.png"}}}"#;
+        let report = validate_format(Format::Json, "config.json", text, false, None);
+        assert_eq!(
+            report.to_string(),
+            "config.json: error: Field \"color\" is required by layer \"bg\" (style \"bg\").\n"
+        );
+
+        let report = validate_format(Format::Json, "config.json", r#"{"styles": {}}"#, true, None);
+        assert!(report.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn templated_config() {
+        let text = r#"
+styles:
+  img:
+    if shadow:
+      shadow: { x: 1, y: 1, blur: 2 }
+    image: "{image}"
+    round: "{round}"
+    rond: 3
+layers:
+  - { ty: Image, id: img, style: img }
+  - if frame:
+    - { ty: Background, id: frame, style: "{frame}" }
+"#;
+        let validate = |args: &[&str]| {
+            let mut vars = Vars::default().keep_unset();
+            for arg in args {
+                vars.insert_arg(arg).unwrap();
+            }
+            validate_format(Format::Yaml, "config.yaml", text, false, Some(&vars)).to_string()
+        };
+
+        // The problems point into the original file, around the templates.
+        assert_eq!(
+            validate(&["shadow=1", "round=5"]),
+            "config.yaml:8:5: error: In style \"img\": Unknown field \"rond\", did you mean \"round\"? Expected one of \"extends\", \"position\", \"size\", \"image\", \"round\", \"shadow\", \"color\", \"css\".\n"
+        );
+        let report = validate(&["round=big", "frame=missing"]);
+        assert!(
+            report.contains(
+                "config.yaml:7:12: error: In style \"img\": Invalid type: string \"big\""
+            ),
+            "{}",
+            report
+        );
+        assert!(
+            report.contains("config.yaml:12:43: error: Style \"missing\" of layer \"frame\""),
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn layer_problems() {
+        let text = r#"
+styles:
+  bg:
+    color: !Pure red
+    round: 3
+layers:
+  - { ty: Background, id: bg, style: bg }
+  - { ty: Image, id: bg, style: img }
+"#;
+
+        assert_eq!(
+            messages(text),
+            [
+                r#"config.yaml:5:5: error: Field "round" is not supported by Background layers, found in layer "bg" (style "bg")."#,
+                r#"config.yaml:8:22: error: Layer id "bg" is used by more than one layer."#,
+                r#"config.yaml:8:33: error: Style "img" of layer "bg" is not in the style collection."#,
+            ]
+        );
+
+        let text = "styles: {}\nlayers:\n  - { ty: Imag, id: a, style: a }\n";
+        assert_eq!(
+            messages(text),
+            [
+                r#"config.yaml:3:11: error: In layer 0: Unknown variant "Imag", did you mean "Image"? Expected one of "Image", "Background"."#
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_error() {
+        let report = validate("config.yaml", "styles:\n  img: [1, 2\n");
+        assert!(report.has_errors());
+        assert_eq!(report.diagnostics.len(), 1);
+        assert!(report.diagnostics[0].message.starts_with("Invalid YAML"));
+    }
+}
@@ -0,0 +1,169 @@
+//! Polling of the files of a render, for `--watch`.
+//!
+//! The files are compared by modification time and size. A change is
+//! reported once the files have been quiet for a while, so that an editor
+//! saving several files, or writing one in several steps, triggers a single
+//! render.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// What is known of a file: its modification time and size, `None` if it
+/// does not exist.
+type Snapshot = Option<(SystemTime, u64)>;
+
+fn snapshot(path: &PathBuf) -> Snapshot {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Watches a set of files.
+pub struct Watcher {
+    files: BTreeMap<PathBuf, Snapshot>,
+    /// The time between two polls.
+    pub interval: Duration,
+    /// How long the files must be quiet after a change.
+    pub debounce: Duration,
+}
+
+impl Default for Watcher {
+    fn default() -> Self {
+        Self {
+            files: BTreeMap::new(),
+            interval: Duration::from_millis(200),
+            debounce: Duration::from_millis(300),
+        }
+    }
+}
+
+impl Watcher {
+    /// Watch `files`, as they are now.
+    ///
+    /// The files that are already watched keep their last state, so that a
+    /// change during a render is not missed.
+    pub fn watch(&mut self, files: BTreeSet<PathBuf>) {
+        self.files.retain(|path, _| files.contains(path));
+        for path in files {
+            self.files.entry(path).or_insert_with_key(snapshot);
+        }
+    }
+
+    /// The number of watched files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Update the snapshots, and return the files that changed.
+    fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, last) in &mut self.files {
+            let current = snapshot(path);
+            if current != *last {
+                *last = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+
+    /// Block until a file changes, then until the files are quiet.
+    ///
+    /// Returns the files that changed.
+    pub fn wait(&mut self) -> Vec<PathBuf> {
+        let mut changed = loop {
+            let changed = self.poll();
+            if !changed.is_empty() {
+                break changed;
+            }
+            thread::sleep(self.interval);
+        };
+
+        loop {
+            thread::sleep(self.debounce);
+            let more = self.poll();
+            if more.is_empty() {
+                break;
+            }
+            changed.extend(more);
+        }
+        changed.sort();
+        changed.dedup();
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watched(name: &str) -> (PathBuf, PathBuf) {
+        // The process id keeps concurrent test runs apart.
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = dir.join("config.yaml");
+        std::fs::write(&config, "styles: {}").unwrap();
+        (config, dir.join("image.png"))
+    }
+
+    #[test]
+    fn poll_changes() {
+        let (config, image) = watched("footlights-watch-poll");
+        let dir = config.parent().unwrap().to_owned();
+        let mut watcher = Watcher::default();
+        watcher.watch(BTreeSet::from([config.clone(), image.clone()]));
+        assert_eq!(watcher.len(), 2);
+        assert!(watcher.poll().is_empty());
+
+        std::fs::write(&config, "styles: { bg: {} }").unwrap();
+        // A file that did not exist is created.
+        std::fs::write(&image, "png").unwrap();
+        assert_eq!(watcher.poll(), [config.clone(), image.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        // A file that is watched again keeps its state, so a change between
+        // two renders is not missed.
+        std::fs::remove_file(&image).unwrap();
+        watcher.watch(BTreeSet::from([config.clone(), image.clone()]));
+        assert_eq!(watcher.poll(), [image]);
+
+        watcher.watch(BTreeSet::from([config]));
+        assert_eq!(watcher.len(), 1);
+        assert!(watcher.poll().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn wait_for_changes() {
+        let (config, image) = watched("footlights-watch-wait");
+        let dir = config.parent().unwrap().to_owned();
+        // The margins are large, so that a slow machine does not split the
+        // writes into two changes.
+        let mut watcher = Watcher {
+            interval: Duration::from_millis(10),
+            debounce: Duration::from_millis(500),
+            ..Default::default()
+        };
+        watcher.watch(BTreeSet::from([config.clone(), image.clone()]));
+
+        let writer = thread::spawn({
+            let (config, image) = (config.clone(), image.clone());
+            move || {
+                std::fs::write(&config, "styles: { bg: {} }").unwrap();
+                thread::sleep(Duration::from_millis(100));
+                std::fs::write(&image, "png").unwrap();
+            }
+        });
+        // Both writes are debounced into one change.
+        assert_eq!(watcher.wait(), [config, image]);
+        writer.join().unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
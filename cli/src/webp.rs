@@ -0,0 +1,67 @@
+//! Lossless WebP output, for `fl serve`.
+
+use footlights_engine::render::raster::tiny_skia::Pixmap;
+use image::{codecs::webp::WebPEncoder, ColorType, ImageResult};
+
+/// Encode the pixmap as a lossless WebP file.
+///
+/// WebP images are at most 16384x16384, larger pixmaps are an error.
+pub fn encode(pixmap: &Pixmap) -> ImageResult<Vec<u8>> {
+    let data: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+
+    let mut file = Vec::new();
+    WebPEncoder::new_lossless(&mut file).encode(
+        &data,
+        pixmap.width(),
+        pixmap.height(),
+        ColorType::Rgba8,
+    )?;
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use footlights_engine::render::raster::tiny_skia::{
+        Color, Paint, PremultipliedColorU8, Rect, Transform,
+    };
+
+    #[test]
+    fn decode_encoded() {
+        let mut pixmap = Pixmap::new(7, 5).unwrap();
+        pixmap.fill(Color::from_rgba8(30, 144, 255, 255));
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(255, 0, 0, 128);
+        pixmap.fill_rect(
+            Rect::from_xywh(0.0, 0.0, 3.0, 2.0).unwrap(),
+            &paint,
+            Transform::identity(),
+            None,
+        );
+        pixmap.pixels_mut()[34] = PremultipliedColorU8::TRANSPARENT;
+
+        let webp = encode(&pixmap).unwrap();
+        let decoded = image::load_from_memory_with_format(&webp, image::ImageFormat::WebP)
+            .unwrap()
+            .to_rgba8();
+
+        assert_eq!(decoded.dimensions(), (7, 5));
+        for (pixel, decoded) in pixmap.pixels().iter().zip(decoded.pixels()) {
+            let color = pixel.demultiply();
+            assert_eq!(
+                decoded.0,
+                [color.red(), color.green(), color.blue(), color.alpha()]
+            );
+        }
+
+        assert!(encode(&Pixmap::new(16385, 1).unwrap()).is_err());
+    }
+}
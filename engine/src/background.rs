@@ -1,3 +1,5 @@
+//! Background layers: pure colors and gradients.
+
 use elementtree::Element;
 use serde::{Deserialize, Serialize};
 
@@ -5,6 +7,7 @@ use crate::configs::style::{PositionOption, SizeOption};
 
 use super::foundation::{Color, Position, PositionOptionT, Size, SizeOptionT};
 use super::svg::SvgTangibleObject;
+use crate::layout::Primitive;
 
 /// A background layer is a layer only contains style.
 /// It has no position info.
@@ -12,51 +15,96 @@ use super::svg::SvgTangibleObject;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Background {
     pub(crate) bg_type: BackgroundType,
-    // size: SizeOption,
+    /// The size of the background, defaults to a padding of 100px.
+    pub(crate) size: Option<SizeOption>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The fill of a background.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum BackgroundType {
+    /// A solid color.
     Pure(Color),
+    /// A linear gradient.
     Linear(LinearGradient),
+    /// A radial gradient.
     Radial(RadialGradient),
+    /// The gradient of a built-in preset, see [`crate::presets`].
+    ///
+    /// Replaced by the gradient when the styles are resolved.
+    Preset(String),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Background {
+    /// Create a white background.
     pub fn new() -> Self {
         Self {
             bg_type: BackgroundType::Pure(Color("white".to_string())),
+            size: None,
         }
     }
 
+    /// Create a background of a solid color.
     pub fn new_pure(color: Color) -> Self {
         Self {
             bg_type: BackgroundType::Pure(color),
+            size: None,
         }
     }
 
+    /// Create a background of a linear gradient.
+    ///
+    /// * `stops`: The color and the offset of each gradient stop.
+    /// * `degree`: The rotation of the gradient, in degrees.
     pub fn new_linear_gradient(stops: Vec<(Color, String)>, degree: f32) -> Self {
         let linear_gradient = LinearGradient { stops, degree };
 
         Self {
             bg_type: BackgroundType::Linear(linear_gradient),
+            size: None,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A linear gradient, see [`Background::new_linear_gradient`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
 pub struct LinearGradient {
     /// Color, offset
-    stops: Vec<(Color, String)>,
-    degree: f32,
+    pub(crate) stops: Vec<(Color, String)>,
+    /// The rotation of the gradient, in degrees.
+    pub(crate) degree: f32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl LinearGradient {
+    /// The color and the offset of each stop.
+    pub fn stops(&self) -> &[(Color, String)] {
+        &self.stops
+    }
+
+    /// The rotation of the gradient, in degrees.
+    pub fn degree(&self) -> f32 {
+        self.degree
+    }
+}
+
+/// A radial gradient.
+///
+/// Not supported yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct RadialGradient {}
 
 impl SizeOptionT for Background {
     fn get_size_option(&self) -> SizeOption {
-        SizeOption::FitContent(100)
+        self.size.unwrap_or(SizeOption::FitContent(100))
     }
 }
 
@@ -110,6 +158,26 @@ impl SvgTangibleObject for Background {
                 (svg, None)
             }
             BackgroundType::Radial(_) => todo!(),
+            BackgroundType::Preset(name) => match crate::presets::get(name).and_then(|p| p.color())
+            {
+                Some(bg_type) => Background {
+                    bg_type: bg_type.clone(),
+                    size: self.size,
+                }
+                .to_svg(size, position, id),
+                // Unknown presets are reported when the styles are resolved, but a
+                // background can be deserialized on its own, draw nothing then.
+                None => {
+                    let mut element = Element::new("rect");
+                    element.set_attr("width", size.0.to_string());
+                    element.set_attr("height", size.1.to_string());
+                    element.set_attr("x", position.0.to_string());
+                    element.set_attr("y", position.1.to_string());
+                    element.set_attr("fill", "none");
+
+                    (element, None)
+                }
+            },
             BackgroundType::Pure(color) => {
                 let mut element = Element::new("rect");
                 element.set_attr("width", size.0.to_string());
@@ -122,8 +190,13 @@ impl SvgTangibleObject for Background {
             }
         }
     }
+
+    fn primitive(&self) -> Option<Primitive<'_>> {
+        Primitive::from_background(&self.bg_type)
+    }
 }
 
+/// A fill of a shape.
 pub struct Fill {}
 
 #[cfg(test)]
@@ -148,6 +221,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn svg_background_unknown_preset() -> Result<()> {
+        let background = Background {
+            bg_type: BackgroundType::Preset("nope".to_string()),
+            size: None,
+        };
+
+        let (xml, defs) = background.to_svg(Size(100, 100), Position(0, 0), "1".to_string());
+
+        assert!(defs.is_none());
+
+        const EXPECT: &str = r#"
+        <rect width="100" height="100" x="0" y="0" fill="none"/>
+        "#;
+        compare_svg(&xml, EXPECT).unwrap();
+
+        Ok(())
+    }
+
     #[test]
     fn svg_background_linear_gradient() -> Result<()> {
         let stops = vec![
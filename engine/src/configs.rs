@@ -1,13 +1,214 @@
 //! Config structs for the templates.
 
+use std::{borrow::Cow, fmt};
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use self::{
+    structure::{ImageSizeError, ImageSizeProvider, LayerType, Structure},
+    style::StyleCollection,
+};
+use crate::Canvas;
+
 #[derive(Debug, Error)]
 /// The error type for the config module.
 pub enum ConfigError {
-    /// Style is not in the style collection.
-    #[error("Style \"{0}\" is not in the style collection.")]
-    StyleNotInCollectionError(String),
+    /// The style of a layer is not in the style collection.
+    #[error("Style \"{}\" of layer \"{}\" is not in the style collection.", .0.style, .0.layer)]
+    UnknownStyle(LayerPath),
+    /// More than one layer has the same id.
+    #[error("Layer id \"{0}\" is used by more than one layer.")]
+    DuplicateLayerId(String),
+    /// A field required by the layer type is not set.
+    #[error("Field \"{field}\" is required by {path}.")]
+    MissingField {
+        /// The layer and its style.
+        path: LayerPath,
+        /// The name of the missing field.
+        field: &'static str,
+    },
+    /// A field is set, but is not used by the layer type.
+    #[error("Field \"{field}\" is not supported by {layer_type:?} layers, found in {path}.")]
+    UnexpectedField {
+        /// The layer and its style.
+        path: LayerPath,
+        /// The name of the unexpected field.
+        field: &'static str,
+        /// The type of the layer.
+        layer_type: LayerType,
+    },
+    /// A field has an invalid value.
+    #[error("Invalid value of field \"{field}\" in {path}: {reason}")]
+    InvalidValue {
+        /// The layer and its style.
+        path: LayerPath,
+        /// The name of the field, with sub-fields separated by dots.
+        field: &'static str,
+        /// Why the value is invalid.
+        reason: String,
+    },
+    /// A style extends a style that is not in the style collection.
+    #[error(
+        "Style \"{style}\" extends \"{parent}\", which is neither in the style collection nor a preset."
+    )]
+    UnknownParent {
+        /// The id of the extending style.
+        style: String,
+        /// The id of the missing parent style.
+        parent: String,
+    },
+    /// A style uses a gradient preset that does not exist.
+    #[error("Style \"{style}\" uses the preset \"{preset}\", which is not a gradient preset.")]
+    UnknownPreset {
+        /// The id of the style.
+        style: String,
+        /// The name of the preset.
+        preset: String,
+    },
+    /// A field is set both directly and by a CSS declaration.
+    #[error("Style \"{style}\" sets both \"{field}\" and the CSS property \"{property}\".")]
+    CssConflict {
+        /// The id of the style.
+        style: String,
+        /// The name of the field.
+        field: &'static str,
+        /// The CSS property setting the same field.
+        property: &'static str,
+    },
+    /// Styles extend each other in a cycle.
+    #[error("Styles extend each other in a cycle: {}.", .0.join(" -> "))]
+    InheritanceCycle(Vec<String>),
+    /// The size of the image of a layer is not available.
+    #[error("Failed to get the image size of {path}: {source}")]
+    ImageSize {
+        /// The layer and its style.
+        path: LayerPath,
+        /// The error returned by the [`ImageSizeProvider`](structure::ImageSizeProvider).
+        #[source]
+        source: ImageSizeError,
+    },
+}
+
+impl ConfigError {
+    /// The layer and style of the error, if the error is about a single layer.
+    pub fn path(&self) -> Option<&LayerPath> {
+        match self {
+            Self::UnknownStyle(path)
+            | Self::MissingField { path, .. }
+            | Self::UnexpectedField { path, .. }
+            | Self::InvalidValue { path, .. }
+            | Self::ImageSize { path, .. } => Some(path),
+            Self::DuplicateLayerId(_)
+            | Self::UnknownParent { .. }
+            | Self::UnknownPreset { .. }
+            | Self::CssConflict { .. }
+            | Self::InheritanceCycle(_) => None,
+        }
+    }
+}
+
+/// A complete config: the styles, and the layers using them.
+///
+/// This is the format of a config file:
+///
+/// ```yaml
+/// # Optional, other config files this one is merged over.
+/// include: [base.yaml]
+/// styles:
+///   bg:
+///     color: !Pure white
+///   img:
+///     image: input.png
+/// # Optional, defaults to a "bg" background layer and an "img" image layer.
+/// layers:
+///   - { ty: Background, id: bg, style: bg }
+///   - { ty: Image, id: img, style: img }
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The config files to merge this config over, in order.
+    ///
+    /// The engine does not read files, includes are loaded by the caller
+    /// and merged with [`Config::merge`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// The styles, indexed by style id.
+    #[serde(default)]
+    pub styles: StyleCollection,
+    /// The layers, `None` for the default layers, see [`Structure::default`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layers: Option<Structure>,
+}
+
+impl Config {
+    /// The layers of the config, or the default layers.
+    pub fn structure(&self) -> Cow<'_, Structure> {
+        match &self.layers {
+            Some(layers) => Cow::Borrowed(layers),
+            None => Cow::Owned(Structure::default()),
+        }
+    }
+
+    /// Merge `other` over this config.
+    ///
+    /// The styles are merged field by field, see [`StyleCollection::merge`],
+    /// and the layers by id, see [`Structure::merge`].
+    /// The includes of `other` are ignored, they must be merged before.
+    pub fn merge(&mut self, other: Config) {
+        self.styles.merge(other.styles);
+        if let Some(layers) = other.layers {
+            let mut structure = self.structure().into_owned();
+            structure.merge(layers);
+            self.layers = Some(structure);
+        }
+    }
+
+    /// Check the layers against the styles, see [`Structure::check`].
+    pub fn check(&self) -> Vec<ConfigError> {
+        self.structure().check(&self.styles)
+    }
+
+    /// The ids of the styles that no layer uses, in alphabetical order.
+    ///
+    /// Styles extended by other styles are not included.
+    pub fn unreferenced_styles(&self) -> Vec<&str> {
+        let structure = self.structure();
+        let mut ids: Vec<_> = self
+            .styles
+            .ids()
+            .filter(|id| !structure.iter().any(|layer| layer.style() == *id))
+            .filter(|id| !self.styles.is_extended(id))
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Build the canvas, see [`Structure::build_canvas`].
+    pub fn build_canvas<I: ImageSizeProvider>(
+        &self,
+        image_size_provider: I,
+    ) -> Result<Canvas, ConfigError> {
+        self.structure()
+            .build_canvas(&self.styles, image_size_provider)
+    }
+}
+
+/// The location of a config error: a layer and the style it uses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerPath {
+    /// The id of the layer.
+    pub layer: String,
+    /// The id of the style.
+    pub style: String,
+}
+
+impl fmt::Display for LayerPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "layer \"{}\" (style \"{}\")", self.layer, self.style)
+    }
 }
 
 /// The `structure` module contains the structure of the template.
@@ -19,47 +220,200 @@ pub enum ConfigError {
 /// can be customized by the user.
 pub mod structure {
     use serde::{Deserialize, Serialize};
+    use thiserror::Error;
 
-    use crate::{background::Background, image::Image, svg::SvgTangibleObject, Canvas};
+    use crate::{
+        background::{Background, BackgroundType},
+        image::Image,
+        svg::SvgTangibleObject,
+        Canvas,
+    };
 
-    use super::{style::StyleCollection, ConfigError};
-    use anyhow::Result;
+    use super::{
+        style::{Style, StyleCollection},
+        ConfigError, LayerPath,
+    };
+    use std::collections::HashSet;
 
-    #[derive(Debug, Serialize, Deserialize)]
+    /// The layers of a template, from the bottom to the top.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    #[serde(transparent)]
     pub struct Structure {
         layers: Vec<Layer>,
     }
 
+    /// A provider of the intrinsic size of images.
+    ///
+    /// The engine does not load images by itself,
+    /// so the caller decides how an image source is resolved.
     pub trait ImageSizeProvider {
-        fn get_image_size(&self, src: &str) -> (u32, u32);
+        /// Get the (width, height) of the image at `src`.
+        fn get_image_size(&self, src: &str) -> Result<(u32, u32), ImageSizeError>;
+    }
+
+    /// The error type for [`ImageSizeProvider`].
+    ///
+    /// The image source in the error is shortened,
+    /// as it may be a data URL of several megabytes.
+    #[derive(Debug, Clone, Error, PartialEq, Eq)]
+    pub enum ImageSizeError {
+        /// The image does not exist, or is unknown to the provider.
+        #[error("Image \"{0}\" is not found.")]
+        NotFound(String),
+        /// The image is in a format that the provider does not support.
+        #[error("Image \"{0}\" is in an unsupported format.")]
+        UnsupportedFormat(String),
+        /// The image is corrupt.
+        #[error("Failed to decode image \"{src}\": {reason}")]
+        Decode {
+            /// The image source.
+            src: String,
+            /// The reason of the failure.
+            reason: String,
+        },
+        /// The image could not be downloaded.
+        #[error("Failed to fetch image \"{src}\": {reason}")]
+        Network {
+            /// The image source.
+            src: String,
+            /// The reason of the failure.
+            reason: String,
+        },
+    }
+
+    impl ImageSizeError {
+        /// Shorten an image source to be shown in an error.
+        pub fn display_src(src: &str) -> String {
+            const MAX_LEN: usize = 64;
+
+            match src.char_indices().nth(MAX_LEN) {
+                Some((end, _)) => format!("{}...", &src[..end]),
+                None => src.to_owned(),
+            }
+        }
+
+        /// The image is not found.
+        pub fn not_found(src: &str) -> Self {
+            Self::NotFound(Self::display_src(src))
+        }
+
+        /// The image is in an unsupported format.
+        pub fn unsupported_format(src: &str) -> Self {
+            Self::UnsupportedFormat(Self::display_src(src))
+        }
+
+        /// The image is corrupt.
+        pub fn decode<R: ToString>(src: &str, reason: R) -> Self {
+            Self::Decode {
+                src: Self::display_src(src),
+                reason: reason.to_string(),
+            }
+        }
+
+        /// The image could not be downloaded.
+        pub fn network<R: ToString>(src: &str, reason: R) -> Self {
+            Self::Network {
+                src: Self::display_src(src),
+                reason: reason.to_string(),
+            }
+        }
     }
 
     impl Structure {
-        pub(crate) fn from_vec(layers: Vec<Layer>) -> Self {
+        /// Create a structure from the layers, from the bottom to the top.
+        pub fn from_vec(layers: Vec<Layer>) -> Self {
             Self { layers }
         }
 
+        /// Iterate over the layers, from the bottom to the top.
+        pub fn iter(&self) -> impl Iterator<Item = &Layer> {
+            self.layers.iter()
+        }
+
+        /// Merge the layers of `other` over this structure.
+        ///
+        /// A layer replaces the layer with the same id in place,
+        /// other layers are added on top.
+        pub fn merge(&mut self, other: Structure) {
+            for layer in other.layers {
+                match self.layers.iter_mut().find(|l| l.id == layer.id) {
+                    Some(existing) => *existing = layer,
+                    None => self.layers.push(layer),
+                }
+            }
+        }
+
+        /// Check the structure against the style collection.
+        ///
+        /// Every problem is reported, in the order of the layers, against
+        /// the resolved styles. If the inheritance of the styles cannot be
+        /// resolved, only that error is reported.
+        /// An empty vector means that [`Structure::build_canvas`] will only
+        /// fail if an image size is not available.
+        pub fn check(&self, style_collections: &StyleCollection) -> Vec<ConfigError> {
+            match style_collections.resolve() {
+                Ok(resolved) => self.check_resolved(&resolved),
+                Err(error) => vec![error],
+            }
+        }
+
+        fn check_resolved(&self, style_collections: &StyleCollection) -> Vec<ConfigError> {
+            let mut errors = Vec::new();
+            let mut ids = HashSet::new();
+
+            for layer in &self.layers {
+                if !ids.insert(layer.id.as_str()) {
+                    errors.push(ConfigError::DuplicateLayerId(layer.id.clone()));
+                }
+
+                match style_collections.get(&layer.style) {
+                    Some(style) => layer.check_style(style, &mut errors),
+                    None => errors.push(ConfigError::UnknownStyle(layer.path())),
+                }
+            }
+
+            errors
+        }
+
         /// Build the canvas from the structure and style collections.
+        ///
+        /// The inheritance of the styles is resolved first.
+        /// Returns the first error found by [`Structure::check`], if any.
         pub fn build_canvas<I: ImageSizeProvider>(
             &self,
             style_collections: &StyleCollection,
             image_size_provider: I,
-        ) -> Result<Canvas> {
+        ) -> Result<Canvas, ConfigError> {
+            let style_collections = &style_collections.resolve()?;
+            if let Some(error) = self.check_resolved(style_collections).into_iter().next() {
+                return Err(error);
+            }
+
             let mut canvas = Canvas::default();
             for layer in &self.layers {
-                // Get the style of the layer.
+                // Get the style of the layer, it is checked above.
                 let style = style_collections
                     .get(&layer.style)
-                    .ok_or_else(|| ConfigError::StyleNotInCollectionError(layer.style.clone()))?;
+                    .ok_or_else(|| ConfigError::UnknownStyle(layer.path()))?;
+                let missing = |field| ConfigError::MissingField {
+                    path: layer.path(),
+                    field,
+                };
 
                 let layer: Box<dyn SvgTangibleObject> = match layer.ty {
                     LayerType::Image => {
+                        let path = style.image.clone().ok_or_else(|| missing("image"))?;
+                        let size = image_size_provider.get_image_size(&path).map_err(|e| {
+                            ConfigError::ImageSize {
+                                path: layer.path(),
+                                source: e,
+                            }
+                        })?;
                         let image = Image {
-                            path: style.image.clone().expect("Image path is not set."),
-                            size: image_size_provider
-                                .get_image_size(&style.image.clone().unwrap())
-                                .into(),
-                            round: style.round.clone(),
+                            path,
+                            size: size.into(),
+                            round: style.round,
 
                             shadow: style.shadow.clone(),
                         };
@@ -67,11 +421,11 @@ pub mod structure {
                         Box::new(image)
                     }
                     LayerType::Background => {
-                        let bg_type = style
-                            .color
-                            .clone()
-                            .expect("Color is not set for background.");
-                        let background = Background { bg_type };
+                        let bg_type = style.color.clone().ok_or_else(|| missing("color"))?;
+                        let background = Background {
+                            bg_type,
+                            size: style.size,
+                        };
 
                         Box::new(background)
                     }
@@ -102,22 +456,147 @@ pub mod structure {
         }
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    pub(crate) enum LayerType {
+    /// The type of a layer, which decides the fields it uses.
+    #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    pub enum LayerType {
+        /// An image, uses `image`, `round` and `shadow`.
         Image,
+        /// A background, uses `color`.
         Background,
     }
 
-    #[derive(Debug, Serialize, Deserialize)]
-    pub(crate) struct Layer {
+    impl LayerType {
+        /// The style fields used by the layer type, besides the common
+        /// `position` and `size`.
+        pub fn fields(&self) -> &'static [&'static str] {
+            match self {
+                LayerType::Image => &["image", "round", "shadow"],
+                LayerType::Background => &["color"],
+            }
+        }
+    }
+
+    /// A layer of the structure, drawn with a style of the style collection.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    #[serde(deny_unknown_fields)]
+    pub struct Layer {
+        /// The type of the layer.
         ty: LayerType,
+        /// The id of the layer, unique in the structure.
         id: String,
+        /// The id of the style used by the layer.
         style: String,
     }
 
+    impl Layer {
+        /// Create a layer of type `ty`, using the style `style`.
+        pub fn new(ty: LayerType, id: String, style: String) -> Self {
+            Self { ty, id, style }
+        }
+
+        /// The type of the layer.
+        pub fn ty(&self) -> LayerType {
+            self.ty
+        }
+
+        /// The id of the layer, unique in the structure.
+        pub fn id(&self) -> &str {
+            &self.id
+        }
+
+        /// The id of the style used by the layer.
+        pub fn style(&self) -> &str {
+            &self.style
+        }
+
+        fn path(&self) -> LayerPath {
+            LayerPath {
+                layer: self.id.clone(),
+                style: self.style.clone(),
+            }
+        }
+
+        /// Check that the style has valid values for the fields the layer uses.
+        fn check_style(&self, style: &Style, errors: &mut Vec<ConfigError>) {
+            let invalid = |field, reason: &str| ConfigError::InvalidValue {
+                path: self.path(),
+                field,
+                reason: reason.to_owned(),
+            };
+
+            let set_fields = [
+                ("image", style.image.is_some()),
+                ("round", style.round.is_some()),
+                ("shadow", style.shadow.is_some()),
+                ("color", style.color.is_some()),
+            ];
+            for (field, _) in set_fields
+                .iter()
+                .filter(|(field, set)| *set && !self.ty.fields().contains(field))
+            {
+                errors.push(ConfigError::UnexpectedField {
+                    path: self.path(),
+                    field,
+                    layer_type: self.ty,
+                });
+            }
+
+            let required = match self.ty {
+                LayerType::Image => ("image", style.image.is_some()),
+                LayerType::Background => ("color", style.color.is_some()),
+            };
+            if !required.1 {
+                errors.push(ConfigError::MissingField {
+                    path: self.path(),
+                    field: required.0,
+                });
+            }
+
+            match self.ty {
+                LayerType::Image => {
+                    if matches!(&style.image, Some(image) if image.trim().is_empty()) {
+                        errors.push(invalid("image", "the image source is empty"));
+                    }
+                    if let Some(shadow) = &style.shadow {
+                        if !(0.0..=1.0).contains(&shadow.opacity) {
+                            errors.push(invalid(
+                                "shadow.opacity",
+                                "the opacity must be between 0 and 1",
+                            ));
+                        }
+                    }
+                }
+                LayerType::Background => match &style.color {
+                    Some(BackgroundType::Linear(linear)) => {
+                        if linear.stops.is_empty() {
+                            errors
+                                .push(invalid("color.stops", "a gradient needs at least one stop"));
+                        }
+                        if !linear.degree.is_finite() {
+                            errors.push(invalid(
+                                "color.degree",
+                                "the degree must be a finite number",
+                            ));
+                        }
+                    }
+                    Some(BackgroundType::Radial(_)) => {
+                        errors.push(invalid("color", "radial gradients are not supported yet"));
+                    }
+                    Some(BackgroundType::Pure(color)) if color.0.trim().is_empty() => {
+                        errors.push(invalid("color", "the color is empty"));
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
     #[cfg(test)]
     mod tests {
         use super::*;
+        use crate::configs::style::DropShadow;
 
         #[test]
         fn structure_serialization() {
@@ -127,6 +606,115 @@ pub mod structure {
             let structure_new: Structure = serde_yaml::from_str(&json).unwrap();
             assert_eq!(structure_new.layers.len(), 2);
         }
+
+        struct FailingProvider;
+
+        impl ImageSizeProvider for FailingProvider {
+            fn get_image_size(&self, src: &str) -> Result<(u32, u32), ImageSizeError> {
+                Err(ImageSizeError::not_found(src))
+            }
+        }
+
+        #[test]
+        fn image_size_error() {
+            let mut styles = StyleCollection::default();
+            styles.add(
+                "bg".to_owned(),
+                Style {
+                    color: Some(crate::background::BackgroundType::Pure("red".into())),
+                    ..Default::default()
+                },
+            );
+            styles.add(
+                "img".to_owned(),
+                Style {
+                    image: Some(format!("data:image/png;base64,{}", "A".repeat(1000))),
+                    ..Default::default()
+                },
+            );
+
+            let err = Structure::default()
+                .build_canvas(&styles, FailingProvider)
+                .unwrap_err();
+
+            match err {
+                ConfigError::ImageSize {
+                    path,
+                    source: ImageSizeError::NotFound(src),
+                } => {
+                    assert_eq!(path.layer, "img");
+                    assert_eq!(path.style, "img");
+                    assert!(src.len() < 100);
+                }
+                _ => panic!("unexpected error: {}", err),
+            }
+        }
+
+        #[test]
+        fn check_errors() {
+            let mut styles = StyleCollection::default();
+            styles.add(
+                "bg".to_owned(),
+                Style {
+                    image: Some("a.png".to_owned()),
+                    ..Default::default()
+                },
+            );
+            styles.add(
+                "img".to_owned(),
+                Style {
+                    image: Some("a.png".to_owned()),
+                    shadow: Some(DropShadow {
+                        opacity: 2.0,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            );
+
+            let layer = |ty, id: &str, style: &str| Layer {
+                ty,
+                id: id.to_owned(),
+                style: style.to_owned(),
+            };
+            let structure = Structure::from_vec(vec![
+                layer(LayerType::Background, "bg", "bg"),
+                layer(LayerType::Image, "img", "img"),
+                layer(LayerType::Image, "img", "missing"),
+            ]);
+
+            let errors = structure.check(&styles);
+            let messages: Vec<_> = errors.iter().map(|e| e.to_string()).collect();
+            assert_eq!(
+                messages,
+                [
+                    r#"Field "image" is not supported by Background layers, found in layer "bg" (style "bg")."#,
+                    r#"Field "color" is required by layer "bg" (style "bg")."#,
+                    r#"Invalid value of field "shadow.opacity" in layer "img" (style "img"): the opacity must be between 0 and 1"#,
+                    r#"Layer id "img" is used by more than one layer."#,
+                    r#"Style "missing" of layer "img" is not in the style collection."#,
+                ]
+            );
+            assert_eq!(
+                errors[0].path(),
+                Some(&LayerPath {
+                    layer: "bg".to_owned(),
+                    style: "bg".to_owned()
+                })
+            );
+
+            let err = structure
+                .build_canvas(&styles, FailingProvider)
+                .unwrap_err();
+            assert!(matches!(
+                err,
+                ConfigError::UnexpectedField {
+                    field: "image",
+                    layer_type: LayerType::Background,
+                    ..
+                }
+            ));
+        }
     }
 }
 
@@ -141,40 +729,393 @@ pub mod style {
     use serde::{Deserialize, Serialize};
     use std::collections::HashMap;
 
-    use crate::background::BackgroundType;
+    use super::ConfigError;
+    use crate::{
+        background::BackgroundType,
+        css::Declarations,
+        presets::{self, Preset},
+    };
 
+    /// A collection of styles, indexed by style id.
     #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    #[serde(transparent)]
     pub struct StyleCollection {
+        #[serde(serialize_with = "serialize_sorted")]
         styles: HashMap<String, Style>,
     }
 
+    /// Serialize the styles sorted by id, so that the output is stable.
+    fn serialize_sorted<S: serde::Serializer>(
+        styles: &HashMap<String, Style>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(styles.iter().collect::<std::collections::BTreeMap<_, _>>())
+    }
+
     impl StyleCollection {
-        pub(crate) fn new(styles: HashMap<String, Style>) -> Self {
+        /// Create a collection from the styles indexed by id.
+        pub fn new(styles: HashMap<String, Style>) -> Self {
             Self { styles }
         }
 
-        pub(crate) fn get(&self, id: &str) -> Option<&Style> {
+        /// Get the style with the id.
+        pub fn get(&self, id: &str) -> Option<&Style> {
             self.styles.get(id)
         }
 
+        /// Iterate over the style ids, in arbitrary order.
+        pub fn ids(&self) -> impl Iterator<Item = &str> {
+            self.styles.keys().map(String::as_str)
+        }
+
+        /// Iterate over the styles with their ids, in arbitrary order.
+        pub fn iter(&self) -> impl Iterator<Item = (&str, &Style)> {
+            self.styles.iter().map(|(id, style)| (id.as_str(), style))
+        }
+
+        /// Whether any style extends the style with the id.
+        pub fn is_extended(&self, id: &str) -> bool {
+            self.styles
+                .values()
+                .any(|style| style.parents().any(|parent| parent == id))
+        }
+
+        /// Add a style to the collection, replacing the style with the same id.
         pub fn add(&mut self, id: String, style: Style) {
             self.styles.insert(id, style);
         }
+
+        /// Merge the styles of `other` over this collection.
+        ///
+        /// The fields set in a style of `other` override the fields of the
+        /// style with the same id, see [`Style::merge`].
+        pub fn merge(&mut self, other: StyleCollection) {
+            for (id, style) in other.styles {
+                self.styles.entry(id).or_default().merge(style);
+            }
+        }
+
+        /// Iterate over the image sources of the styles, with the style id.
+        pub fn image_sources(&self) -> impl Iterator<Item = (&str, &str)> {
+            self.styles
+                .iter()
+                .filter_map(|(id, style)| Some((id.as_str(), style.image.as_deref()?)))
+        }
+
+        /// Iterate over the image sources of the styles, with the style id.
+        ///
+        /// This allows the caller to load and rewrite image references
+        /// (e.g. into [`crate::resources`] handles) before building the canvas.
+        pub fn image_sources_mut(&mut self) -> impl Iterator<Item = (&str, &mut String)> {
+            self.styles
+                .iter_mut()
+                .filter_map(|(id, style)| style.image.as_mut().map(|image| (id.as_str(), image)))
+        }
+
+        /// Resolve the inheritance of the styles.
+        ///
+        /// Every style gets the unset fields from the styles it extends,
+        /// recursively. A style can extend a style of the collection or a
+        /// preset, see [`crate::presets`]. The resolved styles extend nothing,
+        /// and their `!Preset` colors are replaced by the gradients.
+        pub fn resolve(&self) -> Result<StyleCollection, ConfigError> {
+            // Resolve in a stable order, so that the same error is reported every time.
+            let mut ids: Vec<_> = self.ids().collect();
+            ids.sort_unstable();
+
+            let mut resolved = HashMap::new();
+            for id in ids {
+                self.resolve_style(id, &mut resolved, &mut Vec::new())?;
+            }
+
+            Ok(StyleCollection::new(resolved))
+        }
+
+        /// Resolve the style with the id, which must be in the collection.
+        ///
+        /// `chain` holds the styles being resolved, to detect cycles.
+        fn resolve_style(
+            &self,
+            id: &str,
+            resolved: &mut HashMap<String, Style>,
+            chain: &mut Vec<String>,
+        ) -> Result<Style, ConfigError> {
+            if let Some(style) = resolved.get(id) {
+                return Ok(style.clone());
+            }
+            if let Some(start) = chain.iter().position(|chained| chained == id) {
+                let mut cycle = chain[start..].to_vec();
+                cycle.push(id.to_owned());
+                return Err(ConfigError::InheritanceCycle(cycle));
+            }
+
+            let style = &self.styles[id];
+            let mut result = Style {
+                extends: None,
+                ..style.clone()
+            };
+            result.apply_css(id)?;
+
+            chain.push(id.to_owned());
+            // Later parents take precedence, so they fill the unset fields first.
+            for parent in style.parents().rev() {
+                // The styles of the collection shadow the presets.
+                let parent = if self.styles.contains_key(parent) {
+                    self.resolve_style(parent, resolved, chain)?
+                } else if let Some(preset) = presets::get(parent).and_then(Preset::style) {
+                    preset.clone()
+                } else {
+                    return Err(ConfigError::UnknownParent {
+                        style: id.to_owned(),
+                        parent: parent.to_owned(),
+                    });
+                };
+                result.inherit(&parent);
+            }
+            chain.pop();
+
+            if let Some(BackgroundType::Preset(name)) = &result.color {
+                let color = presets::get(name).and_then(Preset::color).ok_or_else(|| {
+                    ConfigError::UnknownPreset {
+                        style: id.to_owned(),
+                        preset: name.clone(),
+                    }
+                })?;
+                result.color = Some(color.clone());
+            }
+
+            resolved.insert(id.to_owned(), result.clone());
+            Ok(result)
+        }
     }
 
+    /// The style of a single layer.
+    ///
+    /// Every field is optional; which fields are required
+    /// depends on the type of the layer using the style.
     #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    #[serde(deny_unknown_fields)]
     pub struct Style {
+        /// The ids of the styles to inherit the unset fields from.
+        ///
+        /// With a list, later styles take precedence over earlier ones.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) extends: Option<Extends>,
+        /// The position of the layer.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         pub(crate) position: Option<PositionOption>,
+        /// The size of the layer.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         pub(crate) size: Option<SizeOption>,
+        /// The image source of an image layer: a path, a URL or a data URL.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         pub(crate) image: Option<String>,
+        /// The rounded corner radius of an image layer, in px.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         pub(crate) round: Option<usize>,
+        /// The drop shadow of an image layer.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         pub(crate) shadow: Option<DropShadow>,
+        /// The fill of a background layer.
         // FIXME: Serde into Background for now.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         pub(crate) color: Option<BackgroundType>,
+        /// CSS declarations setting the other fields, see [`crate::css`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub(crate) css: Option<Declarations>,
+    }
+
+    impl Style {
+        /// The ids of the styles this style extends, in declaration order.
+        pub fn parents(&self) -> impl DoubleEndedIterator<Item = &str> {
+            self.extends
+                .iter()
+                .flat_map(|extends| extends.ids())
+                .map(String::as_str)
+        }
+
+        /// The names of the fields set in the style.
+        pub fn set_fields(&self) -> Vec<&'static str> {
+            [
+                ("extends", self.extends.is_some()),
+                ("position", self.position.is_some()),
+                ("size", self.size.is_some()),
+                ("image", self.image.is_some()),
+                ("round", self.round.is_some()),
+                ("shadow", self.shadow.is_some()),
+                ("color", self.color.is_some()),
+                ("css", self.css.is_some()),
+            ]
+            .into_iter()
+            .filter_map(|(field, is_set)| is_set.then_some(field))
+            .collect()
+        }
+
+        /// Set the size of the layer.
+        pub fn with_size(mut self, size: SizeOption) -> Self {
+            self.size = Some(size);
+            self
+        }
+
+        /// Set the image source of an image layer.
+        pub fn with_image(mut self, image: impl Into<String>) -> Self {
+            self.image = Some(image.into());
+            self
+        }
+
+        /// Set the rounded corner radius of an image layer, in px.
+        pub fn with_round(mut self, round: usize) -> Self {
+            self.round = Some(round);
+            self
+        }
+
+        /// Set the drop shadow of an image layer.
+        pub fn with_shadow(mut self, shadow: DropShadow) -> Self {
+            self.shadow = Some(shadow);
+            self
+        }
+
+        /// Set the fill of a background layer.
+        pub fn with_color(mut self, color: BackgroundType) -> Self {
+            self.color = Some(color);
+            self
+        }
+
+        /// Set the CSS declarations, applied when the styles are resolved.
+        pub fn with_css(mut self, css: Declarations) -> Self {
+            self.css = Some(css);
+            self
+        }
+
+        /// Override the fields of this style with the fields set in `other`.
+        ///
+        /// The CSS of each style is applied to its own fields first, so a
+        /// field set in `other` overrides a CSS declaration of this style, and
+        /// the other way around.
+        pub fn merge(&mut self, mut other: Style) {
+            self.try_apply_css();
+            other.try_apply_css();
+
+            fn or<T>(field: &mut Option<T>, other: Option<T>) {
+                if other.is_some() {
+                    *field = other;
+                }
+            }
+
+            or(&mut self.extends, other.extends);
+            or(&mut self.position, other.position);
+            or(&mut self.size, other.size);
+            or(&mut self.image, other.image);
+            or(&mut self.round, other.round);
+            or(&mut self.shadow, other.shadow);
+            or(&mut self.color, other.color);
+            or(&mut self.css, other.css);
+        }
+
+        /// Apply the CSS declarations if they don't conflict with the fields.
+        ///
+        /// Conflicting declarations are kept, for [`StyleCollection::resolve`]
+        /// to report them with the style id.
+        fn try_apply_css(&mut self) {
+            if self.css.is_none() {
+                return;
+            }
+            let mut applied = self.clone();
+            if applied.apply_css("").is_ok() {
+                *self = applied;
+            }
+        }
+
+        /// Move the CSS declarations into the fields they set.
+        ///
+        /// `id` is the id of the style, for errors.
+        fn apply_css(&mut self, id: &str) -> Result<(), ConfigError> {
+            let Some(css) = self.css.take() else {
+                return Ok(());
+            };
+
+            fn set<T>(
+                id: &str,
+                (field, slot): (&'static str, &mut Option<T>),
+                (property, value): (&'static str, Option<T>),
+            ) -> Result<(), ConfigError> {
+                match value {
+                    Some(_) if slot.is_some() => Err(ConfigError::CssConflict {
+                        style: id.to_owned(),
+                        field,
+                        property,
+                    }),
+                    Some(value) => {
+                        *slot = Some(value);
+                        Ok(())
+                    }
+                    None => Ok(()),
+                }
+            }
+
+            set(
+                id,
+                ("color", &mut self.color),
+                ("background", css.background),
+            )?;
+            set(
+                id,
+                ("round", &mut self.round),
+                ("border-radius", css.border_radius),
+            )?;
+            set(
+                id,
+                ("shadow", &mut self.shadow),
+                ("box-shadow", css.box_shadow),
+            )?;
+            set(id, ("size", &mut self.size), ("padding", css.padding))?;
+
+            Ok(())
+        }
+
+        /// Set the unset fields to the fields of `parent`.
+        fn inherit(&mut self, parent: &Style) {
+            fn or<T: Clone>(field: &mut Option<T>, parent: &Option<T>) {
+                if field.is_none() {
+                    field.clone_from(parent);
+                }
+            }
+
+            or(&mut self.position, &parent.position);
+            or(&mut self.size, &parent.size);
+            or(&mut self.image, &parent.image);
+            or(&mut self.round, &parent.round);
+            or(&mut self.shadow, &parent.shadow);
+            or(&mut self.color, &parent.color);
+        }
+    }
+
+    /// The styles a style extends: a single style id, or a list of them.
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    #[serde(untagged)]
+    pub enum Extends {
+        /// A single style id.
+        One(String),
+        /// A list of style ids.
+        Many(Vec<String>),
+    }
+
+    impl Extends {
+        /// The style ids, in declaration order.
+        pub fn ids(&self) -> &[String] {
+            match self {
+                Self::One(id) => std::slice::from_ref(id),
+                Self::Many(ids) => ids,
+            }
+        }
     }
 
     /// Position of the element, costomized by the user.
     #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
     pub enum PositionOption {
         /// The element is positioned at the center of the parent element.
         Center,
@@ -184,18 +1125,24 @@ pub mod style {
 
     /// Size of the element, costomized by the user.
     #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
     pub enum SizeOption {
         /// The element is sized to fit the content (the child element).
         /// The argument is the padding of the element. (in px)
         FitContent(u32),
         /// The element is absolute sized in x and y direction. (in px)
         Absolute(u32, u32),
+        /// The element is sized to fit the content, with different paddings
+        /// in x and y direction. (in px)
+        FitContentXY(u32, u32),
     }
 
     /// A struct that represents a drop shadow.
     ///
     /// See [the official documentation](https://www.w3.org/TR/filter-effects/#feDropShadowElement).
     #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+    #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+    #[serde(deny_unknown_fields)]
     pub struct DropShadow {
         /// The x offset of the drop shadow.
         pub x: usize,
@@ -257,6 +1204,8 @@ pub mod style {
         #[test]
         fn style_serialization() {
             let style = Style {
+                extends: None,
+                css: None,
                 position: Some(PositionOption::Center),
                 size: Some(SizeOption::FitContent(10)),
                 image: Some("image.png".to_string()),
@@ -277,5 +1226,197 @@ pub mod style {
             assert_eq!(style_new.round, Some(10));
             assert_eq!(style_new.shadow, Some(DropShadow::new(5, 5, 7)));
         }
+
+        #[test]
+        fn style_inheritance() {
+            let styles: StyleCollection = serde_yaml::from_str(
+                r#"
+base: { round: 10, image: a.png }
+shadowed: { shadow: { x: 1, y: 1 }, round: 5 }
+child: { extends: base, round: 20 }
+grandchild: { extends: [child, shadowed] }
+"#,
+            )
+            .unwrap();
+            assert!(styles.is_extended("child"));
+            assert!(!styles.is_extended("grandchild"));
+
+            let resolved = styles.resolve().unwrap();
+            let child = resolved.get("child").unwrap();
+            assert_eq!(child.round, Some(20));
+            assert_eq!(child.image.as_deref(), Some("a.png"));
+            assert_eq!(child.extends, None);
+
+            // Later parents take precedence.
+            let grandchild = resolved.get("grandchild").unwrap();
+            assert_eq!(grandchild.round, Some(5));
+            assert_eq!(grandchild.image.as_deref(), Some("a.png"));
+            assert_eq!(grandchild.shadow, Some(DropShadow::new(1, 1, 7)));
+        }
+
+        #[test]
+        fn css_declarations() {
+            let styles: StyleCollection = serde_yaml::from_str(
+                r#"
+base: { css: "border-radius: 20px; padding: 10px" }
+child: { extends: base, round: 5, css: "background: red" }
+conflict: { round: 5, css: "border-radius: 20px" }
+"#,
+            )
+            .unwrap();
+
+            let err = styles.resolve().unwrap_err();
+            assert!(matches!(
+                err,
+                ConfigError::CssConflict { ref style, field: "round", property: "border-radius" }
+                    if style == "conflict"
+            ));
+
+            let mut styles = styles;
+            styles.styles.remove("conflict");
+            let resolved = styles.resolve().unwrap();
+            let base = resolved.get("base").unwrap();
+            assert_eq!(base.round, Some(20));
+            assert_eq!(base.size, Some(SizeOption::FitContent(10)));
+            assert_eq!(base.css, None);
+
+            // CSS of a child overrides the fields of the parent, like fields do.
+            let child = resolved.get("child").unwrap();
+            assert_eq!(child.round, Some(5));
+            assert_eq!(child.size, Some(SizeOption::FitContent(10)));
+            assert!(child.color.is_some());
+        }
+
+        #[test]
+        fn merge_css() {
+            let parse = |yaml| serde_yaml::from_str::<StyleCollection>(yaml).unwrap();
+
+            // CSS overrides the fields of the style it is merged over.
+            let mut styles = parse("img: { round: 20, size: !FitContent 10 }");
+            styles.merge(parse(r#"img: { css: "border-radius: 5px" }"#));
+            let img = styles.resolve().unwrap().get("img").unwrap().clone();
+            assert_eq!(img.round, Some(5));
+            assert_eq!(img.size, Some(SizeOption::FitContent(10)));
+
+            // Fields override the CSS of the style they are merged over.
+            let mut styles = parse(r#"img: { css: "border-radius: 12px; padding: 10px" }"#);
+            styles.merge(parse("img: { round: 4 }"));
+            let img = styles.resolve().unwrap().get("img").unwrap().clone();
+            assert_eq!(img.round, Some(4));
+            assert_eq!(img.size, Some(SizeOption::FitContent(10)));
+
+            // A conflict within one style is still reported.
+            let mut styles = parse("img: { round: 20 }");
+            styles.merge(parse(r#"img: { round: 4, css: "border-radius: 5px" }"#));
+            assert!(matches!(
+                styles.resolve(),
+                Err(ConfigError::CssConflict { field: "round", .. })
+            ));
+        }
+
+        #[test]
+        fn inheritance_errors() {
+            let styles: StyleCollection =
+                serde_yaml::from_str("a: { extends: b }\nb: { extends: [c] }\nc: { extends: a }")
+                    .unwrap();
+            match styles.resolve().unwrap_err() {
+                ConfigError::InheritanceCycle(cycle) => assert_eq!(cycle, ["a", "b", "c", "a"]),
+                err => panic!("unexpected error: {}", err),
+            }
+
+            let styles: StyleCollection = serde_yaml::from_str("a: { extends: a }").unwrap();
+            assert!(matches!(
+                styles.resolve(),
+                Err(ConfigError::InheritanceCycle(cycle)) if cycle == ["a", "a"]
+            ));
+
+            let styles: StyleCollection =
+                serde_yaml::from_str("a: { extends: [b, missing] }\nb: {}").unwrap();
+            assert!(matches!(
+                styles.resolve(),
+                Err(ConfigError::UnknownParent { style, parent }) if style == "a" && parent == "missing"
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+styles:
+  bg:
+    color: !Pure white
+  img:
+    image: input.png
+    round: 20
+  unused:
+    round: 10
+  base:
+    round: 20
+  inherited:
+    extends: base
+    image: input.png
+"#;
+
+    #[test]
+    fn config_serialization() {
+        let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+        assert_eq!(config.structure().iter().count(), 2);
+        assert!(config.check().is_empty());
+        assert_eq!(config.unreferenced_styles(), ["inherited", "unused"]);
+
+        let yaml = serde_yaml::to_string(&config).unwrap();
+        let config_new: Config = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(config_new.styles.ids().count(), 5);
+        assert_eq!(config_new.structure().iter().count(), 2);
+    }
+
+    #[test]
+    fn config_merge() {
+        let mut config: Config = serde_yaml::from_str(CONFIG).unwrap();
+        let project: Config = serde_yaml::from_str(
+            r#"
+include: [base.yaml]
+styles:
+  img:
+    round: 5
+  logo:
+    image: logo.png
+layers:
+  - { ty: Image, id: img, style: inherited }
+  - { ty: Image, id: logo, style: logo }
+"#,
+        )
+        .unwrap();
+        assert_eq!(project.include, ["base.yaml"]);
+
+        config.merge(project);
+        let img = config.styles.get("img").unwrap();
+        assert_eq!(img.set_fields(), ["image", "round"]);
+        assert_eq!(img.round, Some(5));
+
+        // Layers are replaced by id, on top of the default layers.
+        let layers: Vec<_> = config
+            .structure()
+            .iter()
+            .map(|layer| (layer.id().to_owned(), layer.style().to_owned()))
+            .collect();
+        assert_eq!(
+            layers,
+            [("bg", "bg"), ("img", "inherited"), ("logo", "logo")]
+                .map(|(id, style)| (id.to_owned(), style.to_owned()))
+        );
+        assert!(config.check().is_empty());
+    }
+
+    #[test]
+    fn config_unknown_fields() {
+        let err = serde_yaml::from_str::<Config>("styles: {}\nlayer: []").unwrap_err();
+        assert!(err.to_string().contains("unknown field `layer`"));
+
+        let err = serde_yaml::from_str::<Config>("styles: { img: { colour: red } }").unwrap_err();
+        assert!(err.to_string().contains("unknown field `colour`"));
     }
 }
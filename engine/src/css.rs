@@ -0,0 +1,701 @@
+//! CSS declarations in styles.
+//!
+//! A style can set its fields with CSS, as copied from the browser devtools:
+//!
+//! ```yaml
+//! styles:
+//!   bg:
+//!     css: |
+//!       background: linear-gradient(35deg, #ff9a9e 0%, #fad0c4 100%);
+//!       padding: 64px 80px;
+//!   img:
+//!     css: "border-radius: 20px; box-shadow: 5px 5px 14px rgba(0, 0, 0, .6)"
+//! ```
+//!
+//! Only the properties that map onto the style fields are supported:
+//!
+//! | Property        | Field    |
+//! | --------------- | -------- |
+//! | `background`    | `color`  |
+//! | `border-radius` | `round`  |
+//! | `box-shadow`    | `shadow` |
+//! | `padding`       | `size`   |
+
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::{
+    background::{BackgroundType, LinearGradient},
+    configs::style::{DropShadow, SizeOption},
+    foundation::Color,
+};
+
+/// The error type for parsing CSS declarations.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum CssError {
+    /// A declaration is not a `property: value` pair.
+    #[error("Invalid declaration \"{0}\", expected \"property: value\".")]
+    Syntax(String),
+    /// The property has no matching style field.
+    #[error(
+        "Unsupported CSS property \"{0}\", supported properties are \"background\", \"border-radius\", \"box-shadow\" and \"padding\"."
+    )]
+    UnsupportedProperty(String),
+    /// The value of a supported property cannot be used.
+    #[error("Invalid value \"{value}\" of CSS property \"{property}\": {reason}")]
+    InvalidValue {
+        /// The property.
+        property: &'static str,
+        /// The value, as written.
+        value: String,
+        /// Why the value cannot be used.
+        reason: String,
+    },
+}
+
+/// A block of CSS declarations, parsed into style fields.
+///
+/// It is written and serialized as the original text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Declarations {
+    text: String,
+    pub(crate) background: Option<BackgroundType>,
+    pub(crate) border_radius: Option<usize>,
+    pub(crate) box_shadow: Option<DropShadow>,
+    pub(crate) padding: Option<SizeOption>,
+}
+
+impl Declarations {
+    /// The text of the declarations, as written.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl FromStr for Declarations {
+    type Err = CssError;
+
+    /// Parse `property: value` declarations, separated by semicolons.
+    ///
+    /// As in CSS, a later declaration of a property overrides earlier ones.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut declarations = Declarations {
+            text: text.to_owned(),
+            ..Default::default()
+        };
+
+        for declaration in split_top_level(text, ';') {
+            let (property, value) = declaration
+                .split_once(':')
+                .ok_or_else(|| CssError::Syntax(declaration.to_owned()))?;
+            let property = property.trim().to_ascii_lowercase();
+            let value = value.trim();
+            let value = value.strip_suffix("!important").unwrap_or(value).trim();
+            if property.is_empty() || value.is_empty() {
+                return Err(CssError::Syntax(declaration.to_owned()));
+            }
+
+            match property.as_str() {
+                "background" => declarations.background = Some(parse_background(value)?),
+                "border-radius" => declarations.border_radius = Some(parse_border_radius(value)?),
+                "box-shadow" => declarations.box_shadow = Some(parse_box_shadow(value)?),
+                "padding" => declarations.padding = Some(parse_padding(value)?),
+                _ => return Err(CssError::UnsupportedProperty(property)),
+            }
+        }
+
+        Ok(declarations)
+    }
+}
+
+impl Serialize for Declarations {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for Declarations {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "schema")]
+impl schemars::JsonSchema for Declarations {
+    fn schema_name() -> String {
+        "Declarations".to_owned()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl fmt::Display for Declarations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Build the error for an invalid value of `property`.
+fn invalid(property: &'static str, value: &str, reason: impl Into<String>) -> CssError {
+    CssError::InvalidValue {
+        property,
+        value: value.to_owned(),
+        reason: reason.into(),
+    }
+}
+
+/// Split `text` at the `separator`s outside of parentheses, trimming the
+/// parts and skipping the empty ones.
+fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            c if depth == 0 && (c == separator || separator == ' ' && c.is_whitespace()) => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Parse a non-negative length in px, unitless zero is allowed.
+fn parse_length(property: &'static str, value: &str, token: &str) -> Result<u32, CssError> {
+    let number = match token.strip_suffix("px") {
+        Some(number) => number,
+        None if token == "0" => "0",
+        None => return Err(invalid(property, value, "only px lengths are supported")),
+    };
+    let number: f32 = number
+        .parse()
+        .map_err(|_| invalid(property, value, format!("\"{}\" is not a length", token)))?;
+    if !number.is_finite() || number < 0. {
+        return Err(invalid(
+            property,
+            value,
+            "negative lengths are not supported",
+        ));
+    }
+
+    Ok(number.round() as u32)
+}
+
+/// Parse a `<number>` or a `<percentage>` into a fraction.
+fn parse_fraction(token: &str) -> Option<f32> {
+    let fraction = match token.strip_suffix('%') {
+        Some(percentage) => percentage.trim().parse::<f32>().ok()? / 100.,
+        None => token.trim().parse().ok()?,
+    };
+
+    fraction.is_finite().then(|| fraction.clamp(0., 1.))
+}
+
+/// Parse `background`: a color or a `linear-gradient()`.
+fn parse_background(value: &str) -> Result<BackgroundType, CssError> {
+    const PROPERTY: &str = "background";
+
+    if let Some(args) = function_args(value, "linear-gradient") {
+        return parse_linear_gradient(value, args).map(BackgroundType::Linear);
+    }
+    if value.contains('(') && !is_color_function(value) {
+        return Err(invalid(
+            PROPERTY,
+            value,
+            "only colors and linear-gradient() are supported",
+        ));
+    }
+    if split_top_level(value, ' ').len() != 1 {
+        return Err(invalid(
+            PROPERTY,
+            value,
+            "only a single color or linear-gradient() is supported",
+        ));
+    }
+
+    Ok(BackgroundType::Pure(Color(value.to_owned())))
+}
+
+/// The arguments of `value` if it is a call of the CSS function `name`.
+fn function_args<'a>(value: &'a str, name: &str) -> Option<&'a str> {
+    let args = value.get(..name.len())?;
+    if !args.eq_ignore_ascii_case(name) {
+        return None;
+    }
+
+    value[name.len()..]
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+/// Whether `value` is a color written as a CSS function, like `rgb()`.
+fn is_color_function(value: &str) -> bool {
+    [
+        "rgb", "rgba", "hsl", "hsla", "hwb", "lab", "lch", "oklab", "oklch",
+    ]
+    .iter()
+    .any(|name| function_args(value, name).is_some())
+}
+
+/// Parse the arguments of `linear-gradient()`.
+///
+/// CSS angles start at the top and turn clockwise, while the gradient
+/// starts at the left, so the angle is turned by 90 degrees.
+fn parse_linear_gradient(value: &str, args: &str) -> Result<LinearGradient, CssError> {
+    const PROPERTY: &str = "background";
+
+    let mut args = split_top_level(args, ',');
+    let angle = match args.first().map(|arg| parse_direction(arg)) {
+        Some(Some(angle)) => {
+            args.remove(0);
+            angle
+        }
+        // Like CSS, default to top to bottom.
+        _ => 180.,
+    };
+    if args.len() < 2 {
+        return Err(invalid(
+            PROPERTY,
+            value,
+            "a gradient needs at least two colors",
+        ));
+    }
+
+    // Colors, with the optional positions.
+    let mut stops: Vec<(&str, Option<f32>)> = Vec::new();
+    for arg in args {
+        let tokens = split_top_level(arg, ' ');
+        let positions = tokens[1..]
+            .iter()
+            .map(|token| {
+                token
+                    .strip_suffix('%')
+                    .and_then(|_| parse_fraction(token))
+                    .or_else(|| (*token == "0").then_some(0.))
+                    .ok_or_else(|| {
+                        invalid(
+                            PROPERTY,
+                            value,
+                            format!(
+                                "only percentage stop positions are supported, found \"{}\"",
+                                token
+                            ),
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match positions[..] {
+            [] => stops.push((tokens[0], None)),
+            [position] => stops.push((tokens[0], Some(position))),
+            // A color with two positions is two stops.
+            [from, to] => {
+                stops.push((tokens[0], Some(from)));
+                stops.push((tokens[0], Some(to)));
+            }
+            _ => {
+                return Err(invalid(
+                    PROPERTY,
+                    value,
+                    format!("invalid color stop \"{}\"", arg),
+                ))
+            }
+        }
+    }
+
+    let positions = fix_up_positions(&stops.iter().map(|(_, p)| *p).collect::<Vec<_>>());
+    let stops = stops
+        .iter()
+        .zip(positions)
+        .map(|((color, _), position)| (Color((*color).to_owned()), format!("{}%", position * 100.)))
+        .collect();
+
+    Ok(LinearGradient {
+        stops,
+        degree: angle - 90.,
+    })
+}
+
+/// Parse the direction of a gradient into a CSS angle, in degrees.
+///
+/// The corners are approximated with diagonal angles.
+fn parse_direction(arg: &str) -> Option<f32> {
+    if let Some(side) = arg.strip_prefix("to ") {
+        let mut sides: Vec<_> = side.split_whitespace().collect();
+        sides.sort_unstable();
+        return match sides[..] {
+            ["top"] => Some(0.),
+            ["right"] => Some(90.),
+            ["bottom"] => Some(180.),
+            ["left"] => Some(270.),
+            ["right", "top"] => Some(45.),
+            ["bottom", "right"] => Some(135.),
+            ["bottom", "left"] => Some(225.),
+            ["left", "top"] => Some(315.),
+            _ => None,
+        };
+    }
+
+    let units = [
+        ("deg", 1.),
+        ("grad", 0.9),
+        ("rad", 180. / std::f32::consts::PI),
+        ("turn", 360.),
+    ];
+    units.iter().find_map(|(unit, degrees)| {
+        let number: f32 = arg.strip_suffix(unit)?.parse().ok()?;
+        Some(number * degrees)
+    })
+}
+
+/// Fill in the missing stop positions, as CSS does.
+///
+/// The first and the last stops default to 0% and 100%, the others are
+/// spread evenly between their neighbors, and no stop is before the
+/// previous one.
+fn fix_up_positions(positions: &[Option<f32>]) -> Vec<f32> {
+    let mut fixed: Vec<Option<f32>> = positions.to_vec();
+    if let Some(first) = fixed.first_mut() {
+        first.get_or_insert(0.);
+    }
+    if let Some(last) = fixed.last_mut() {
+        last.get_or_insert(1.);
+    }
+
+    let mut max = 0f32;
+    for position in fixed.iter_mut().flatten() {
+        max = max.max(*position);
+        *position = max;
+    }
+
+    let mut i = 0;
+    while i < fixed.len() {
+        if fixed[i].is_some() {
+            i += 1;
+            continue;
+        }
+        // The first and the last stops are set, so the run has neighbors.
+        let start = i - 1;
+        let end = (i..fixed.len()).find(|&j| fixed[j].is_some()).unwrap();
+        let (from, to) = (fixed[start].unwrap(), fixed[end].unwrap());
+        for (k, position) in fixed[i..end].iter_mut().enumerate() {
+            *position = Some(from + (to - from) * (k + 1) as f32 / (end - start) as f32);
+        }
+        i = end;
+    }
+
+    fixed.into_iter().flatten().collect()
+}
+
+/// Parse `border-radius`, which must be a single radius.
+fn parse_border_radius(value: &str) -> Result<usize, CssError> {
+    const PROPERTY: &str = "border-radius";
+
+    if value.contains('/') {
+        return Err(invalid(
+            PROPERTY,
+            value,
+            "elliptical corners are not supported",
+        ));
+    }
+    let radii = split_top_level(value, ' ')
+        .into_iter()
+        .map(|token| parse_length(PROPERTY, value, token))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match radii[..] {
+        [radius, ..] if radii.iter().all(|r| *r == radius) => Ok(radius as usize),
+        _ => Err(invalid(
+            PROPERTY,
+            value,
+            "only the same radius for every corner is supported",
+        )),
+    }
+}
+
+/// Parse `box-shadow`, which must be a single black outer shadow.
+///
+/// The CSS blur radius is twice the standard deviation of the SVG filter.
+fn parse_box_shadow(value: &str) -> Result<DropShadow, CssError> {
+    const PROPERTY: &str = "box-shadow";
+
+    if split_top_level(value, ',').len() > 1 {
+        return Err(invalid(
+            PROPERTY,
+            value,
+            "only a single shadow is supported",
+        ));
+    }
+
+    let mut lengths = Vec::new();
+    // Without a color, the shadow is opaque `currentcolor`.
+    let mut opacity = 1.;
+    for token in split_top_level(value, ' ') {
+        if token.eq_ignore_ascii_case("inset") {
+            return Err(invalid(PROPERTY, value, "inset shadows are not supported"));
+        }
+        if token.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.') {
+            if token.starts_with('-') {
+                return Err(invalid(
+                    PROPERTY,
+                    value,
+                    "negative offsets are not supported",
+                ));
+            }
+            lengths.push(parse_length(PROPERTY, value, token)?);
+        } else {
+            opacity = black_alpha(token).ok_or_else(|| {
+                invalid(
+                    PROPERTY,
+                    value,
+                    "only black shadows are supported, the alpha of the color sets the opacity",
+                )
+            })?;
+        }
+    }
+
+    let (x, y, blur) = match lengths[..] {
+        [x, y] => (x, y, 0),
+        [x, y, blur] | [x, y, blur, 0] => (x, y, blur),
+        [_, _, _, _] => return Err(invalid(PROPERTY, value, "spread is not supported")),
+        _ => return Err(invalid(PROPERTY, value, "expected 2 to 4 lengths")),
+    };
+
+    Ok(DropShadow {
+        x: x as usize,
+        y: y as usize,
+        blur: (blur as f32 / 2.).round() as usize,
+        opacity,
+    })
+}
+
+/// The alpha of a black color, or `None` if the color is not black.
+fn black_alpha(color: &str) -> Option<f32> {
+    let color = color.to_ascii_lowercase();
+
+    if color == "black" {
+        return Some(1.);
+    }
+    if color == "transparent" {
+        return Some(0.);
+    }
+    if let Some(hex) = color.strip_prefix('#') {
+        let digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16)).collect::<Option<_>>()?;
+        return match digits[..] {
+            [0, 0, 0] => Some(1.),
+            [0, 0, 0, a] => Some((a * 17) as f32 / 255.),
+            [0, 0, 0, 0, 0, 0] => Some(1.),
+            [0, 0, 0, 0, 0, 0, a1, a2] => Some((a1 * 16 + a2) as f32 / 255.),
+            _ => None,
+        };
+    }
+
+    let args = function_args(&color, "rgba").or_else(|| function_args(&color, "rgb"))?;
+    // Both `rgb(0, 0, 0, .5)` and `rgb(0 0 0 / .5)`.
+    let args: Vec<_> = args
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect();
+    match args[..] {
+        [r, g, b, ref alpha @ ..] if [r, g, b].iter().all(|c| parse_fraction(c) == Some(0.)) => {
+            match alpha {
+                [] => Some(1.),
+                [alpha] => parse_fraction(alpha),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Parse `padding`, which must be the same on opposite sides.
+fn parse_padding(value: &str) -> Result<SizeOption, CssError> {
+    const PROPERTY: &str = "padding";
+
+    let lengths = split_top_level(value, ' ')
+        .into_iter()
+        .map(|token| parse_length(PROPERTY, value, token))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Top, right, bottom and left, as in CSS.
+    let (top, right, bottom, left) = match lengths[..] {
+        [all] => (all, all, all, all),
+        [vertical, horizontal] => (vertical, horizontal, vertical, horizontal),
+        [top, horizontal, bottom] => (top, horizontal, bottom, horizontal),
+        [top, right, bottom, left] => (top, right, bottom, left),
+        _ => return Err(invalid(PROPERTY, value, "expected 1 to 4 lengths")),
+    };
+    if top != bottom || left != right {
+        return Err(invalid(
+            PROPERTY,
+            value,
+            "the padding must be the same on opposite sides",
+        ));
+    }
+
+    Ok(if top == left {
+        SizeOption::FitContent(top)
+    } else {
+        SizeOption::FitContentXY(left, top)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(background: BackgroundType) -> (Vec<(String, String)>, f32) {
+        match background {
+            BackgroundType::Linear(gradient) => (
+                gradient
+                    .stops
+                    .into_iter()
+                    .map(|(color, offset)| (color.0, offset))
+                    .collect(),
+                gradient.degree,
+            ),
+            other => panic!("not a linear gradient: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn declarations() {
+        let css: Declarations = "border-radius: 20px; box-shadow: 5px 5px 14px rgba(0,0,0,.5);\n\
+             padding: 64px 80px; background: white !important;"
+            .parse()
+            .unwrap();
+
+        assert_eq!(css.border_radius, Some(20));
+        assert_eq!(
+            css.box_shadow,
+            Some(DropShadow {
+                x: 5,
+                y: 5,
+                blur: 7,
+                opacity: 0.5
+            })
+        );
+        assert_eq!(css.padding, Some(SizeOption::FitContentXY(80, 64)));
+        assert!(matches!(css.background, Some(BackgroundType::Pure(Color(c))) if c == "white"));
+
+        // Later declarations override earlier ones.
+        let css: Declarations = "padding: 1px; padding: 0".parse().unwrap();
+        assert_eq!(css.padding, Some(SizeOption::FitContent(0)));
+    }
+
+    #[test]
+    fn linear_gradient() {
+        let background =
+            parse_background("linear-gradient(35deg, hsl(240 46% 65%) 0%, #fff, red 80% 90%)")
+                .unwrap();
+        let (stops, degree) = gradient(background);
+        assert_eq!(degree, -55.);
+        assert_eq!(
+            stops,
+            [
+                ("hsl(240 46% 65%)".to_owned(), "0%".to_owned()),
+                ("#fff".to_owned(), "40%".to_owned()),
+                ("red".to_owned(), "80%".to_owned()),
+                ("red".to_owned(), "90%".to_owned()),
+            ]
+        );
+
+        let (stops, degree) = gradient(parse_background("linear-gradient(red, blue)").unwrap());
+        assert_eq!(degree, 90.);
+        assert_eq!(stops[1].1, "100%");
+
+        let (_, degree) =
+            gradient(parse_background("linear-gradient(to right, red, blue)").unwrap());
+        assert_eq!(degree, 0.);
+        let (_, degree) =
+            gradient(parse_background("linear-gradient(0.5turn, red, blue)").unwrap());
+        assert_eq!(degree, 90.);
+    }
+
+    #[test]
+    fn black_shadows() {
+        assert_eq!(black_alpha("black"), Some(1.));
+        assert_eq!(black_alpha("#00000080"), Some(128. / 255.));
+        assert_eq!(black_alpha("rgb(0 0 0 / 60%)"), Some(0.6));
+        assert_eq!(black_alpha("rgba(0, 0, 0, 0.25)"), Some(0.25));
+        assert_eq!(black_alpha("rgba(255, 0, 0, 0.25)"), None);
+        assert_eq!(black_alpha("#f00"), None);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |css: &str| css.parse::<Declarations>().unwrap_err();
+
+        assert_eq!(
+            error("margin: 10px"),
+            CssError::UnsupportedProperty("margin".to_owned())
+        );
+        assert_eq!(error("padding"), CssError::Syntax("padding".to_owned()));
+
+        let reason = |css: &str| match error(css) {
+            CssError::InvalidValue { reason, .. } => reason,
+            other => panic!("unexpected error: {}", other),
+        };
+        assert_eq!(
+            reason("border-radius: 10%"),
+            "only px lengths are supported"
+        );
+        assert_eq!(
+            reason("border-radius: 10px 5px"),
+            "only the same radius for every corner is supported"
+        );
+        assert_eq!(
+            reason("box-shadow: inset 1px 1px black"),
+            "inset shadows are not supported"
+        );
+        assert_eq!(
+            reason("box-shadow: -1px 1px"),
+            "negative offsets are not supported"
+        );
+        assert_eq!(
+            reason("box-shadow: 1px 1px 2px 3px"),
+            "spread is not supported"
+        );
+        assert_eq!(
+            reason("box-shadow: 1px 1px red"),
+            "only black shadows are supported, the alpha of the color sets the opacity"
+        );
+        assert_eq!(
+            reason("padding: 1px 2px 3px"),
+            "the padding must be the same on opposite sides"
+        );
+        assert_eq!(
+            reason("background: radial-gradient(red, blue)"),
+            "only colors and linear-gradient() are supported"
+        );
+        assert_eq!(
+            reason("background: linear-gradient(red 10px, blue)"),
+            "only percentage stop positions are supported, found \"10px\""
+        );
+    }
+
+    #[test]
+    fn serialization() {
+        let css: Declarations = "padding: 10px".parse().unwrap();
+        let yaml = serde_yaml::to_string(&css).unwrap();
+        assert_eq!(yaml, "'padding: 10px'\n");
+        assert_eq!(serde_yaml::from_str::<Declarations>(&yaml).unwrap(), css);
+    }
+}
@@ -1,3 +1,5 @@
+//! Basic geometry and color types shared by all layers.
+
 use serde::{Deserialize, Serialize};
 
 use crate::configs::style::{PositionOption, SizeOption};
@@ -16,7 +18,8 @@ impl From<(u32, u32)> for Size {
 /// Position is a tuple of x and y.
 pub struct Position(pub u32, pub u32);
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 /// Color is a string of color.
 ///
 /// Example: "red", "#ff0000", "rgb(255, 0, 0)"
@@ -1,9 +1,12 @@
+//! Image layers.
+
 use elementtree::Element;
 
 use crate::configs::style::{DropShadow, PositionOption, SizeOption};
 
 use super::foundation::{Position, PositionOptionT, Size, SizeOptionT};
 use super::svg::SvgTangibleObject;
+use crate::layout::Primitive;
 
 /// A struct that represents a image.
 #[derive(Debug)]
@@ -105,7 +108,7 @@ impl SvgTangibleObject for Image {
                 drop_shadow.set_attr("dx", ds.x.to_string());
                 drop_shadow.set_attr("dy", ds.y.to_string());
                 drop_shadow.set_attr("stdDeviation", ds.blur.to_string());
-                drop_shadow.set_attr("flood-opacity", "0.6");
+                drop_shadow.set_attr("flood-opacity", ds.opacity.to_string());
 
                 filter.append_child(drop_shadow);
                 defs.append_child(filter);
@@ -137,6 +140,15 @@ impl SvgTangibleObject for Image {
 
         (svg, None)
     }
+
+    fn primitive(&self) -> Option<Primitive<'_>> {
+        Some(Primitive::Image {
+            src: &self.path,
+            padding: self.get_padding(),
+            round: self.round,
+            shadow: self.shadow.as_ref(),
+        })
+    }
 }
 
 #[cfg(test)]
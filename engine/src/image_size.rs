@@ -0,0 +1,209 @@
+//! Header-only image size probing.
+//!
+//! [`HeaderSizeProvider`] reads the size of an image from the first bytes of
+//! its header, without decoding the pixels. It supports file paths and
+//! base64 data URLs as image sources, and raw bytes with [`probe_bytes`].
+
+use std::path::Path;
+
+use base64::{
+    alphabet,
+    engine::{general_purpose::GeneralPurpose, DecodePaddingMode, GeneralPurposeConfig},
+    Engine,
+};
+use imagesize::ImageError;
+
+use crate::configs::structure::{ImageSizeError, ImageSizeProvider};
+
+/// Base64 engine for data URLs, padding is optional when decoding.
+const BASE64: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The length of the first chunk of a data URL payload to decode.
+///
+/// Most headers fit in the first few hundred bytes,
+/// but JPEG may put large EXIF data before the frame header.
+const FIRST_CHUNK: usize = 4 * 1024;
+
+/// An [`ImageSizeProvider`] that reads only the image headers.
+///
+/// The image source can be a path to a local file or a base64 data URL.
+/// Remote images must be downloaded by the caller.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeaderSizeProvider;
+
+impl ImageSizeProvider for HeaderSizeProvider {
+    fn get_image_size(&self, src: &str) -> Result<(u32, u32), ImageSizeError> {
+        if src.starts_with("data:") {
+            probe_data_url(src)
+        } else if src.starts_with("http://") || src.starts_with("https://") {
+            Err(ImageSizeError::network(
+                src,
+                "remote images must be downloaded before probing",
+            ))
+        } else {
+            probe_path(src.strip_prefix("file://").unwrap_or(src))
+        }
+    }
+}
+
+/// Read the size of an encoded image.
+pub fn probe_bytes(bytes: &[u8]) -> Result<(u32, u32), ImageSizeError> {
+    imagesize::blob_size(bytes)
+        .map(|size| (size.width as u32, size.height as u32))
+        .map_err(|e| from_image_error("<bytes>", e))
+}
+
+/// Read the size of the image file at `path`.
+pub fn probe_path<P: AsRef<Path>>(path: P) -> Result<(u32, u32), ImageSizeError> {
+    let path = path.as_ref();
+    let src = path.to_string_lossy();
+
+    match imagesize::size(path) {
+        Ok(size) => Ok((size.width as u32, size.height as u32)),
+        Err(ImageError::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(ImageSizeError::not_found(&src))
+        }
+        Err(e) => Err(from_image_error(&src, e)),
+    }
+}
+
+/// Read the size of the image in a base64 data URL.
+///
+/// Only the beginning of the payload is decoded. The decoded prefix grows
+/// until the header is complete, so large images are never decoded as a whole
+/// unless the header is truncated.
+pub fn probe_data_url(url: &str) -> Result<(u32, u32), ImageSizeError> {
+    let (header, payload) = url
+        .strip_prefix("data:")
+        .and_then(|url| url.split_once(','))
+        .ok_or_else(|| ImageSizeError::decode(url, "invalid data URL"))?;
+    if !header.ends_with(";base64") {
+        return Err(ImageSizeError::unsupported_format(url));
+    }
+    let payload = payload.trim().as_bytes();
+
+    let mut len = FIRST_CHUNK;
+    loop {
+        // Keep the prefix aligned to whole base64 quanta.
+        let is_whole = len >= payload.len();
+        let chunk = if is_whole {
+            payload
+        } else {
+            &payload[..len / 4 * 4]
+        };
+
+        let bytes = BASE64
+            .decode(chunk)
+            .map_err(|e| ImageSizeError::decode(url, e))?;
+
+        match imagesize::blob_size(&bytes) {
+            Ok(size) => return Ok((size.width as u32, size.height as u32)),
+            Err(ImageError::NotSupported) => return Err(ImageSizeError::unsupported_format(url)),
+            Err(e) if is_whole => return Err(from_image_error(url, e)),
+            // The header is truncated, try a longer prefix.
+            Err(_) => len *= 16,
+        }
+    }
+}
+
+fn from_image_error(src: &str, e: ImageError) -> ImageSizeError {
+    match e {
+        ImageError::NotSupported => ImageSizeError::unsupported_format(src),
+        e => ImageSizeError::decode(src, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The header of a 123x321 PNG image.
+    const PNG_HEADER: [u8; 32] = [
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x7B, 0x00, 0x00, 0x01, 0x41, 0x08, 0x06, 0x00, 0x00, 0x00, 0x9A,
+        0x38, 0xC4,
+    ];
+
+    /// A JPEG image whose frame header comes after a large APP1 segment.
+    fn jpeg_with_large_exif() -> Vec<u8> {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // APP1, 60 KiB of padding.
+        let app1_len: u16 = 60 * 1024;
+        jpeg.extend_from_slice(&[0xFF, 0xE1]);
+        jpeg.extend_from_slice(&app1_len.to_be_bytes());
+        jpeg.resize(jpeg.len() + app1_len as usize - 2, 0);
+        // SOF0: 8 bits, 200x300, 3 components.
+        jpeg.extend_from_slice(&[
+            0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0x2C, 0x00, 0xC8, 0x03, 0x01, 0x22, 0x00, 0x02,
+            0x11, 0x01, 0x03, 0x11, 0x01,
+        ]);
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+        jpeg
+    }
+
+    fn data_url(bytes: &[u8]) -> String {
+        format!("data:image/png;base64,{}", BASE64.encode(bytes))
+    }
+
+    #[test]
+    fn probe_png() {
+        assert_eq!(probe_bytes(&PNG_HEADER), Ok((123, 321)));
+        assert_eq!(probe_data_url(&data_url(&PNG_HEADER)), Ok((123, 321)));
+
+        // Trailing pixel data is never decoded.
+        let mut png = PNG_HEADER.to_vec();
+        png.resize(png.len() + 1024 * 1024, 0);
+        assert_eq!(
+            HeaderSizeProvider.get_image_size(&data_url(&png)),
+            Ok((123, 321))
+        );
+    }
+
+    #[test]
+    fn probe_large_header() {
+        let jpeg = jpeg_with_large_exif();
+
+        assert_eq!(probe_bytes(&jpeg), Ok((200, 300)));
+        assert_eq!(probe_data_url(&data_url(&jpeg)), Ok((200, 300)));
+    }
+
+    #[test]
+    fn probe_file() {
+        // The process id keeps concurrent test runs apart.
+        let path =
+            std::env::temp_dir().join(format!("footlights-probe-file-{}.png", std::process::id()));
+        std::fs::write(&path, PNG_HEADER).unwrap();
+
+        let src = path.to_str().unwrap();
+        assert_eq!(HeaderSizeProvider.get_image_size(src), Ok((123, 321)));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            HeaderSizeProvider.get_image_size(src),
+            Err(ImageSizeError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn probe_errors() {
+        assert!(matches!(
+            probe_data_url(&data_url(b"not an image at all")),
+            Err(ImageSizeError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            probe_data_url(&data_url(&PNG_HEADER[..14])),
+            Err(ImageSizeError::Decode { .. })
+        ));
+        assert!(matches!(
+            probe_data_url("data:image/svg+xml,<svg/>"),
+            Err(ImageSizeError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            HeaderSizeProvider.get_image_size("https://example.com/a.png"),
+            Err(ImageSizeError::Network { .. })
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! The computed layout of a [`Canvas`], for backends that draw it without
+//! going through SVG.
+//!
+//! [`Canvas::layout`] places every layer, as [`Canvas::to_svg_string`]
+//! does, and [`SvgTangibleObject::primitive`] describes what a layer draws.
+//! A backend that knows every primitive of a canvas can draw it directly.
+
+use crate::{
+    background::{BackgroundType, LinearGradient},
+    configs::style::DropShadow,
+    foundation::{Position, Size},
+    svg::SvgTangibleObject,
+    Canvas,
+};
+
+/// A layer with its computed size and position on the canvas.
+#[derive(Debug, Clone, Copy)]
+pub struct Placement<'a> {
+    /// The layer.
+    pub layer: &'a dyn SvgTangibleObject,
+    /// The size of the layer, with its padding.
+    pub size: Size,
+    /// The position of the layer on the canvas.
+    pub position: Position,
+}
+
+/// What a layer draws in its placement.
+#[derive(Debug, Clone, Copy)]
+pub enum Primitive<'a> {
+    /// A rectangle filled with a color, as written in the style.
+    Color(&'a str),
+    /// A rectangle filled with a linear gradient.
+    Linear(&'a LinearGradient),
+    /// An image, inside the padding of its placement.
+    Image {
+        /// The image source.
+        src: &'a str,
+        /// The space around the image, left for the shadow.
+        padding: (usize, usize),
+        /// The rounded corner radius of the image.
+        round: Option<usize>,
+        /// The drop shadow of the image.
+        shadow: Option<&'a DropShadow>,
+    },
+}
+
+impl<'a> Primitive<'a> {
+    /// The primitive of a background fill, `None` if it has none.
+    pub(crate) fn from_background(bg_type: &'a BackgroundType) -> Option<Self> {
+        match bg_type {
+            BackgroundType::Pure(color) => Some(Self::Color(&color.0)),
+            BackgroundType::Linear(linear) => Some(Self::Linear(linear)),
+            BackgroundType::Radial(_) => None,
+            BackgroundType::Preset(name) => {
+                Self::from_background(crate::presets::get(name)?.color()?)
+            }
+        }
+    }
+}
+
+impl Canvas {
+    /// Place the layers, from the bottom to the top.
+    ///
+    /// The sizes are computed from the top to the bottom, each layer fits
+    /// the layer above it, then the positions from the bottom to the top.
+    pub fn layout(&self) -> Vec<Placement<'_>> {
+        let mut child_size = Size::default();
+        let mut sizes: Vec<_> = self
+            .layers
+            .iter()
+            .rev()
+            .map(|layer| {
+                child_size = layer.cal_size(child_size);
+                child_size
+            })
+            .collect();
+        sizes.reverse();
+
+        let mut parent_size = Size::default();
+        self.layers
+            .iter()
+            .zip(sizes)
+            .map(|(layer, size)| {
+                let position = layer.cal_position(parent_size, size);
+                parent_size = size;
+                Placement {
+                    layer: layer.as_ref(),
+                    size,
+                    position,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{background::Background, foundation::Color, image::Image};
+
+    #[test]
+    fn place_layers() {
+        let mut canvas = Canvas::new();
+        canvas.add_layer_on_top(Box::new(Background::new_pure(Color::from("red"))));
+        canvas.add_layer_on_top(Box::new(Image::new_from_path(
+            "resource:0".to_owned(),
+            (300, 200),
+        )));
+
+        let layout = canvas.layout();
+        let placed: Vec<_> = layout
+            .iter()
+            .map(|p| (p.size.0, p.size.1, p.position.0, p.position.1))
+            .collect();
+        assert_eq!(placed, [(500, 400, 0, 0), (300, 200, 100, 100)]);
+
+        assert!(matches!(
+            layout[0].layer.primitive(),
+            Some(Primitive::Color("red"))
+        ));
+        assert!(matches!(
+            layout[1].layer.primitive(),
+            Some(Primitive::Image {
+                src: "resource:0",
+                padding: (0, 0),
+                round: None,
+                shadow: None
+            })
+        ));
+    }
+}
@@ -4,11 +4,21 @@
 
 pub mod configs;
 
-pub mod svg;
-pub mod foundation;
-pub mod shape;
+pub mod css;
+
 pub mod background;
+pub mod foundation;
 pub mod image;
+pub mod image_size;
+pub mod layout;
+pub mod limits;
+pub mod presets;
+pub mod render;
+pub mod resources;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod shape;
+pub mod svg;
 
 pub use svg::Canvas;
 
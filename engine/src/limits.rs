@@ -0,0 +1,188 @@
+//! Limits on the sizes of the inputs and of the renders.
+//!
+//! A small file can declare a huge image, and a config can declare a huge
+//! canvas. The limits are checked against the sizes that are known before
+//! anything is decoded or allocated: the length of a file, the dimensions
+//! in the header of an image, and the size of the [`crate::Canvas`].
+
+use thiserror::Error;
+
+use crate::foundation::Size;
+
+/// The bytes of a pixel of a raster, in RGBA.
+const BYTES_PER_PIXEL: u64 = 4;
+
+/// The error type of the sizes over a limit.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LimitError {
+    /// An input file is larger than [`Limits::max_file_size`].
+    #[error("The file is {size} bytes, larger than the limit of {limit} bytes.")]
+    FileSize {
+        /// The size of the file, in bytes.
+        size: u64,
+        /// The limit, in bytes.
+        limit: u64,
+    },
+    /// An input image has more pixels than [`Limits::max_input_pixels`].
+    #[error("The image is {width}x{height}, more than the limit of {limit} pixels.")]
+    InputPixels {
+        /// The width of the image.
+        width: u32,
+        /// The height of the image.
+        height: u32,
+        /// The limit, in pixels.
+        limit: u64,
+    },
+    /// The canvas is wider or taller than [`Limits::max_canvas_size`].
+    #[error("The canvas is {width}x{height}, larger than the limit of {limit}x{limit}.")]
+    CanvasSize {
+        /// The width of the canvas.
+        width: u32,
+        /// The height of the canvas.
+        height: u32,
+        /// The limit of the width and of the height.
+        limit: u32,
+    },
+    /// The raster of the canvas needs more than [`Limits::max_raster_memory`].
+    #[error(
+        "Rendering {width}x{height} needs {bytes} bytes, more than the limit of {limit} bytes."
+    )]
+    RasterMemory {
+        /// The width of the raster.
+        width: u32,
+        /// The height of the raster.
+        height: u32,
+        /// The memory the raster needs, in bytes.
+        bytes: u64,
+        /// The limit, in bytes.
+        limit: u64,
+    },
+}
+
+/// The limits of a render.
+///
+/// The defaults fit an 8K screenshot with a large margin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest input file, in bytes.
+    pub max_file_size: u64,
+    /// The most pixels of an input image.
+    pub max_input_pixels: u64,
+    /// The largest width and height of the canvas.
+    pub max_canvas_size: u32,
+    /// The most memory of the raster of the canvas, in bytes.
+    pub max_raster_memory: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_file_size: 64 * 1024 * 1024,
+            max_input_pixels: 64 * 1024 * 1024,
+            max_canvas_size: 16384,
+            max_raster_memory: 512 * 1024 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// Check the size of an input file, in bytes.
+    pub fn check_file_size(&self, size: u64) -> Result<(), LimitError> {
+        if size > self.max_file_size {
+            return Err(LimitError::FileSize {
+                size,
+                limit: self.max_file_size,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check the dimensions of an input image, as read from its header.
+    pub fn check_input(&self, width: u32, height: u32) -> Result<(), LimitError> {
+        if u64::from(width) * u64::from(height) > self.max_input_pixels {
+            return Err(LimitError::InputPixels {
+                width,
+                height,
+                limit: self.max_input_pixels,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check the size of the canvas.
+    pub fn check_canvas(&self, size: Size) -> Result<(), LimitError> {
+        let Size(width, height) = size;
+        if width > self.max_canvas_size || height > self.max_canvas_size {
+            return Err(LimitError::CanvasSize {
+                width,
+                height,
+                limit: self.max_canvas_size,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check the memory of a raster, before it is allocated.
+    pub fn check_raster(&self, width: u32, height: u32) -> Result<(), LimitError> {
+        let bytes = (u64::from(width) * u64::from(height)).saturating_mul(BYTES_PER_PIXEL);
+        if bytes > self.max_raster_memory {
+            return Err(LimitError::RasterMemory {
+                width,
+                height,
+                bytes,
+                limit: self.max_raster_memory,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_limits() {
+        let limits = Limits::default();
+
+        assert_eq!(limits.check_file_size(1024), Ok(()));
+        assert!(matches!(
+            limits.check_file_size(u64::MAX),
+            Err(LimitError::FileSize { .. })
+        ));
+
+        assert_eq!(limits.check_input(7680, 4320), Ok(()));
+        assert_eq!(
+            limits.check_input(100_000, 100_000),
+            Err(LimitError::InputPixels {
+                width: 100_000,
+                height: 100_000,
+                limit: 64 * 1024 * 1024,
+            })
+        );
+
+        assert_eq!(limits.check_canvas(Size(7680, 4320)), Ok(()));
+        assert_eq!(
+            limits
+                .check_canvas(Size(100_000, 100_000))
+                .unwrap_err()
+                .to_string(),
+            "The canvas is 100000x100000, larger than the limit of 16384x16384."
+        );
+
+        assert_eq!(limits.check_raster(7680, 4320), Ok(()));
+        assert_eq!(
+            limits.check_raster(100_000, 100_000),
+            Err(LimitError::RasterMemory {
+                width: 100_000,
+                height: 100_000,
+                bytes: 40_000_000_000,
+                limit: 512 * 1024 * 1024,
+            })
+        );
+    }
+}
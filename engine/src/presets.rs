@@ -0,0 +1,203 @@
+//! Built-in presets: gradients, shadows, frames and complete looks.
+//!
+//! The presets are defined in `presets.yaml` and compiled into the engine.
+//! A style can extend a preset by name, like another style of the config,
+//! and a background can use the gradient of a preset with
+//! `color: !Preset sunset`.
+
+use std::{collections::BTreeMap, fmt, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    background::BackgroundType,
+    configs::{style::Style, Config},
+};
+
+/// The kind of a preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresetKind {
+    /// A background gradient.
+    Gradient,
+    /// The shadow of an image.
+    Shadow,
+    /// The corners and the shadow of an image.
+    Frame,
+    /// A complete config.
+    Look,
+}
+
+impl fmt::Display for PresetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Gradient => "gradient",
+            Self::Shadow => "shadow",
+            Self::Frame => "frame",
+            Self::Look => "look",
+        })
+    }
+}
+
+/// A built-in preset.
+#[derive(Debug, Clone, Serialize)]
+pub struct Preset {
+    /// The name of the preset.
+    pub name: String,
+    /// The kind of the preset.
+    pub kind: PresetKind,
+    /// What the preset looks like.
+    pub description: String,
+    /// The config of the preset, see [`Preset::config`].
+    config: Config,
+}
+
+/// A preset, as written in `presets.yaml`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    kind: PresetKind,
+    description: String,
+    #[serde(default)]
+    color: Option<BackgroundType>,
+    #[serde(default)]
+    style: Option<Style>,
+    #[serde(default)]
+    config: Option<Config>,
+}
+
+/// The style a gradient preset sets.
+const BACKGROUND_STYLE: &str = "bg";
+/// The style a shadow or frame preset sets.
+const IMAGE_STYLE: &str = "img";
+
+impl Preset {
+    fn from_entry(name: String, entry: Entry) -> Self {
+        let mut config = entry.config.unwrap_or_default();
+        if let Some(color) = entry.color {
+            let style = Style {
+                color: Some(color),
+                ..Default::default()
+            };
+            config.styles.add(BACKGROUND_STYLE.to_owned(), style);
+        }
+        if let Some(style) = entry.style {
+            config.styles.add(IMAGE_STYLE.to_owned(), style);
+        }
+
+        Self {
+            name,
+            kind: entry.kind,
+            description: entry.description,
+            config,
+        }
+    }
+
+    /// The preset as a config, to merge user configs over.
+    ///
+    /// A gradient sets the `bg` style, a shadow or a frame the `img` style,
+    /// which are the styles of the default layers.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The style to extend, `None` for a look.
+    pub fn style(&self) -> Option<&Style> {
+        match self.kind {
+            PresetKind::Gradient => self.config.styles.get(BACKGROUND_STYLE),
+            PresetKind::Shadow | PresetKind::Frame => self.config.styles.get(IMAGE_STYLE),
+            PresetKind::Look => None,
+        }
+    }
+
+    /// The gradient of a gradient preset.
+    pub fn color(&self) -> Option<&BackgroundType> {
+        match self.kind {
+            PresetKind::Gradient => self.style().and_then(|style| style.color.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// The presets, indexed by name.
+fn presets() -> &'static BTreeMap<String, Preset> {
+    static PRESETS: OnceLock<BTreeMap<String, Preset>> = OnceLock::new();
+
+    PRESETS.get_or_init(|| {
+        let entries: BTreeMap<String, Entry> =
+            serde_yaml::from_str(include_str!("../presets.yaml"))
+                .expect("the built-in presets are valid");
+        entries
+            .into_iter()
+            .map(|(name, entry)| (name.clone(), Preset::from_entry(name, entry)))
+            .collect()
+    })
+}
+
+/// Get the preset with the name.
+pub fn get(name: &str) -> Option<&'static Preset> {
+    presets().get(name)
+}
+
+/// Iterate over the presets, by name.
+pub fn iter() -> impl Iterator<Item = &'static Preset> {
+    presets().values()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::FixedSizeProvider;
+
+    #[test]
+    fn presets_are_valid() {
+        assert!(iter().count() >= 10);
+
+        for preset in iter() {
+            let config = preset.config();
+            assert!(config.layers.is_none(), "{}", preset.name);
+
+            match preset.kind {
+                PresetKind::Gradient => assert!(preset.color().is_some(), "{}", preset.name),
+                PresetKind::Shadow | PresetKind::Frame => {
+                    let style = preset.style().unwrap();
+                    assert!(style.parents().next().is_none(), "{}", preset.name);
+                    assert!(style.shadow.is_some(), "{}", preset.name);
+                }
+                PresetKind::Look => {
+                    // A look only misses the image.
+                    let mut config = config.clone();
+                    config
+                        .merge(serde_yaml::from_str("styles: { img: { image: a.png } }").unwrap());
+                    assert!(config.check().is_empty(), "{}", preset.name);
+                    config.build_canvas(FixedSizeProvider(200, 100)).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn use_presets() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+styles:
+  bg: { color: !Preset ocean }
+  img: { extends: [window, hard-shadow], image: a.png, round: 4 }
+"#,
+        )
+        .unwrap();
+        let styles = config.styles.resolve().unwrap();
+
+        assert_eq!(
+            styles.get("bg").unwrap().color.as_ref(),
+            get("ocean").unwrap().color()
+        );
+        let img = styles.get("img").unwrap();
+        assert_eq!(img.round, Some(4));
+        assert_eq!(
+            img.shadow,
+            get("hard-shadow").unwrap().style().unwrap().shadow
+        );
+        assert!(config.check().is_empty());
+    }
+}
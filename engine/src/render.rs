@@ -0,0 +1,97 @@
+//! The backends that render a [`Canvas`].
+//!
+//! A backend implements [`Renderer`], and a canvas is rendered with
+//! [`Canvas::render`]. [`Svg`] writes the SVG string of the canvas. The
+//! `raster` feature adds [`raster::Raster`], which renders it with resvg.
+
+use anyhow::Result;
+
+use crate::Canvas;
+
+#[cfg(feature = "raster")]
+mod direct;
+#[cfg(feature = "raster")]
+pub mod raster;
+
+/// A backend of [`Canvas::render`].
+pub trait Renderer {
+    /// The options of a render.
+    type Options<'a>;
+    /// What a render returns.
+    type Output;
+
+    /// Render the canvas.
+    fn render(canvas: &Canvas, options: Self::Options<'_>) -> Result<Self::Output>;
+}
+
+/// The SVG backend, which returns the SVG string of the canvas.
+///
+/// The images are written as they are referenced, handles included, see
+/// [`crate::resources::Resources::inline`].
+#[derive(Debug, Clone, Copy)]
+pub struct Svg;
+
+impl Renderer for Svg {
+    type Options<'a> = ();
+    type Output = String;
+
+    fn render(canvas: &Canvas, _: ()) -> Result<String> {
+        canvas.to_svg_string()
+    }
+}
+
+impl Canvas {
+    /// Render the canvas with the backend `R`, as
+    /// `canvas.render::<Svg>(())`.
+    pub fn render<R: Renderer>(&self, options: R::Options<'_>) -> Result<R::Output> {
+        R::render(self, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{background::Background, foundation::Color};
+
+    fn canvas() -> Canvas {
+        let mut canvas = Canvas::new();
+        canvas.add_layer_on_top(Box::new(Background::new_pure(Color::from("#ff0000"))));
+        canvas
+    }
+
+    #[test]
+    fn render_svg() {
+        let canvas = canvas();
+        assert_eq!(
+            canvas.render::<Svg>(()).unwrap(),
+            canvas.to_svg_string().unwrap()
+        );
+    }
+
+    #[cfg(feature = "raster")]
+    #[test]
+    fn render_raster() {
+        use raster::{fontdb, Raster, RasterOptions};
+
+        let options = RasterOptions {
+            resources: &Default::default(),
+            fontdb: &fontdb::Database::new(),
+            limits: Default::default(),
+        };
+        let pixmap = canvas().render::<Raster>(options).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (200, 200));
+        assert!(pixmap
+            .data()
+            .chunks(4)
+            .all(|pixel| pixel == [255, 0, 0, 255]));
+
+        let options = RasterOptions {
+            limits: crate::limits::Limits {
+                max_raster_memory: 1024,
+                ..Default::default()
+            },
+            ..options
+        };
+        assert!(canvas().render::<Raster>(options).is_err());
+    }
+}
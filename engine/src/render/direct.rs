@@ -0,0 +1,542 @@
+//! The fast path of [`super::raster`], drawing the layout of a canvas with
+//! tiny-skia.
+//!
+//! The SVG path writes the canvas as SVG and parses it back with usvg. When
+//! every layer has a primitive, see [`crate::layout`], the layers are drawn
+//! directly instead. Each primitive is drawn the way resvg draws the SVG of
+//! its layer, so both paths give the same pixels, up to rounding: the tests
+//! compare them.
+
+use crate::{
+    background::LinearGradient,
+    configs::style::DropShadow,
+    foundation::{Position, Size},
+    layout::{Placement, Primitive},
+    limits::Limits,
+    resources::Resources,
+    Canvas,
+};
+use anyhow::{anyhow, Result};
+use resvg::tiny_skia::{
+    self, BlendMode, Color, FillRule, FilterQuality, GradientStop, Paint, Path, PathBuilder,
+    Pattern, Pixmap, PixmapPaint, Point, Rect, SpreadMode, Transform,
+};
+use rgb::FromSlice;
+
+/// The control point distance of a cubic quarter circle, for a radius of 1.
+const KAPPA: f32 = 0.552_284_8;
+
+/// A layer, with the colors parsed and the image decoded.
+struct Layer<'a> {
+    size: Size,
+    position: Position,
+    fill: Fill<'a>,
+}
+
+enum Fill<'a> {
+    Paint(Paint<'static>),
+    Image {
+        pixmap: Pixmap,
+        padding: (usize, usize),
+        round: Option<usize>,
+        shadow: Option<&'a DropShadow>,
+    },
+}
+
+/// Render the canvas, `None` if a layer can only be drawn through SVG.
+///
+/// The images must be handles of `resources`.
+pub(super) fn render(
+    canvas: &Canvas,
+    resources: &Resources,
+    limits: &Limits,
+) -> Result<Option<Pixmap>> {
+    let layout = canvas.layout();
+    let layers: Option<Vec<_>> = layout
+        .iter()
+        .map(|placement| Layer::new(placement, resources))
+        .collect();
+    let (Some(layers), Some(bottom)) = (layers, layout.first()) else {
+        return Ok(None);
+    };
+
+    let Size(width, height) = bottom.size;
+    limits.check_raster(width, height)?;
+    let mut pixmap = Pixmap::new(width, height).ok_or_else(|| anyhow!("The canvas is empty."))?;
+    for layer in &layers {
+        layer.draw(&mut pixmap, limits)?;
+    }
+
+    Ok(Some(pixmap))
+}
+
+impl<'a> Layer<'a> {
+    fn new(placement: &Placement<'a>, resources: &Resources) -> Option<Self> {
+        let Placement { size, position, .. } = *placement;
+        let fill = match placement.layer.primitive()? {
+            Primitive::Color(color) => {
+                let color = parse_color(color)?;
+                let mut paint = Paint {
+                    anti_alias: true,
+                    ..Default::default()
+                };
+                paint.set_color_rgba8(color.red, color.green, color.blue, color.alpha);
+                Fill::Paint(paint)
+            }
+            Primitive::Linear(linear) => Fill::Paint(linear_paint(linear, size, position)?),
+            Primitive::Image {
+                src,
+                padding,
+                round,
+                shadow,
+            } => Fill::Image {
+                pixmap: decode(resources, src)?,
+                padding,
+                round,
+                shadow,
+            },
+        };
+
+        Some(Self {
+            size,
+            position,
+            fill,
+        })
+    }
+
+    fn draw(&self, canvas: &mut Pixmap, limits: &Limits) -> Result<()> {
+        let (Size(width, height), Position(x, y)) = (self.size, self.position);
+        let Some(rect) = Rect::from_xywh(x as f32, y as f32, width as f32, height as f32) else {
+            return Ok(());
+        };
+
+        match &self.fill {
+            Fill::Paint(paint) => {
+                canvas.fill_path(
+                    &PathBuilder::from_rect(rect),
+                    paint,
+                    FillRule::Winding,
+                    Transform::identity(),
+                    None,
+                );
+            }
+            Fill::Image {
+                pixmap,
+                padding,
+                round,
+                shadow,
+            } => {
+                // A nested SVG, which clips what it draws to its box.
+                limits.check_raster(width, height)?;
+                let Some(mut layer) = Pixmap::new(width, height) else {
+                    return Ok(());
+                };
+                let inner = Rect::from_xywh(
+                    padding.0 as f32,
+                    padding.1 as f32,
+                    width.saturating_sub(padding.0 as u32 * 2) as f32,
+                    height.saturating_sub(padding.1 as u32 * 2) as f32,
+                );
+                if let Some(inner) = inner.filter(|r| r.width() > 0.0 && r.height() > 0.0) {
+                    let round = round.map(|round| round as f32);
+                    if let Some(shadow) = shadow {
+                        draw_shadow(&mut layer, canvas, self.position, inner, round, shadow);
+                    }
+                    draw_image(&mut layer, pixmap, inner, round);
+                }
+
+                canvas.draw_pixmap(
+                    x as i32,
+                    y as i32,
+                    layer.as_ref(),
+                    &PixmapPaint::default(),
+                    Transform::identity(),
+                    None,
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse a color as usvg does, `None` if it is not a plain color.
+fn parse_color(color: &str) -> Option<svgtypes::Color> {
+    color.trim().parse().ok()
+}
+
+/// The paint of a linear gradient over the box of its layer.
+///
+/// The gradient goes from the left to the right of the box, rotated by its
+/// degree around the top left corner, as the `gradientTransform` of SVG.
+fn linear_paint(linear: &LinearGradient, size: Size, position: Position) -> Option<Paint<'static>> {
+    let mut previous = 0.0;
+    let stops = linear
+        .stops()
+        .iter()
+        .map(|(color, offset)| {
+            let color = parse_color(&color.0)?;
+            // A missing or invalid offset is the one of the previous stop.
+            let offset = match offset.trim().parse::<svgtypes::Length>() {
+                Ok(length) if length.unit == svgtypes::LengthUnit::None => length.number,
+                Ok(length) if length.unit == svgtypes::LengthUnit::Percent => length.number / 100.0,
+                _ => previous,
+            }
+            .clamp(0.0, 1.0);
+            previous = offset;
+
+            Some(GradientStop::new(
+                offset as f32,
+                Color::from_rgba8(color.red, color.green, color.blue, color.alpha),
+            ))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if stops.is_empty() {
+        return None;
+    }
+
+    let Size(width, height) = size;
+    let transform = Transform::from_row(
+        width as f32,
+        0.0,
+        0.0,
+        height as f32,
+        position.0 as f32,
+        position.1 as f32,
+    )
+    .pre_concat(Transform::from_rotate(linear.degree()));
+    let shader = tiny_skia::LinearGradient::new(
+        Point::from_xy(0.0, 0.0),
+        Point::from_xy(1.0, 0.0),
+        stops,
+        SpreadMode::Pad,
+        transform,
+    )?;
+
+    Some(Paint {
+        shader,
+        anti_alias: true,
+        ..Default::default()
+    })
+}
+
+/// Decode an image of the table into a premultiplied pixmap.
+///
+/// Only the formats that the SVG path decodes are accepted.
+fn decode(resources: &Resources, src: &str) -> Option<Pixmap> {
+    let resource = resources.get(src)?;
+    if !matches!(
+        resource.mime_type.as_str(),
+        "image/png" | "image/jpeg" | "image/gif"
+    ) {
+        return None;
+    }
+
+    let image = ::image::load_from_memory(&resource.bytes).ok()?.to_rgba8();
+    let mut pixmap = Pixmap::new(image.width(), image.height())?;
+    for (pixel, rgba) in pixmap.data_mut().chunks_exact_mut(4).zip(image.pixels()) {
+        let [r, g, b, a] = rgba.0;
+        let alpha = f64::from(a) / 255.0;
+        let premultiply = |c: u8| (f64::from(c) * alpha + 0.5) as u8;
+        pixel.copy_from_slice(&[premultiply(r), premultiply(g), premultiply(b), a]);
+    }
+
+    Some(pixmap)
+}
+
+/// A rectangle with rounded corners, as usvg builds a `rect` with `rx`.
+fn rounded_rect(rect: Rect, round: Option<f32>) -> Option<Path> {
+    let round = round.unwrap_or(0.0);
+    if round <= 0.0 {
+        return Some(PathBuilder::from_rect(rect));
+    }
+
+    let rx = round.min(rect.width() / 2.0);
+    let ry = round.min(rect.height() / 2.0);
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let (left, top, right, bottom) = (rect.left(), rect.top(), rect.right(), rect.bottom());
+
+    let mut builder = PathBuilder::new();
+    builder.move_to(left + rx, top);
+    builder.line_to(right - rx, top);
+    builder.cubic_to(right - rx + kx, top, right, top + ry - ky, right, top + ry);
+    builder.line_to(right, bottom - ry);
+    builder.cubic_to(
+        right,
+        bottom - ry + ky,
+        right - rx + kx,
+        bottom,
+        right - rx,
+        bottom,
+    );
+    builder.line_to(left + rx, bottom);
+    builder.cubic_to(
+        left + rx - kx,
+        bottom,
+        left,
+        bottom - ry + ky,
+        left,
+        bottom - ry,
+    );
+    builder.line_to(left, top + ry);
+    builder.cubic_to(left, top + ry - ky, left + rx - kx, top, left + rx, top);
+    builder.close();
+    builder.finish()
+}
+
+/// Draw the drop shadow of the image, as resvg applies `feDropShadow` to a
+/// black rectangle under the image.
+///
+/// The filter works in the default filter region, 10% around the
+/// rectangle, on the canvas. The shadow is cut at the edges of the region.
+fn draw_shadow(
+    layer: &mut Pixmap,
+    canvas: &Pixmap,
+    position: Position,
+    inner: Rect,
+    round: Option<f32>,
+    shadow: &DropShadow,
+) -> Option<()> {
+    // The region in pixels, as usvg rounds it, then fitted into the canvas.
+    let (width, height) = (f64::from(inner.width()), f64::from(inner.height()));
+    let left = f64::from(position.0) + f64::from(inner.x()) - width * 0.1;
+    let top = f64::from(position.1) + f64::from(inner.y()) - height * 0.1;
+    // The size is kept when the region is moved into the canvas.
+    let (x, y) = ((left as i32).max(0), (top as i32).max(0));
+    let mut region_width = ((width * 1.2).round() as i32).max(1);
+    let mut region_height = ((height * 1.2).round() as i32).max(1);
+    if x + region_width > canvas.width() as i32 {
+        region_width = (canvas.width() as i32 - x).max(1);
+    }
+    if y + region_height > canvas.height() as i32 {
+        region_height = (canvas.height() as i32 - y).max(1);
+    }
+
+    // The source graphic: the rectangle, in the region.
+    let mut source = Pixmap::new(region_width as u32, region_height as u32)?;
+    let black = Paint {
+        anti_alias: true,
+        ..Default::default()
+    };
+    source.fill_path(
+        &rounded_rect(inner, round)?,
+        &black,
+        FillRule::Winding,
+        Transform::from_translate(
+            (position.0 as i32 - x) as f32,
+            (position.1 as i32 - y) as f32,
+        ),
+        None,
+    );
+
+    let mut blurred = source.clone();
+    if shadow.blur > 0 {
+        let sigma = shadow.blur as f64;
+        let (width, height) = (blurred.width(), blurred.height());
+        let image = svgfilters::ImageRefMut::new(blurred.data_mut().as_rgba_mut(), width, height);
+        // resvg switches to a box blur for large deviations.
+        match sigma >= 2.0 {
+            true => svgfilters::box_blur(sigma, sigma, image),
+            false => svgfilters::iir_blur(sigma, sigma, image),
+        }
+    }
+
+    // The flood, black at the opacity of the shadow, keeps the blurred alpha.
+    let alpha = (shadow.opacity.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+    for pixel in blurred.pixels_mut() {
+        let mut color = Color::from_rgba8(0, 0, 0, alpha);
+        color.apply_opacity(f32::from(pixel.alpha()) / 255.0);
+        *pixel = color.premultiply().to_color_u8();
+    }
+
+    let mut result = Pixmap::new(source.width(), source.height())?;
+    let paint = PixmapPaint::default();
+    for (pixmap, dx, dy) in [
+        (&blurred, shadow.x as i32, shadow.y as i32),
+        (&source, 0, 0),
+    ] {
+        result.draw_pixmap(dx, dy, pixmap.as_ref(), &paint, Transform::identity(), None);
+    }
+    layer.draw_pixmap(
+        x - position.0 as i32,
+        y - position.1 as i32,
+        result.as_ref(),
+        &paint,
+        Transform::identity(),
+        None,
+    );
+
+    Some(())
+}
+
+/// Draw the image into `inner`, fitted and centered as SVG does by default,
+/// and clipped to the rounded corners.
+fn draw_image(layer: &mut Pixmap, image: &Pixmap, inner: Rect, round: Option<f32>) -> Option<()> {
+    let (image_width, image_height) = (image.width() as f32, image.height() as f32);
+    let scale = (inner.width() / image_width).min(inner.height() / image_height);
+    let (width, height) = (image_width * scale, image_height * scale);
+    let rect = Rect::from_xywh(
+        inner.x() + (inner.width() - width) / 2.0,
+        inner.y() + (inner.height() - height) / 2.0,
+        width,
+        height,
+    )?;
+
+    let transform = Transform::from_row(
+        rect.width() / image_width,
+        0.0,
+        0.0,
+        rect.height() / image_height,
+        rect.x(),
+        rect.y(),
+    );
+    let paint = Paint {
+        shader: Pattern::new(
+            image.as_ref(),
+            SpreadMode::Pad,
+            FilterQuality::Bicubic,
+            1.0,
+            transform,
+        ),
+        ..Default::default()
+    };
+
+    if round.is_none() {
+        layer.fill_rect(rect, &paint, Transform::identity(), None);
+        return Some(());
+    }
+
+    // A clipped image is drawn alone, then its outside is cleared, as
+    // resvg draws a group with a clip path.
+    let mut group = Pixmap::new(layer.width(), layer.height())?;
+    group.fill_rect(rect, &paint, Transform::identity(), None);
+    let mut clip = Pixmap::new(layer.width(), layer.height())?;
+    clip.fill(Color::BLACK);
+    let clear = Paint {
+        blend_mode: BlendMode::Clear,
+        anti_alias: true,
+        ..Default::default()
+    };
+    clip.fill_path(
+        &rounded_rect(inner, round)?,
+        &clear,
+        FillRule::Winding,
+        Transform::identity(),
+        None,
+    );
+    let destination_out = PixmapPaint {
+        blend_mode: BlendMode::DestinationOut,
+        ..Default::default()
+    };
+    group.draw_pixmap(
+        0,
+        0,
+        clip.as_ref(),
+        &destination_out,
+        Transform::identity(),
+        None,
+    );
+    layer.draw_pixmap(
+        0,
+        0,
+        group.as_ref(),
+        &PixmapPaint::default(),
+        Transform::identity(),
+        None,
+    );
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{configs::Config, render::raster::svg_string_to_pixmap};
+    use resvg::usvg_text_layout::fontdb;
+
+    /// A PNG with a gradient of colors and of transparency.
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = ::image::RgbaImage::from_fn(width, height, |x, y| {
+            ::image::Rgba([
+                (x * 255 / width) as u8,
+                (y * 255 / height) as u8,
+                ((x + y) % 256) as u8,
+                match x < width / 4 {
+                    true => (y * 255 / height) as u8,
+                    false => 255,
+                },
+            ])
+        });
+        let mut bytes = std::io::Cursor::new(Vec::new());
+        image
+            .write_to(&mut bytes, ::image::ImageOutputFormat::Png)
+            .unwrap();
+        bytes.into_inner()
+    }
+
+    /// Build the canvas of a config, whose `{image}` is a PNG of the table.
+    fn build(config: &str) -> (Canvas, Resources) {
+        let mut resources = Resources::default();
+        let handle = resources.insert("image/png", png(160, 100)).unwrap();
+        let config: Config = serde_yaml::from_str(&config.replace("{image}", &handle)).unwrap();
+        (config.build_canvas(&resources).unwrap(), resources)
+    }
+
+    /// The largest and the mean difference of the channels of the direct
+    /// render and of the SVG render.
+    fn compare(config: &str) -> (u8, f64) {
+        let (canvas, resources) = build(config);
+        let limits = Limits::default();
+
+        let direct = render(&canvas, &resources, &limits).unwrap().unwrap();
+        let svg = svg_string_to_pixmap(
+            &canvas.to_svg_string().unwrap(),
+            &fontdb::Database::new(),
+            &resources,
+            &limits,
+        )
+        .unwrap();
+        assert_eq!(direct.width(), svg.width());
+        assert_eq!(direct.height(), svg.height());
+
+        let differences: Vec<_> = direct
+            .data()
+            .iter()
+            .zip(svg.data())
+            .map(|(a, b)| a.abs_diff(*b))
+            .collect();
+        let max = differences.iter().copied().max().unwrap();
+        let mean =
+            differences.iter().map(|&d| f64::from(d)).sum::<f64>() / differences.len() as f64;
+        (max, mean)
+    }
+
+    #[test]
+    fn same_as_svg() {
+        let configs = [
+            "styles:\n  img: { image: '{image}' }\n  bg: { color: !Pure '#1e90ff' }\n",
+            "styles:\n  img: { image: '{image}', round: 20 }\n  bg: { color: !Preset sunset }\n",
+            "styles:\n  img: { image: '{image}', round: 12, shadow: { x: 5, y: 5, blur: 7 } }\n  bg: { color: !Preset peach }\n",
+            "styles:\n  img: { image: '{image}', shadow: { x: 30, y: 0, blur: 1, opacity: 0.8 } }\n  bg: { color: !Pure 'rgba(0, 0, 0, 0.5)' }\n",
+        ];
+        for config in configs {
+            let (max, mean) = compare(config);
+            assert!(max <= 2 && mean < 0.05, "{config}: max {max}, mean {mean}");
+        }
+    }
+
+    #[test]
+    fn fall_back_to_svg() {
+        let (canvas, resources) =
+            build("styles:\n  img: { image: '{image}' }\n  bg: { color: !Pure none }\n");
+        assert!(render(&canvas, &resources, &Limits::default())
+            .unwrap()
+            .is_none());
+
+        let (canvas, _) =
+            build("styles:\n  img: { image: '{image}' }\n  bg: { color: !Pure red }\n");
+        assert!(render(&canvas, &Resources::default(), &Limits::default())
+            .unwrap()
+            .is_none());
+    }
+}
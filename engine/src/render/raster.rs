@@ -0,0 +1,116 @@
+//! The raster backend, which renders the canvas with resvg.
+//!
+//! The layers are drawn directly with tiny-skia when they can be, and the
+//! SVG of the canvas is rendered otherwise.
+
+use anyhow::{anyhow, Context, Result};
+use resvg::{usvg, usvg_text_layout::TreeTextToPath};
+use tiny_skia::Pixmap;
+
+pub use resvg::{tiny_skia, usvg_text_layout::fontdb};
+
+use super::{direct, Renderer};
+use crate::{limits::Limits, resources::Resources, Canvas};
+
+/// The font database of the system fonts.
+///
+/// Loading it is slow, load it once for many renders.
+pub fn system_fonts() -> fontdb::Database {
+    let mut fontdb = fontdb::Database::new();
+    fontdb.load_system_fonts();
+    fontdb
+}
+
+/// The raster backend, see [`Canvas::render`].
+///
+/// The output is a premultiplied RGBA buffer, see [`Pixmap::data`], which
+/// [`Pixmap::encode_png`] writes as PNG.
+#[derive(Debug, Clone, Copy)]
+pub struct Raster;
+
+/// The options of [`Raster`].
+#[derive(Debug, Clone, Copy)]
+pub struct RasterOptions<'a> {
+    /// The images that the canvas refers to by their handles.
+    pub resources: &'a Resources,
+    /// The fonts of the text.
+    pub fontdb: &'a fontdb::Database,
+    /// The limits of the raster, checked before it is allocated.
+    pub limits: Limits,
+}
+
+impl Renderer for Raster {
+    type Options<'a> = RasterOptions<'a>;
+    type Output = Pixmap;
+
+    fn render(canvas: &Canvas, options: RasterOptions<'_>) -> Result<Pixmap> {
+        if let Some(pixmap) = direct::render(canvas, options.resources, &options.limits)? {
+            return Ok(pixmap);
+        }
+
+        svg_string_to_pixmap(
+            &canvas.to_svg_string()?,
+            options.fontdb,
+            options.resources,
+            &options.limits,
+        )
+    }
+}
+
+/// The options of the SVG parser.
+///
+/// The SVG refers to the images by their handles in `resources`, so the
+/// parser reads no file. Other references are refused, except data URLs,
+/// and so are nested SVGs, which could reference files themselves.
+fn options(resources: Resources) -> usvg::Options {
+    let mut opt = usvg::Options::default();
+    let resolve_data = usvg::ImageHrefResolver::default_data_resolver();
+    opt.image_href_resolver = usvg::ImageHrefResolver {
+        resolve_data: Box::new(move |mime, data, opt| match mime {
+            "image/svg+xml" => None,
+            _ => resolve_data(mime, data, opt)
+                .filter(|kind| !matches!(kind, usvg::ImageKind::SVG(_))),
+        }),
+        resolve_string: Box::new(move |href, _| {
+            let resource = resources.get(href)?;
+            let bytes = resource.bytes.clone();
+            match resource.mime_type.as_str() {
+                "image/png" => Some(usvg::ImageKind::PNG(bytes)),
+                "image/jpeg" => Some(usvg::ImageKind::JPEG(bytes)),
+                "image/gif" => Some(usvg::ImageKind::GIF(bytes)),
+                _ => None,
+            }
+        }),
+    };
+    opt
+}
+
+/// Render an SVG string.
+///
+/// The images of the SVG come from `resources`. The memory of the pixmap is
+/// checked against the limits before it is allocated.
+pub(super) fn svg_string_to_pixmap(
+    svg_string: &str,
+    fontdb: &fontdb::Database,
+    resources: &Resources,
+    limits: &Limits,
+) -> Result<Pixmap> {
+    let opt = options(resources.clone());
+    let mut tree = usvg::Tree::from_data(svg_string.as_bytes(), &opt)
+        .context("Failed to parse the rendered SVG.")?;
+
+    tree.convert_text(fontdb, opt.keep_named_groups);
+
+    let pixmap_size = tree.size.to_screen_size();
+    limits.check_raster(pixmap_size.width(), pixmap_size.height())?;
+    let mut pixmap = tiny_skia::Pixmap::new(pixmap_size.width(), pixmap_size.height())
+        .ok_or_else(|| anyhow!("The canvas is empty."))?;
+    resvg::render(
+        &tree,
+        usvg::FitTo::Original,
+        tiny_skia::Transform::default(),
+        pixmap.as_mut(),
+    )
+    .ok_or_else(|| anyhow!("Failed to render the SVG."))?;
+    Ok(pixmap)
+}
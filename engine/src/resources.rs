@@ -0,0 +1,142 @@
+//! A table of the images of a render, referenced by handles.
+//!
+//! A loaded image is added to [`Resources`], and the styles refer to it by
+//! its handle, a short `resource:<n>` source, instead of a data URL of the
+//! whole image. The renderer gets the bytes straight from the table, and
+//! data URLs are only made for SVG output, with [`Resources::inline`].
+
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+
+use crate::{
+    configs::{
+        structure::{ImageSizeError, ImageSizeProvider},
+        style::StyleCollection,
+    },
+    image_size::{probe_bytes, HeaderSizeProvider},
+};
+
+/// The prefix of the handles.
+const HANDLE_PREFIX: &str = "resource:";
+
+/// An image of the table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    /// The MIME type of `bytes`.
+    pub mime_type: String,
+    /// The encoded image, shared with the renderer.
+    pub bytes: Arc<Vec<u8>>,
+    /// The width and height of the image, read from its header.
+    pub size: (u32, u32),
+}
+
+impl Resource {
+    /// Encode the image into a data URL.
+    pub fn to_data_url(&self) -> String {
+        format!(
+            "data:{};base64,{}",
+            self.mime_type,
+            BASE64.encode(self.bytes.as_slice())
+        )
+    }
+}
+
+/// The images of a render.
+///
+/// Cloning the table shares the bytes of the images.
+#[derive(Debug, Clone, Default)]
+pub struct Resources {
+    images: Vec<Resource>,
+}
+
+impl Resources {
+    /// Add an encoded image, and return its handle.
+    ///
+    /// Only the header of the image is read, for its size.
+    pub fn insert(
+        &mut self,
+        mime_type: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Result<String, ImageSizeError> {
+        let size = probe_bytes(&bytes)?;
+        self.images.push(Resource {
+            mime_type: mime_type.into(),
+            bytes: Arc::new(bytes),
+            size,
+        });
+
+        Ok(format!("{}{}", HANDLE_PREFIX, self.images.len() - 1))
+    }
+
+    /// Whether `src` is a handle, of this table or of another one.
+    pub fn is_handle(src: &str) -> bool {
+        src.starts_with(HANDLE_PREFIX)
+    }
+
+    /// The image of a handle.
+    pub fn get(&self, handle: &str) -> Option<&Resource> {
+        let index = handle.strip_prefix(HANDLE_PREFIX)?.parse::<usize>().ok()?;
+        self.images.get(index)
+    }
+
+    /// Replace the handles of the styles by data URLs, for SVG output.
+    pub fn inline(&self, styles: &mut StyleCollection) {
+        for (_, src) in styles.image_sources_mut() {
+            if let Some(resource) = self.get(src) {
+                *src = resource.to_data_url();
+            }
+        }
+    }
+}
+
+/// The sizes of handles come from the table, other sources are probed
+/// with [`HeaderSizeProvider`].
+impl ImageSizeProvider for &Resources {
+    fn get_image_size(&self, src: &str) -> Result<(u32, u32), ImageSizeError> {
+        match Resources::is_handle(src) {
+            true => self
+                .get(src)
+                .map(|resource| resource.size)
+                .ok_or_else(|| ImageSizeError::not_found(src)),
+            false => HeaderSizeProvider.get_image_size(src),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configs::style::Style;
+
+    /// A 1x1 PNG.
+    const PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mP8z8DwHwAFBQIAX8jx0gAAAABJRU5ErkJggg==";
+
+    #[test]
+    fn handles() {
+        let bytes = BASE64.decode(PNG).unwrap();
+        let mut resources = Resources::default();
+        let handle = resources.insert("image/png", bytes.clone()).unwrap();
+
+        assert!(Resources::is_handle(&handle));
+        assert_eq!(resources.get(&handle).unwrap().bytes.as_slice(), bytes);
+        assert_eq!((&resources).get_image_size(&handle), Ok((1, 1)));
+        assert_eq!(
+            (&resources).get_image_size("resource:7"),
+            Err(ImageSizeError::not_found("resource:7"))
+        );
+        assert!(resources
+            .insert("image/png", b"not an image".to_vec())
+            .is_err());
+
+        let mut styles = StyleCollection::default();
+        styles.add("img".to_owned(), Style::default().with_image(&handle));
+        resources.inline(&mut styles);
+        let data_url = format!("data:image/png;base64,{}", PNG);
+        assert_eq!(
+            styles.image_sources().collect::<Vec<_>>(),
+            [("img", data_url.as_str())]
+        );
+        assert_eq!((&resources).get_image_size(&data_url), Ok((1, 1)));
+    }
+}
@@ -0,0 +1,92 @@
+//! JSON Schema of the config format.
+//!
+//! The schema is derived from the serde types of [`crate::configs`], so it
+//! always matches what the engine accepts.
+//!
+//! serde_yaml writes enum variants as YAML tags (`!Linear { .. }`), which
+//! YAML language servers strip before validation. So each externally tagged
+//! variant is also accepted in its untagged form.
+
+use serde_json::{Map, Value};
+
+use crate::configs::Config;
+
+/// Generate the JSON Schema of [`Config`].
+pub fn config_schema() -> Value {
+    let schema = schemars::schema_for!(Config);
+    let mut schema = serde_json::to_value(schema).expect("a schema is always serializable");
+
+    if let Some(Value::Object(definitions)) = schema.get_mut("definitions") {
+        definitions.values_mut().for_each(accept_tagged_variants);
+    }
+
+    schema
+}
+
+/// Accept the payload of every `{ Variant: payload }` alternative on its own.
+fn accept_tagged_variants(definition: &mut Value) {
+    let Some(definition) = definition.as_object_mut() else {
+        return;
+    };
+    let Some(Value::Array(variants)) = definition.remove("oneOf") else {
+        return;
+    };
+
+    let payloads: Vec<Value> = variants.iter().filter_map(tagged_payload).collect();
+    let key = if payloads.is_empty() {
+        "oneOf"
+    } else {
+        "anyOf"
+    };
+    definition.insert(key.to_owned(), Value::Array([variants, payloads].concat()));
+}
+
+/// The payload schema of an externally tagged variant, described by its tag.
+fn tagged_payload(variant: &Value) -> Option<Value> {
+    let properties = variant.get("properties")?.as_object()?;
+    let (tag, payload) = match properties.iter().next() {
+        Some(entry) if properties.len() == 1 => entry,
+        _ => return None,
+    };
+
+    let mut described = Map::new();
+    described.insert(
+        "description".to_owned(),
+        Value::String(format!("The payload of `!{}`.", tag)),
+    );
+    described.insert("allOf".to_owned(), Value::Array(vec![payload.clone()]));
+    Some(Value::Object(described))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The checked-in schema, used by editors.
+    const SCHEMA: &str = include_str!("../config.schema.json");
+
+    #[test]
+    fn schema_is_up_to_date() {
+        let schema = serde_json::to_string_pretty(&config_schema()).unwrap() + "\n";
+
+        assert!(
+            schema == SCHEMA,
+            "engine/config.schema.json is out of date, regenerate it with `fl schema > engine/config.schema.json`"
+        );
+    }
+
+    #[test]
+    fn tagged_variants() {
+        let schema = config_schema();
+        let background = &schema["definitions"]["BackgroundType"]["anyOf"];
+
+        // Pure, Linear, Radial, Preset, then their payloads.
+        assert_eq!(background.as_array().unwrap().len(), 8);
+        assert_eq!(
+            background[5]["allOf"][0]["$ref"],
+            "#/definitions/LinearGradient"
+        );
+        // Unit variants are kept as is.
+        assert!(schema["definitions"]["LayerType"].get("oneOf").is_some());
+    }
+}
@@ -1,21 +1,27 @@
+//! Basic geometric shapes.
+
 use elementtree::Element;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     configs::style::{PositionOption, SizeOption},
     foundation::{Position, PositionOptionT, Size, SizeOptionT},
+    layout::Primitive,
     svg::SvgTangibleObject,
 };
 
+/// A basic shape, filled with a color.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BasicShape {
     shape_type: BasicShapeType,
     position: PositionOption,
     size: SizeOption,
+    /// The fill color of the shape.
     pub fill: Option<String>,
 }
 
 impl BasicShape {
+    /// Create a 100x100 centered shape without fill.
     pub fn new(shape_type: BasicShapeType) -> Self {
         Self {
             shape_type,
@@ -38,13 +44,15 @@ impl PositionOptionT for BasicShape {
     }
 }
 
+/// The type of a [`BasicShape`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BasicShapeType {
+    /// A rectangle.
     Rectangle,
 }
 
 impl SvgTangibleObject for BasicShape {
-    fn to_svg(&self, size: Size, position: Position, id: String) -> (Element, Option<Element>) {
+    fn to_svg(&self, size: Size, position: Position, _id: String) -> (Element, Option<Element>) {
         let mut element = Element::new("rect");
         element.set_attr("width", size.0.to_string());
         element.set_attr("height", size.1.to_string());
@@ -59,4 +67,9 @@ impl SvgTangibleObject for BasicShape {
         //     .map(|stroke| element.set_attr("stroke", stroke));
         (element, None)
     }
+
+    fn primitive(&self) -> Option<Primitive<'_>> {
+        // SVG fills shapes in black by default.
+        Some(Primitive::Color(self.fill.as_deref().unwrap_or("black")))
+    }
 }
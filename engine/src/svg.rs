@@ -1,11 +1,16 @@
+//! SVG generation: the [`Canvas`] and the traits of the objects drawn on it.
+
 use anyhow::Result;
 use elementtree::Element;
 
 use crate::configs::style::{PositionOption, SizeOption};
 
 use super::foundation::{Position, PositionOptionT, Size, SizeOptionT};
+use crate::layout::Primitive;
 
+/// A SvgObject is a standalone element that can be expressed in svg.
 pub trait SvgObject {
+    /// Generate the svg element of the object.
     fn to_svg(&self) -> Element;
 }
 
@@ -30,8 +35,13 @@ pub trait SvgTangibleObject: SizeOptionT + PositionOptionT + std::fmt::Debug {
         let size_option = self.get_size_option();
         match size_option {
             SizeOption::FitContent(padding) => {
-                let width = child_size.0 + padding * 2;
-                let height = child_size.1 + padding * 2;
+                let width = child_size.0.saturating_add(padding.saturating_mul(2));
+                let height = child_size.1.saturating_add(padding.saturating_mul(2));
+                Size(width, height)
+            }
+            SizeOption::FitContentXY(x, y) => {
+                let width = child_size.0.saturating_add(x.saturating_mul(2));
+                let height = child_size.1.saturating_add(y.saturating_mul(2));
                 Size(width, height)
             }
             SizeOption::Absolute(width, height) => Size(width, height),
@@ -42,16 +52,25 @@ pub trait SvgTangibleObject: SizeOptionT + PositionOptionT + std::fmt::Debug {
     ///
     /// * `size`: The size of the object.
     /// * `position`: The absolute position of the object.
-    /// * `Id`: The id of the object.
-    fn to_svg(&self, size: Size, position: Position, Id: String) -> (Element, Option<Element>);
+    /// * `id`: The id of the object.
+    fn to_svg(&self, size: Size, position: Position, id: String) -> (Element, Option<Element>);
+
+    /// What the object draws, for the backends that draw the layout
+    /// directly, see [`crate::layout`].
+    ///
+    /// `None` if the object can only be drawn as SVG.
+    fn primitive(&self) -> Option<Primitive<'_>> {
+        None
+    }
 }
 
 /// A canvas is a container for a series of layers.
+#[derive(Debug)]
 pub struct Canvas {
     /// A series of layers that are rendered in order.
     ///
     /// The first layer is rendered first, and the last layer is rendered last.
-    layers: Vec<Box<dyn SvgTangibleObject>>,
+    pub(crate) layers: Vec<Box<dyn SvgTangibleObject>>,
 }
 
 impl Default for Canvas {
@@ -61,10 +80,12 @@ impl Default for Canvas {
 }
 
 impl Canvas {
+    /// Create an empty canvas.
     pub fn new() -> Self {
         Self { layers: Vec::new() }
     }
 
+    /// Add a layer on top of the existing layers.
     pub fn add_layer_on_top(&mut self, layer: Box<dyn SvgTangibleObject>) {
         self.layers.push(layer);
     }
@@ -73,18 +94,28 @@ impl Canvas {
     //     self.layers.insert(0, layer);
     // }
 
+    /// The size of the canvas, which is the size of its bottom layer.
+    ///
+    /// It is known before the canvas is rendered, to check it against
+    /// [`crate::limits::Limits`].
+    pub fn size(&self) -> Size {
+        self.layers
+            .iter()
+            .rev()
+            .fold(Size::default(), |child_size, layer| {
+                layer.cal_size(child_size)
+            })
+    }
+
+    /// Build the root `svg` element of the given size.
     pub fn build_svg_canvas(&self, size: Size) -> Element {
         let mut root = elementtree::Element::new(("http://www.w3.org/2000/svg", "svg"));
-        root.set_attr("width", &size.0.to_string());
-        root.set_attr("height", &size.1.to_string());
+        root.set_attr("width", size.0.to_string());
+        root.set_attr("height", size.1.to_string());
         root
     }
 
-    /// Calculate absolute size
-    fn calculate_size(&mut self) {}
-
-    fn calculate_position(&mut self) {}
-
+    /// Render the canvas into a svg string.
     pub fn to_svg_string(&self) -> Result<String> {
         let string = self.to_svg().to_string()?;
 
@@ -94,34 +125,21 @@ impl Canvas {
 
 impl SvgObject for Canvas {
     fn to_svg(&self) -> Element {
-        let mut child_size = Size::default();
-        let mut parent_size = Size::default();
-        let mut parent_position = Position(0, 0);
-        // Calculate the size from the top to the bottom.
-        let (childs, defs_childs): (Vec<_>, Vec<_>) = self
-            .layers
+        let layout = self.layout();
+        let (childs, defs_childs): (Vec<_>, Vec<_>) = layout
             .iter()
             .enumerate()
-            .rev()
-            .map(|(i, o)| {
-                let size = o.cal_size(child_size);
-                child_size = size;
-                (i, o, size)
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .rev()
-            // Calculate the position from the bottom to the top.
-            .map(|(i, o, s)| {
-                let position = o.cal_position(parent_size, s);
-                parent_size = s;
-                parent_position = position;
-                (i, o, s, position)
+            .map(|(i, placement)| {
+                placement
+                    .layer
+                    .to_svg(placement.size, placement.position, i.to_string())
             })
-            .map(|(i, o, s, p)| o.to_svg(s, p, i.to_string()))
             .unzip();
 
-        let mut root = self.build_svg_canvas(child_size);
+        let size = layout
+            .first()
+            .map_or_else(Size::default, |bottom| bottom.size);
+        let mut root = self.build_svg_canvas(size);
         let mut defs = Element::new("defs");
 
         childs.into_iter().for_each(|child| {
@@ -1,12 +1,9 @@
 use anyhow::Result;
 use elementtree::Element;
 
-use super::background::Background;
-use super::foundation::Color;
-use super::image::Image;
-use super::svg::Canvas;
-use pretty_assertions::{assert_eq, assert_ne};
+use pretty_assertions::assert_eq;
 
+#[allow(dead_code)]
 pub fn compare_svg_text(left: &str, right: &str) -> Result<()> {
     let left_root = elementtree::Element::from_reader(left.as_bytes()).unwrap();
 
@@ -43,3 +40,15 @@ pub fn delete_all_whitespaces(tree: &mut Element) {
         delete_all_whitespaces(child);
     }
 }
+
+/// An image size provider returning the same size for every image.
+pub struct FixedSizeProvider(pub u32, pub u32);
+
+impl crate::configs::structure::ImageSizeProvider for FixedSizeProvider {
+    fn get_image_size(
+        &self,
+        _src: &str,
+    ) -> Result<(u32, u32), crate::configs::structure::ImageSizeError> {
+        Ok((self.0, self.1))
+    }
+}
@@ -88,6 +88,7 @@ function App() {
       console.log(bg)
       engine.add_style("bg", bg);
       engine.add_style("img", img);
+      engine.set_image_size(logo, 841, 595);
 
 
       let str = engine.render();
@@ -1,9 +1,18 @@
 mod utils;
 
-use footlights_engine::configs::{
-    structure::{ImageSizeProvider, Structure},
-    style::{Style, StyleCollection},
+use std::collections::HashMap;
+
+use footlights_engine::{
+    configs::{
+        structure::{ImageSizeError, ImageSizeProvider, Structure},
+        style::{Style, StyleCollection},
+        ConfigError,
+    },
+    image_size::HeaderSizeProvider,
+    presets,
+    render::Svg,
 };
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
@@ -29,15 +38,25 @@ pub fn greet() {
 pub struct Engine {
     structure: Structure,
     styles: StyleCollection,
+    image_sizes: HashMap<String, (u32, u32)>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
 impl Engine {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
+        utils::set_panic_hook();
+
         Self {
             structure: Structure::default(),
             styles: StyleCollection::default(),
+            image_sizes: HashMap::new(),
         }
     }
 
@@ -49,25 +68,90 @@ impl Engine {
         Ok(())
     }
 
+    /// Merge the styles of a built-in preset under the styles added so far.
+    ///
+    /// Gradients set the `bg` style, shadows and frames the `img` style.
+    #[wasm_bindgen]
+    pub fn use_preset(&mut self, name: &str) -> Result<(), JsValue> {
+        let preset = presets::get(name)
+            .ok_or_else(|| JsValue::from_str(&format!("Preset \"{}\" does not exist.", name)))?;
+
+        let mut styles = preset.config().styles.clone();
+        styles.merge(std::mem::take(&mut self.styles));
+        self.styles = styles;
+        Ok(())
+    }
+
+    /// Register the size of the image at `src`.
+    ///
+    /// The engine reads the size of data URL images by itself, but cannot
+    /// load other images in the browser, so their size must be set before
+    /// rendering.
+    #[wasm_bindgen]
+    pub fn set_image_size(&mut self, src: String, width: u32, height: u32) {
+        self.image_sizes.insert(src, (width, height));
+    }
+
+    /// Render the styles into a svg string.
+    ///
+    /// On a config error, throws a `{ message, layer, style }` object.
     pub fn render(&self) -> Result<String, JsValue> {
-        let image_size_provider = ImageSizeProviderImpl;
+        let image_size_provider = ImageSizeProviderImpl {
+            sizes: &self.image_sizes,
+        };
         let canvas = self
             .structure
             .build_canvas(&self.styles, image_size_provider)
-            .map_err(|e| e.to_string())?;
+            .map_err(RenderError::from)?;
+
+        Ok(canvas.render::<Svg>(()).map_err(|e| e.to_string())?)
+    }
+}
+
+/// A config error, as thrown by [`Engine::render`].
+#[derive(Serialize)]
+struct RenderError {
+    message: String,
+    layer: Option<String>,
+    style: Option<String>,
+}
+
+impl From<ConfigError> for RenderError {
+    fn from(e: ConfigError) -> Self {
+        Self {
+            message: e.to_string(),
+            layer: e.path().map(|path| path.layer.clone()),
+            style: e.path().map(|path| path.style.clone()),
+        }
+    }
+}
 
-        Ok(canvas.to_svg_string().map_err(|e| e.to_string())?)
+impl From<RenderError> for JsValue {
+    fn from(e: RenderError) -> Self {
+        serde_wasm_bindgen::to_value(&e).unwrap_or_else(|_| JsValue::from_str(&e.message))
     }
 }
 
-pub struct ImageSizeProviderImpl;
+pub struct ImageSizeProviderImpl<'a> {
+    sizes: &'a HashMap<String, (u32, u32)>,
+}
 
-impl ImageSizeProvider for ImageSizeProviderImpl {
-    fn get_image_size(&self, src: &str) -> (u32, u32) {
-        (100, 100)
+impl ImageSizeProvider for ImageSizeProviderImpl<'_> {
+    fn get_image_size(&self, src: &str) -> Result<(u32, u32), ImageSizeError> {
+        match self.sizes.get(src) {
+            Some(size) => Ok(*size),
+            None => HeaderSizeProvider.get_image_size(src),
+        }
     }
 }
 
+/// The built-in presets, as `{ name, kind, description, config }` objects.
+#[wasm_bindgen]
+pub fn presets() -> JsValue {
+    let presets: Vec<_> = presets::iter().collect();
+    serde_wasm_bindgen::to_value(&presets).unwrap()
+}
+
 #[wasm_bindgen]
 pub fn new_structure() -> JsValue {
     serde_wasm_bindgen::to_value(&footlights_engine::configs::structure::Structure::default())